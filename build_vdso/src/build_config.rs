@@ -33,6 +33,32 @@ pub struct BuildConfig {
     ///
     /// log等级不在此处指定，而由主编译单元控制。
     pub log: bool,
+    /// 构建标识，参与vDSO的ABI指纹计算，默认为空字符串。
+    ///
+    /// API库初始化时会检查已映射的vDSO与自身的指纹是否一致。
+    /// 若希望每次发布的vDSO只能与同一次构建生成的API库配合使用，可以在此填入版本号或提交哈希等信息。
+    pub build_id: String,
+    /// 是否在运行时解析vDSO中的函数地址，默认为`false`。
+    ///
+    /// 为`false`时，API库在构建时从so文件中读取各个函数的偏移并写入代码，因此只能与构建时的so文件配合使用；
+    /// 为`true`时，API库在`init_vdso_vtable`中遍历已映射的vDSO镜像的动态符号表，按名称解析各个函数，
+    /// 只要ABI指纹一致，就可以与重新构建的so文件配合使用。
+    ///
+    /// 为`true`时，API库中还会生成`replace_so`，用于在运行时替换内核中已加载的vDSO。
+    pub resolve_at_runtime: bool,
+    /// vDSO的ABI版本，形如"1.1"，默认为"1.0"。
+    ///
+    /// 每个ABI版本对应版本脚本中的一个版本节点（如`VDSO_1.1`）。
+    /// API可以通过`#[vdso_api(since = "...")]`指定其所属的版本，未指定版本的API属于`VDSO_1.0`。
+    /// 生成的API库绑定到该版本，在初始化时与已映射的vDSO协商双方都支持的最新版本。
+    pub abi_version: String,
+    /// vDSO私有堆的大小（字节），默认为0，表示不启用私有堆。
+    ///
+    /// 不为0时，生成的wrapper库会在vDSO的私有数据中保留一块该大小的内存，并将其注册为`#[global_allocator]`，
+    /// 从而vDSO库中可以使用`alloc` crate中的`Vec`、`Box`等类型。与其它私有数据相同，每个地址空间持有各自的堆。
+    ///
+    /// 该值作为`vdso_helper::mut_cfg!`常量`VDSO_HEAP_SIZE`的默认值，构建时也可以通过同名的环境变量修改。
+    pub heap_size: usize,
 }
 
 impl BuildConfig {
@@ -49,6 +75,10 @@ impl BuildConfig {
     /// - verbose: 0
     /// - api_lib_name: "lib" + package_name
     /// - toolchain: "nightly"
+    /// - build_id: ""
+    /// - resolve_at_runtime: false
+    /// - abi_version: "1.0"
+    /// - heap_size: 0
     ///
     /// 其他字段必须手动指定。
     ///
@@ -69,6 +99,10 @@ impl BuildConfig {
             page_size: 0x1000,
             features: Vec::new(),
             log: false,
+            build_id: String::new(),
+            resolve_at_runtime: false,
+            abi_version: "1.0".to_string(),
+            heap_size: 0,
         }
     }
 }
@@ -0,0 +1,464 @@
+//! vDSO的ABI指纹。
+//!
+//! 每个ABI版本（见[`crate::version`]）都有一个指纹，它是以下内容的FNV-1a哈希：
+//!
+//! - 该版本中可见的所有导出函数的符号名、所属模块和函数指针类型（按符号名排序，不包含文档注释和参数名）。
+//!   同一符号存在多个版本时，取不超过该版本的最新定义。
+//!   函数指针类型中的路径只保留最后一段（如`core::ffi::c_int`与`c_int`相同），空白也按词法单元统一，
+//!   因此同一签名的不同写法得到相同的指纹；
+//! - 所有`trait_interface!`依赖接口的trait名和函数表；
+//! - `VvarData`和`VvarKernelData`的大小和对齐；
+//! - 页大小；
+//! - [`BuildConfig::build_id`]。
+//!
+//! 由于`VvarData`和`VvarKernelData`的布局只有在编译vDSO库时才能确定，指纹由wrapper在编译期计算，
+//! 并以ELF note的形式嵌入so文件（节名`.note.vdso`，位于`PT_NOTE`段中）。
+//! note的内容为若干个16字节的表项，依次为小端序的`u32`主版本号、`u32`次版本号和`u64`指纹，按版本从旧到新排列。
+//!
+//! 生成API库时从so文件中读出指纹表，写入API库；API库在初始化VTABLE前，
+//! 会从已映射的vDSO镜像中读出指纹表，找到双方都支持的最新版本并比较指纹，不一致时拒绝初始化。
+
+use quote::ToTokens;
+use xmas_elf::ElfFile;
+
+use crate::{
+    scan::VdsoItems,
+    version::{abi_version, AbiVersion},
+    BuildConfig,
+};
+
+/// 指纹note所在的节名
+const FINGERPRINT_SECTION: &str = ".note.vdso";
+/// 指纹note的名称（含结尾的`\0`）
+const NOTE_NAME: &[u8; 5] = b"VDSO\0";
+/// 指纹note的类型
+const NOTE_TYPE_FINGERPRINT: u32 = 1;
+/// 指纹表中每一项的长度
+const ENTRY_SIZE: usize = 16;
+
+/// 版本`version`中参与指纹计算的签名文本，每行为一个导出函数或依赖接口。
+fn signature_desc(items: &VdsoItems, version: AbiVersion) -> String {
+    let visible = items
+        .fns
+        .iter()
+        .chain(items.compat_fns.iter())
+        .filter(|f| f.version <= version);
+    let mut lines: Vec<(String, AbiVersion, String)> = Vec::new();
+    for f in visible {
+        // 主模块中的函数不标注模块，以保持不使用模块时的指纹不变
+        let ty = match &f.module {
+            Some(module) => format!("[{}] {}", module, normalize_type(&f.fn_ptr_type())),
+            None => normalize_type(&f.fn_ptr_type()),
+        };
+        match lines.iter_mut().find(|(name, _, _)| *name == f.name) {
+            Some(line) if line.1 >= f.version => {}
+            Some(line) => *line = (f.name.clone(), f.version, ty),
+            None => lines.push((f.name.clone(), f.version, ty)),
+        }
+    }
+    let mut lines: Vec<String> = lines
+        .into_iter()
+        .map(|(name, _, ty)| format!("fn {}: {}", name, ty))
+        .chain(
+            items
+                .traits
+                .iter()
+                .map(|t| format!("trait {}: {}", t.name, t.fns.join(", "))),
+        )
+        .collect();
+    lines.sort();
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+/// 将类型中的路径缩短为最后一段，并按词法单元重新输出，使同一类型的不同写法得到相同的文本。
+fn normalize_type(ty: &str) -> String {
+    struct LastSegment;
+    impl syn::visit_mut::VisitMut for LastSegment {
+        fn visit_type_path_mut(&mut self, ty: &mut syn::TypePath) {
+            if ty.qself.is_none() && ty.path.segments.len() > 1 {
+                let last = ty.path.segments.pop().unwrap().into_value();
+                ty.path = last.into();
+            }
+            syn::visit_mut::visit_type_path_mut(self, ty);
+        }
+    }
+    let mut ty: syn::Type = syn::parse_str(ty).unwrap();
+    syn::visit_mut::VisitMut::visit_type_mut(&mut LastSegment, &mut ty);
+    ty.to_token_stream().to_string()
+}
+
+/// 生成wrapper中计算指纹并嵌入so文件的代码。
+pub(crate) fn wrapper_fingerprint_content(config: &BuildConfig, items: &VdsoItems) -> String {
+    let versions = items.versions(abi_version(config));
+    let descs: String = versions
+        .iter()
+        .map(|version| {
+            format!(
+                "    ({}, {}, {:?}),\n",
+                version.major,
+                version.minor,
+                signature_desc(items, *version)
+            )
+        })
+        .collect();
+    format!(
+        r#"
+/// 各个ABI版本中参与指纹计算的签名文本，按版本从旧到新排列。
+const VDSO_SIGNATURE_DESCS: [(u32, u32, &str); {}] = [
+{}];
+/// 构建标识，见`BuildConfig::build_id`。
+const VDSO_BUILD_ID: &str = {:?};
+
+const fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {{
+    let mut i = 0;
+    while i < bytes.len() {{
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+        i += 1;
+    }}
+    hash
+}}
+
+/// 计算一个ABI版本的指纹。
+const fn vdso_fingerprint(desc: &str) -> u64 {{
+    let hash = fnv1a(0xcbf29ce484222325, desc.as_bytes());
+    let hash = fnv1a(hash, &(core::mem::size_of::<VvarData>() as u64).to_le_bytes());
+    let hash = fnv1a(hash, &(core::mem::align_of::<VvarData>() as u64).to_le_bytes());
+    let hash = fnv1a(hash, &(core::mem::size_of::<VvarKernelData>() as u64).to_le_bytes());
+    let hash = fnv1a(hash, &(core::mem::align_of::<VvarKernelData>() as u64).to_le_bytes());
+    let hash = fnv1a(hash, &({}u64).to_le_bytes());
+    fnv1a(hash, VDSO_BUILD_ID.as_bytes())
+}}
+
+const fn vdso_fingerprint_table() -> [u8; {}] {{
+    let mut table = [0u8; {}];
+    let mut i = 0;
+    while i < VDSO_SIGNATURE_DESCS.len() {{
+        let (major, minor, desc) = VDSO_SIGNATURE_DESCS[i];
+        let major = major.to_le_bytes();
+        let minor = minor.to_le_bytes();
+        let fingerprint = vdso_fingerprint(desc).to_le_bytes();
+        let mut j = 0;
+        while j < 4 {{
+            table[i * 16 + j] = major[j];
+            table[i * 16 + 4 + j] = minor[j];
+            j += 1;
+        }}
+        j = 0;
+        while j < 8 {{
+            table[i * 16 + 8 + j] = fingerprint[j];
+            j += 1;
+        }}
+        i += 1;
+    }}
+    table
+}}
+
+#[repr(C, align(4))]
+struct VdsoFingerprintNote {{
+    namesz: u32,
+    descsz: u32,
+    ty: u32,
+    name: [u8; 8],
+    desc: [u8; {}],
+}}
+
+/// 嵌入so文件的指纹note，供API库在构建时和运行时读取。
+#[used]
+#[link_section = "{}"]
+static VDSO_FINGERPRINT_NOTE: VdsoFingerprintNote = VdsoFingerprintNote {{
+    namesz: {},
+    descsz: {},
+    ty: {},
+    name: *b"VDSO\0\0\0\0",
+    desc: vdso_fingerprint_table(),
+}};
+"#,
+        versions.len(),
+        descs,
+        config.build_id,
+        config.page_size,
+        versions.len() * ENTRY_SIZE,
+        versions.len() * ENTRY_SIZE,
+        versions.len() * ENTRY_SIZE,
+        FINGERPRINT_SECTION,
+        NOTE_NAME.len(),
+        versions.len() * ENTRY_SIZE,
+        NOTE_TYPE_FINGERPRINT,
+    )
+}
+
+/// 从链接好的so文件中读取指纹表，按版本从旧到新排列。
+pub(crate) fn read_fingerprints(elf: &ElfFile) -> Vec<(AbiVersion, u64)> {
+    let section = elf
+        .find_section_by_name(FINGERPRINT_SECTION)
+        .unwrap_or_else(|| panic!("{} section not found in vDSO", FINGERPRINT_SECTION));
+    parse_fingerprint_note(section.raw_data(elf))
+}
+
+/// 解析指纹note的内容（含note头），得到指纹表。
+fn parse_fingerprint_note(data: &[u8]) -> Vec<(AbiVersion, u64)> {
+    let word = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+    assert!(
+        data.len() >= 20
+            && word(0) as usize == NOTE_NAME.len()
+            && word(8) == NOTE_TYPE_FINGERPRINT
+            && &data[12..17] == NOTE_NAME
+            && data.len() >= 20 + word(4) as usize,
+        "Invalid {} section",
+        FINGERPRINT_SECTION
+    );
+    (0..word(4) as usize / ENTRY_SIZE)
+        .map(|i| {
+            let entry = 20 + i * ENTRY_SIZE;
+            let version = AbiVersion {
+                major: word(entry),
+                minor: word(entry + 4),
+            };
+            let fingerprint = u64::from_le_bytes(data[entry + 8..entry + 16].try_into().unwrap());
+            (version, fingerprint)
+        })
+        .collect()
+}
+
+/// 生成的API库中`fingerprint.rs`的内容，用于在运行时从已映射的vDSO镜像中读取指纹表。
+pub(crate) const FINGERPRINT_RS: &str = r#"//! 从已映射的vDSO镜像中读取ABI指纹表。
+
+const PT_NOTE: u32 = 4;
+const NOTE_NAME: &[u8; 5] = b"VDSO\0";
+const NOTE_TYPE_FINGERPRINT: u32 = 1;
+const ENTRY_SIZE: usize = 16;
+
+unsafe fn read<T: Copy>(addr: usize) -> T {
+    unsafe { core::ptr::read_unaligned(addr as *const T) }
+}
+
+/// vDSO镜像中的ABI指纹表，按版本从旧到新排列。
+#[derive(Debug, Clone, Copy)]
+pub struct VdsoFingerprints {
+    addr: usize,
+    len: usize,
+}
+
+impl VdsoFingerprints {
+    /// 遍历vDSO镜像的`PT_NOTE`段，找到其中的指纹表。若镜像不是合法的ELF文件或不包含指纹，则返回`None`。
+    ///
+    /// # Safety
+    ///
+    /// `base`必须为已映射的vDSO镜像的首地址，且ELF头和各个`PT_NOTE`段均可读。
+    pub unsafe fn from_image(base: usize) -> Option<Self> {
+        let ident: [u8; 4] = unsafe { read(base) };
+        if ident != *b"\x7fELF" {
+            return None;
+        }
+        let phoff: u64 = unsafe { read(base + 32) };
+        let phentsize: u16 = unsafe { read(base + 54) };
+        let phnum: u16 = unsafe { read(base + 56) };
+        for i in 0..phnum as usize {
+            let phdr = base + phoff as usize + i * phentsize as usize;
+            let p_type: u32 = unsafe { read(phdr) };
+            if p_type != PT_NOTE {
+                continue;
+            }
+            let p_vaddr: u64 = unsafe { read(phdr + 16) };
+            let p_memsz: u64 = unsafe { read(phdr + 40) };
+            let start = base + p_vaddr as usize;
+            let end = start + p_memsz as usize;
+            let mut note = start;
+            while note + 12 <= end {
+                let namesz: u32 = unsafe { read(note) };
+                let descsz: u32 = unsafe { read(note + 4) };
+                let ty: u32 = unsafe { read(note + 8) };
+                let name = note + 12;
+                let desc = name + ((namesz as usize + 3) & !3);
+                if namesz as usize == NOTE_NAME.len() && ty == NOTE_TYPE_FINGERPRINT {
+                    let note_name: [u8; 5] = unsafe { read(name) };
+                    if note_name == *NOTE_NAME {
+                        return Some(Self {
+                            addr: desc,
+                            len: descsz as usize / ENTRY_SIZE,
+                        });
+                    }
+                }
+                note = desc + ((descsz as usize + 3) & !3);
+            }
+        }
+        None
+    }
+
+    /// 遍历指纹表中的所有版本及其指纹。
+    pub fn iter(&self) -> impl Iterator<Item = ((u32, u32), u64)> + '_ {
+        (0..self.len).map(|i| {
+            let entry = self.addr + i * ENTRY_SIZE;
+            let major = u32::from_le(unsafe { read(entry) });
+            let minor = u32::from_le(unsafe { read(entry + 4) });
+            let fingerprint = u64::from_le(unsafe { read(entry + 8) });
+            ((major, minor), fingerprint)
+        })
+    }
+
+    /// 获取ABI版本`version`的指纹，若vDSO不支持该版本则返回`None`。
+    pub fn get(&self, version: (u32, u32)) -> Option<u64> {
+        self.iter().find(|(v, _)| *v == version).map(|(_, fingerprint)| fingerprint)
+    }
+
+    /// vDSO支持的最新版本及其指纹。
+    pub fn latest(&self) -> Option<((u32, u32), u64)> {
+        self.iter().last()
+    }
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::{ApiFn, InterfaceTrait};
+
+    const V1_0: AbiVersion = AbiVersion::BASE;
+    const V1_1: AbiVersion = AbiVersion { major: 1, minor: 1 };
+
+    fn api(name: &str, args: &[&str], ret: Option<&str>, version: AbiVersion) -> ApiFn {
+        ApiFn {
+            name: name.to_string(),
+            docs: Vec::new(),
+            lifetimes: Vec::new(),
+            is_unsafe: false,
+            args: args
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| (format!("arg{}", i), ty.to_string()))
+                .collect(),
+            ret: ret.map(String::from),
+            version,
+            module: None,
+        }
+    }
+
+    fn items(fns: Vec<ApiFn>, compat_fns: Vec<ApiFn>) -> VdsoItems {
+        VdsoItems {
+            fns,
+            traits: vec![InterfaceTrait {
+                name: "WaitIf".to_string(),
+                fns: vec!["wait".to_string(), "wake".to_string()],
+            }],
+            compat_fns,
+            async_fns: Vec::new(),
+            generic_fns: Vec::new(),
+            methods: Vec::new(),
+        }
+    }
+
+    /// 与wrapper中的`vdso_fingerprint`相同的哈希，只计算签名文本部分
+    fn hash(items: &VdsoItems, version: AbiVersion) -> u64 {
+        signature_desc(items, version)
+            .bytes()
+            .fold(0xcbf29ce484222325, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+
+    #[test]
+    fn equivalent_signatures_hash_equally() {
+        let a = items(
+            vec![api(
+                "f",
+                &["core::ffi::c_int", "* const crate::Pair"],
+                Some("Option<&'static core::ptr::NonNull<u8>>"),
+                V1_0,
+            )],
+            Vec::new(),
+        );
+        let b = items(
+            vec![api(
+                "f",
+                &["c_int", "*const Pair"],
+                Some("Option < & 'static NonNull < u8 > >"),
+                V1_0,
+            )],
+            Vec::new(),
+        );
+        assert_eq!(signature_desc(&a, V1_0), signature_desc(&b, V1_0));
+        assert_eq!(hash(&a, V1_0), hash(&b, V1_0));
+        // 参数名和文档注释不参与指纹计算
+        let mut c = items(
+            vec![api("f", &["c_int", "*const Pair"], None, V1_0)],
+            Vec::new(),
+        );
+        c.fns[0].ret = b.fns[0].ret.clone();
+        c.fns[0].args[0].0 = "renamed".to_string();
+        c.fns[0].docs.push("#[doc = \" 说明\"]".to_string());
+        assert_eq!(hash(&b, V1_0), hash(&c, V1_0));
+    }
+
+    #[test]
+    fn changed_argument_type_changes_hash() {
+        let before = items(vec![api("f", &["u32"], Some("usize"), V1_0)], Vec::new());
+        let after = items(vec![api("f", &["u64"], Some("usize"), V1_0)], Vec::new());
+        assert_ne!(hash(&before, V1_0), hash(&after, V1_0));
+        let unsafe_fn = {
+            let mut items = items(vec![api("f", &["u32"], Some("usize"), V1_0)], Vec::new());
+            items.fns[0].is_unsafe = true;
+            items
+        };
+        assert_ne!(hash(&before, V1_0), hash(&unsafe_fn, V1_0));
+    }
+
+    #[test]
+    fn per_version_descs() {
+        let items = items(
+            vec![
+                api("get", &[], Some("usize"), V1_0),
+                api("version", &[], Some("VersionExample"), V1_1),
+                api("added", &["usize"], None, V1_1),
+            ],
+            vec![api("version", &[], Some("usize"), V1_0)],
+        );
+        // 1.0中只有旧版本的`version`，1.1中为新的定义
+        assert_eq!(
+            signature_desc(&items, V1_0),
+            "fn get: extern \"C\" fn () -> usize\n\
+             fn version: extern \"C\" fn () -> usize\n\
+             trait WaitIf: wait, wake\n"
+        );
+        assert_eq!(
+            signature_desc(&items, V1_1),
+            "fn added: extern \"C\" fn (usize)\n\
+             fn get: extern \"C\" fn () -> usize\n\
+             fn version: extern \"C\" fn () -> VersionExample\n\
+             trait WaitIf: wait, wake\n"
+        );
+        assert_ne!(hash(&items, V1_0), hash(&items, V1_1));
+
+        // wrapper中每个版本一项，按版本从旧到新排列，note中每项16字节
+        let mut config = BuildConfig::new(".", "fingerprint_test");
+        config.abi_version = "1.1".to_string();
+        let content = wrapper_fingerprint_content(&config, &items);
+        let v1_0 = content
+            .find(&format!("(1, 0, {:?})", signature_desc(&items, V1_0)))
+            .unwrap();
+        let v1_1 = content
+            .find(&format!("(1, 1, {:?})", signature_desc(&items, V1_1)))
+            .unwrap();
+        assert!(v1_0 < v1_1);
+        assert!(content.contains("VDSO_SIGNATURE_DESCS: [(u32, u32, &str); 2]"));
+        assert!(content.contains("desc: [u8; 32],"));
+    }
+
+    #[test]
+    fn parse_note() {
+        let mut note = Vec::new();
+        note.extend(5u32.to_le_bytes());
+        note.extend(32u32.to_le_bytes());
+        note.extend(NOTE_TYPE_FINGERPRINT.to_le_bytes());
+        note.extend(b"VDSO\0\0\0\0");
+        for (major, minor, fingerprint) in [(1u32, 0u32, 0x1122u64), (1, 1, 0x3344)] {
+            note.extend(major.to_le_bytes());
+            note.extend(minor.to_le_bytes());
+            note.extend(fingerprint.to_le_bytes());
+        }
+        assert_eq!(
+            parse_fingerprint_note(&note),
+            [(V1_0, 0x1122), (V1_1, 0x3344)]
+        );
+    }
+}
@@ -1,8 +1,14 @@
 use std::{fs, path::Path};
 
-use xmas_elf::symbol_table::Entry;
-
-use crate::BuildConfig;
+use crate::{
+    fingerprint::{read_fingerprints, FINGERPRINT_RS},
+    meta::{read_meta, VdsoMeta, META_RS},
+    resolve::{symbol_offset, RESOLVE_RS},
+    scan::{substitute_type, ApiFn, ImplMethod},
+    so_name,
+    version::{abi_version, AbiVersion},
+    BuildConfig,
+};
 
 /// 在输出路径中创建一个Rust项目“api”，用于：
 /// - 向调用者提供so文件和vvar数据结构的定义，用于调用者初始化vdso。
@@ -11,15 +17,45 @@ pub(crate) fn gen_api(config: &BuildConfig) {
     let lib_path = Path::new(&config.out_dir).join(&config.api_lib_name);
     let src_path = lib_path.join("src");
     fs::create_dir_all(&src_path).unwrap();
+    let elf_path = Path::new(&config.out_dir).join(format!("{}.so", config.so_name));
+    let so_content = fs::read(&elf_path).unwrap();
+    let vdso_elf = xmas_elf::ElfFile::new(&so_content).expect("Error parsing app ELF file.");
+    // 从so文件的元数据节中获取vDSO的API和依赖接口
+    let meta = read_meta(&vdso_elf);
+    // 从so文件中读取各个ABI版本的指纹，写入API库以便在初始化时检查
+    let fingerprints = read_fingerprints(&vdso_elf);
+    // 各个按需加载的模块的so文件
+    let modules = meta.modules();
+    let module_contents: Vec<Vec<u8>> = modules
+        .iter()
+        .map(|module| {
+            let path =
+                Path::new(&config.out_dir).join(format!("{}.so", so_name(config, Some(module))));
+            fs::read(&path).unwrap()
+        })
+        .collect();
+    let module_elfs: Vec<(&str, xmas_elf::ElfFile)> = modules
+        .iter()
+        .zip(module_contents.iter())
+        .map(|(module, content)| {
+            let elf = xmas_elf::ElfFile::new(content).expect("Error parsing module ELF file.");
+            (module.as_str(), elf)
+        })
+        .collect();
+
     let cargo_toml = cargo_toml_content(config);
     let lib_rs = lib_rs_content(config);
-    let api_rs = api_rs_content(config);
-    let loader_rs = loader_rs_content(config);
+    let api_rs = api_rs_content(config, &vdso_elf, &module_elfs, &meta, &fingerprints);
+    let loader_rs = loader_rs_content(config, &meta, &modules);
 
     fs::write(&lib_path.join("Cargo.toml"), cargo_toml).unwrap();
     fs::write(&src_path.join("lib.rs"), lib_rs).unwrap();
     fs::write(&src_path.join("api.rs"), api_rs).unwrap();
     fs::write(&src_path.join("loader.rs"), loader_rs).unwrap();
+    fs::write(src_path.join("meta.rs"), META_RS).unwrap();
+    fs::write(src_path.join("fingerprint.rs"), FINGERPRINT_RS).unwrap();
+    fs::write(src_path.join("error.rs"), ERROR_RS).unwrap();
+    fs::write(src_path.join("resolve.rs"), RESOLVE_RS).unwrap();
 }
 
 fn cargo_toml_content(config: &BuildConfig) -> String {
@@ -37,7 +73,7 @@ page_table_entry = "0.5.7"
 include_bytes_aligned = "0.1.4"
 xmas-elf = "0.9.0"
 elf_parser = {{ git = "https://github.com/rosy233333/elf_parser.git" }}
-lazyinit = "0.2"
+spin = {{ version = "0.9", default-features = false, features = ["spin_mutex"] }}
 
 [features]
 log = []
@@ -56,220 +92,271 @@ pub mod api;
 pub use api::*;
 pub mod loader;
 pub use loader::*;
+pub mod meta;
+pub use meta::*;
+pub mod fingerprint;
+pub use fingerprint::*;
+pub mod error;
+pub use error::*;
+pub mod resolve;
+pub use resolve::*;
 
 extern crate alloc;
 "#,
     )
 }
 
-fn api_rs_content(config: &BuildConfig) -> String {
+fn api_rs_content(
+    config: &BuildConfig,
+    vdso_elf: &xmas_elf::ElfFile,
+    module_elfs: &[(&str, xmas_elf::ElfFile)],
+    meta: &VdsoMeta,
+    fingerprints: &[(AbiVersion, u64)],
+) -> String {
     // 修改自https://github.com/AsyncModules/vsched/blob/e728dadd75aeb8da5cec1642320a6bd24af5b5bb/vsched_apis/build.rs的build_vsched_api函数
 
-    let elf_path = Path::new(&config.out_dir).join(format!("{}.so", config.so_name));
-    let so_content = fs::read(&elf_path).unwrap();
-    let vdso_elf = xmas_elf::ElfFile::new(&so_content).expect("Error parsing app ELF file.");
-
-    // 获取vDSO的 api
-    let api_rs_path = Path::new(&config.src_dir)
-        .join("src")
-        .join("api")
-        .with_extension("rs");
-    // println!("api.rs path: {}", api_rs_path.display());
-    let mut fns = vec![];
-    if let Ok(mut vsched_api_file_content) = fs::read_to_string(&api_rs_path) {
-        vsched_api_file_content = vsched_api_file_content
-            .split('\n')
-            .filter(|s| !(*s).trim().starts_with("//"))
-            .collect();
-        vsched_api_file_content = vsched_api_file_content.split('\t').collect();
-        vsched_api_file_content = vsched_api_file_content.split("    ").collect();
-        // println!("vsched_api_file_content: {}", vsched_api_file_content);
-
-        let re = regex::Regex::new(
-            r#"#\[unsafe\(no_mangle\)\]pub extern \"C\" fn ([a-zA-Z0-9_]+)(\([a-zA-Z0-9_:]?[^\{]*\)[->]?[^\{]*) \{"#,
-        )
-        .unwrap();
-
-        for (_, [name, args]) in re
-            .captures_iter(&vsched_api_file_content)
-            .map(|c| c.extract())
-        {
-            println!("name: {}\nargs: {}", name, args);
-            fns.push((name.to_owned(), args.to_owned()));
-        }
-
-        let re = regex::Regex::new(r#"extern \"C\" \{([^\{\}]+)\}"#).unwrap();
-        for (_, [extern_fns]) in re
-            .captures_iter(&vsched_api_file_content)
-            .map(|c| c.extract())
-        {
-            let fns_re = regex::Regex::new(r#"fn ([a-zA-Z0-9_]+)\(\) -> !;"#).unwrap();
-            for (_, [name]) in fns_re.captures_iter(&extern_fns).map(|c| c.extract()) {
-                println!("name: {}", name);
-                fns.push((name.to_owned(), "() -> !".into()));
-            }
-        }
-
-        if config.log {
-            fns.push(("init_log".into(), "(logger_fat_ptr: (usize, usize))".into()));
-        }
-    }
-
-    let mut traits = vec![];
-    let interface_rs_path = Path::new(&config.src_dir)
-        .join("src")
-        .join("interface")
-        .with_extension("rs");
-    // println!("api.rs path: {}", api_rs_path.display());
-    if let Ok(mut vsched_interface_file_content) = fs::read_to_string(&interface_rs_path) {
-        vsched_interface_file_content = vsched_interface_file_content
-            .split('\n')
-            .filter(|s| !(*s).trim().starts_with("//"))
-            .collect();
-        vsched_interface_file_content = vsched_interface_file_content.split('\t').collect();
-        vsched_interface_file_content = vsched_interface_file_content.split("    ").collect();
-        // println!(
-        //     "cargo:warning=vsched_interface_file_content: {}",
-        //     vsched_interface_file_content
-        // );
-
-        let re =
-            regex::Regex::new(r#"trait_interface\! \{pub trait ([a-zA-Z0-9_]+) \{([^\{\}]+)\}\}"#)
-                .unwrap();
-        // let re = regex::Regex::new(r#"pub trait ([a-zA-Z0-9_]+) \{([^\{\}]+)\}"#).unwrap();
-        // let re = regex::Regex::new(r#"pub trait ([a-zA-Z0-9_]+)\{(.)?"#).unwrap();
-
-        // 获取vDSO的 interface
-        for (_, [name, fns]) in re
-            .captures_iter(&vsched_interface_file_content)
-            .map(|c| c.extract())
-        {
-            let mut fns_name = vec![];
-            let fns_name_re = regex::Regex::new(r#"fn ([a-zA-Z0-9_]+)\("#).unwrap();
-            fns_name_re
-                .captures_iter(&fns)
-                .map(|c| c.extract())
-                .for_each(|(_, [fn_name])| {
-                    fns_name.push(fn_name.to_owned());
-                });
-            traits.push((name.to_owned(), fns_name));
-        }
-        println!("cargo:warning=traits: {:?}", traits);
-        // panic!("pause");
-    }
+    // 获取vDSO的 api 和 interface
+    let fns = &meta.fns;
+    let traits = &meta.traits;
+    // VDSO_VTABLE中的所有表项：API函数和各个依赖接口的初始化函数
+    let vtable_fns: Vec<ApiFn> = fns
+        .iter()
+        .cloned()
+        .chain(traits.iter().map(|t| t.init_fn()))
+        .collect();
 
     // pub use vdso库中的内容
     let pub_use_vdso_str = format!(
-        "extern crate {};\nuse alloc::vec::Vec;\npub use page_table_entry::MappingFlags;\npub use self::{}::*;\n\n",
-        config.package_name, config.package_name
+        "extern crate {};\nuse alloc::vec::Vec;\nuse core::sync::atomic::{{AtomicU64, AtomicUsize, Ordering}};\nuse spin::Mutex;\npub use page_table_entry::MappingFlags;\npub use self::{}::*;\nuse crate::{{error::VdsoError, fingerprint::VdsoFingerprints, loader::KernelVdso}};\n{}\n",
+        config.package_name,
+        config.package_name,
+        if config.resolve_at_runtime {
+            "use crate::resolve::DynSymTable;\n"
+        } else {
+            ""
+        }
     );
     // vdso_vtable 数据结构定义
-    let mut vdso_vtable_struct_str = "pub struct VdsoVTable {\n".to_string();
-    for (name, args) in fns.iter() {
-        vdso_vtable_struct_str.push_str(&format!("    pub {}: Option<fn{}>,\n", name, args));
-    }
-    for (name, fns_name) in traits.iter() {
-        let init_fn_name = format!("init_vtable_{}", name);
-        let args = format!(
-            "({})",
-            fns_name
-                .iter()
-                .map(|_fn_name| "usize")
-                .collect::<Vec<_>>()
-                .join(", ")
-        );
-        vdso_vtable_struct_str
-            .push_str(&format!("    pub {}: Option<fn{}>,\n", init_fn_name, args));
+    let mut vdso_vtable_struct_str =
+        "#[derive(Clone, Copy)]\npub struct VdsoVTable {\n".to_string();
+    for f in vtable_fns.iter() {
+        vdso_vtable_struct_str.push_str(&format!(
+            "    pub {}: Option<{}>,\n",
+            f.name,
+            f.fn_ptr_type()
+        ));
     }
     vdso_vtable_struct_str.push_str("}\n");
 
-    // 定义静态的 VDSO_VTABLE
+    // 定义空的VTABLE，以及持有VTABLE的实例类型
     let mut static_vdso_vtable_str =
-        "\npub static mut VDSO_VTABLE: VdsoVTable = VdsoVTable {\n".to_string();
-    for (name, _) in fns.iter() {
-        static_vdso_vtable_str.push_str(&format!("    {}: None,\n", name));
+        "\nimpl VdsoVTable {\n    const EMPTY: Self = Self {\n".to_string();
+    for f in vtable_fns.iter() {
+        static_vdso_vtable_str.push_str(&format!("        {}: None,\n", f.name));
     }
-    for (name, _) in traits.iter() {
-        let init_fn_name = format!("init_vtable_{}", name);
-        static_vdso_vtable_str.push_str(&format!("    {}: None,\n", init_fn_name));
+    static_vdso_vtable_str.push_str("    };\n}\n");
+
+    // VTABLE的存储形式：每一项为原子的函数地址，以便多个核并发地初始化和调用
+    static_vdso_vtable_str.push_str(
+        r#"
+/// VTABLE在实例中的存储形式，每一项为函数地址，0表示未初始化。
+struct AtomicVTable {
+"#,
+    );
+    for f in vtable_fns.iter() {
+        static_vdso_vtable_str.push_str(&format!(
+            "    {}: AtomicUsize,
+",
+            f.name
+        ));
+    }
+    static_vdso_vtable_str.push_str(
+        "}\n\nimpl AtomicVTable {\n    #[allow(clippy::declare_interior_mutable_const)]\n    const EMPTY: Self = Self {\n",
+    );
+    for f in vtable_fns.iter() {
+        static_vdso_vtable_str.push_str(&format!("        {}: AtomicUsize::new(0),\n", f.name));
     }
-    static_vdso_vtable_str.push_str("};\n");
+    static_vdso_vtable_str.push_str("    };\n");
+    for f in vtable_fns.iter() {
+        static_vdso_vtable_str.push_str(&format!(
+            r#"
+    fn {}(&self) -> Option<{}> {{
+        match self.{}.load(Ordering::Acquire) {{
+            0 => None,
+            addr => Some(unsafe {{ core::mem::transmute::<usize, {}>(addr) }}),
+        }}
+    }}
+"#,
+            f.name,
+            f.fn_ptr_type(),
+            f.name,
+            f.fn_ptr_type()
+        ));
+    }
+    static_vdso_vtable_str.push_str("}\n");
+    static_vdso_vtable_str.push_str(
+        "\n/// 读出`slot`中的VTABLE。\nfn load_vtable(slot: &AtomicVTable) -> VdsoVTable {\n    VdsoVTable {\n",
+    );
+    for f in vtable_fns.iter() {
+        static_vdso_vtable_str.push_str(&format!("        {}: slot.{}(),\n", f.name, f.name));
+    }
+    static_vdso_vtable_str.push_str(
+        "    }\n}\n\n/// 将`table`写入`slot`。\nfn store_vtable(slot: &AtomicVTable, table: &VdsoVTable) {\n",
+    );
+    for f in vtable_fns.iter() {
+        static_vdso_vtable_str.push_str(&format!(
+            "    slot.{}.store(table.{}.map_or(0, |f| f as usize), Ordering::Release);\n",
+            f.name, f.name
+        ));
+    }
+    static_vdso_vtable_str.push_str("}\n");
+    // 已初始化的其它模块。依赖接口需要在之后加载的模块中重新注册，因此在此记录
+    static_vdso_vtable_str.push_str(
+        r#"
+/// 实例中已初始化的其它模块，以及已注册的依赖接口。
+struct ModuleState {
+    /// 已初始化的模块名、该模块镜像的首地址，以及存放该模块自己的依赖接口初始化函数和`init_log`的VTABLE
+    loaded: Vec<(&'static str, u64, VdsoVTable)>,
+"#,
+    );
+    let track_images = !traits.is_empty() || config.log;
+    if !traits.is_empty() {
+        static_vdso_vtable_str.push_str(
+            "    /// 通过`init_vtable_*`注册的依赖接口的trait名和各个函数的地址，用于在之后加载的模块中注册\n    interfaces: Vec<(&'static str, Vec<usize>)>,\n",
+        );
+    }
+    if track_images {
+        static_vdso_vtable_str
+            .push_str("    /// 各个模块镜像中已完成的初始化\n    images: Vec<ModuleImage>,\n");
+    }
+    static_vdso_vtable_str.push_str(&format!(
+        "}}\n\nimpl ModuleState {{\n    const fn new() -> Self {{\n        Self {{\n            loaded: Vec::new(),\n{}{}        }}\n    }}\n}}\n",
+        if traits.is_empty() { "" } else { "            interfaces: Vec::new(),\n" },
+        if track_images { "            images: Vec::new(),\n" } else { "" }
+    ));
+    if track_images {
+        // 模块镜像中的依赖接口和日志只能初始化一次，因此记录每个镜像中已完成的初始化
+        let mut image_str = String::from(
+            "\n/// 首地址为`base`的模块镜像中已完成的初始化。\n///\n/// 镜像中的依赖接口和日志只能初始化一次，因此再次以该镜像调用`init_module`时，只补做尚未完成的部分。\nstruct ModuleImage {\n    base: u64,\n",
+        );
+        if !traits.is_empty() {
+            image_str.push_str(
+                "    /// 已在该镜像中注册的依赖接口的trait名\n    interfaces: Vec<&'static str>,\n",
+            );
+        }
+        if config.log {
+            image_str.push_str("    /// 该镜像中的日志是否已初始化\n    log: bool,\n");
+        }
+        image_str.push_str("}\n");
+        static_vdso_vtable_str.push_str(&image_str);
+    }
+    static_vdso_vtable_str.push_str(INSTANCE_STR);
 
     // 运行时初始化 vsched_table 的函数
-    let dyn_sym_table = vdso_elf.find_section_by_name(".dynsym").unwrap();
-    let dyn_sym_table = match dyn_sym_table.get_data(&vdso_elf) {
-        Ok(xmas_elf::sections::SectionData::DynSymbolTable64(dyn_sym_table)) => dyn_sym_table,
-        _ => panic!("Invalid data in .dynsym section"),
-    };
-    let mut fn_init_vdso_vtable_str = INIT_VDSO_VTABLE_STR.to_string();
-
-    for (name, args) in fns.iter() {
-        let mut sym_value: usize = 0;
-        for dynsym in dyn_sym_table {
-            let sym_name = dynsym.get_name(&vdso_elf).unwrap();
-            if sym_name == *name {
-                sym_value = dynsym.value() as usize;
-                break;
+    let abi_version = abi_version(config);
+    let mut fn_init_vdso_vtable_str = format!(
+        r#"
+/// 该API库绑定的ABI版本。
+pub const VDSO_ABI_VERSION: (u32, u32) = ({}, {});
+/// 构建API库时so文件中各个ABI版本的指纹，按版本从旧到新排列。
+pub const VDSO_FINGERPRINTS: &[((u32, u32), u64)] = &[
+{}];
+/// 除主模块外，可以按需加载的各个模块的名称。
+pub const VDSO_MODULE_NAMES: &[&str] = &[{}];
+"#,
+        abi_version.major,
+        abi_version.minor,
+        fingerprints
+            .iter()
+            .map(|(version, fingerprint)| format!(
+                "    (({}, {}), 0x{:016x}),\n",
+                version.major, version.minor, fingerprint
+            ))
+            .collect::<String>(),
+        module_elfs
+            .iter()
+            .map(|(module, _)| format!("\"{}\"", module))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    fn_init_vdso_vtable_str.push_str(CHECK_FINGERPRINTS_STR);
+    if config.resolve_at_runtime {
+        // 与vDSO协商版本，只比较该版本的指纹
+        fn_init_vdso_vtable_str.push_str(
+            r#"    // 找到双方都支持的最新版本，比较该版本的指纹
+    for &(version, expected) in VDSO_FINGERPRINTS.iter().rev() {
+        if let Some(found) = fingerprints.get(version) {
+            if found != expected {
+                return Err(VdsoError::FingerprintMismatch { expected, found });
             }
+            return Ok(version);
         }
-        assert!(sym_value != 0, "Function {} not found in .dynsym", name);
-
-        fn_init_vdso_vtable_str.push_str(&format!(
-            r#"    // {}:
-    let fn_ptr = base + 0x{:x};
-    #[cfg(feature = "log")]
-    log::debug!("{}: 0x{{:x}}", fn_ptr);
-    let f: fn{} = unsafe {{ core::mem::transmute(fn_ptr) }};
-    unsafe {{ VDSO_VTABLE.{}  = Some(f); }}
-
-"#,
-            name, sym_value, name, args, name
-        ));
     }
-
-    for (name, fns_name) in traits.iter() {
-        let init_fn_name = format!("init_vtable_{}", name);
-        let mut sym_value: usize = 0;
-        for dynsym in dyn_sym_table {
-            let sym_name = dynsym.get_name(&vdso_elf).unwrap();
-            if sym_name == init_fn_name.as_str() {
-                sym_value = dynsym.value() as usize;
-                break;
+    Err(VdsoError::NoCommonVersion)
+}
+"#,
+        );
+    } else {
+        // 函数偏移在构建时写入，只能与构建时的so文件配合使用，因此不协商版本
+        fn_init_vdso_vtable_str.push_str(
+            r#"    let (expected_version, expected) = VDSO_FINGERPRINTS[VDSO_FINGERPRINTS.len() - 1];
+    match fingerprints.latest() {
+        Some((version, found)) if version == expected_version => {
+            if found != expected {
+                return Err(VdsoError::FingerprintMismatch { expected, found });
             }
+            Ok(version)
         }
-        assert!(
-            sym_value != 0,
-            "Function {} not found in .dynsym",
-            init_fn_name
+        Some(_) => Err(VdsoError::NoCommonVersion),
+        None => Err(VdsoError::MissingFingerprint),
+    }
+}
+"#,
         );
+    }
 
-        let args = format!(
-            "({})",
-            fns_name
-                .iter()
-                .map(|_fn_name| "usize")
-                .collect::<Vec<_>>()
-                .join(", ")
-        );
+    // 主模块中的函数：API和各个依赖接口的初始化函数
+    fn_init_vdso_vtable_str.push_str(RESOLVE_VTABLE_STR);
+    if config.resolve_at_runtime {
+        fn_init_vdso_vtable_str.push_str(RESOLVE_SYMBOLS_STR);
+    }
+    let main_fns: Vec<&ApiFn> = vtable_fns.iter().filter(|f| f.module.is_none()).collect();
+    for f in main_fns.iter() {
+        fn_init_vdso_vtable_str.push_str(&format!(
+            "    let addr_{} = {};\n",
+            f.name,
+            addr_expr(config, vdso_elf, f)
+        ));
+    }
+    fn_init_vdso_vtable_str.push_str("\n    let mut table = VdsoVTable::EMPTY;\n");
 
+    for f in main_fns.iter() {
         fn_init_vdso_vtable_str.push_str(&format!(
             r#"    // {}:
-    let fn_ptr = base + 0x{:x};
     #[cfg(feature = "log")]
-    log::debug!("{}: 0x{{:x}}", fn_ptr);
-    let f: fn{} = unsafe {{ core::mem::transmute(fn_ptr) }};
-    unsafe {{ VDSO_VTABLE.{}  = Some(f); }}
+    log::debug!("{}: {{:x?}}", addr_{});
+    let f = addr_{}.map(|fn_ptr| unsafe {{ core::mem::transmute::<usize, {}>(fn_ptr) }});
+    table.{} = f;
 
 "#,
-            init_fn_name, sym_value, init_fn_name, args, init_fn_name
+            f.name,
+            f.name,
+            f.name,
+            f.name,
+            f.fn_ptr_type(),
+            f.name
         ));
     }
 
     fn_init_vdso_vtable_str.push_str(
-        r#"}
-    "#,
+        r#"    Ok((table, bound))
+}
+"#,
     );
 
+    // 其它模块中的函数
+    fn_init_vdso_vtable_str.push_str(&resolve_module_content(config, module_elfs, &vtable_fns));
+
     //     fn_init_vdso_vtable_str.push_str(
     //         r#"
     // /// 在加载vDSO的地址空间（通常是内核）中调用，同时加载vDSO和初始化VTABLE。
@@ -300,157 +387,1280 @@ fn api_rs_content(config: &BuildConfig) -> String {
     // "#,
     //     );
 
-    fn_init_vdso_vtable_str.push_str(
-        r#"
-/// 在加载vDSO的地址空间（通常是内核）中调用，同时加载vDSO和初始化VTABLE。
-/// 
-/// 若在一个地址空间中加载再映射到另一个地址空间中，需使用`map_and_init`。
-/// 
-/// 该函数的返回值为vDSO和vVAR的映射区域的信息，元组的三项依次为首地址、大小和访问权限。vDSO首地址为第二个映射区域的首地址。
-/// 
-/// 在调用该库的其余API前，需先调用此函数。
-pub fn load_and_init(vspace: usize) {
-    let vdso = crate::map_so(vspace);
-    unsafe{ init_vdso_vtable(vdso as _) };
-    init_vdso_log();
-}
-"#,
-    );
+    fn_init_vdso_vtable_str.push_str(INSTANCE_INIT_STR);
 
     let init_vdso_log_body = if config.log {
         r#"
-    let logger = log::logger();
-    let fat_ptr: (usize, usize) = unsafe { core::mem::transmute(logger) };
-    init_log(fat_ptr);
-"#
+        let logger = log::logger();
+        let fat_ptr: (usize, usize) = unsafe { core::mem::transmute(logger) };
+        self.init_log(fat_ptr);
+    "#
     } else {
         ""
     };
 
     let init_vdso_log_fn = format!(
         r#"
-fn init_vdso_log() {{{}}}
-
+    pub(crate) fn init_vdso_log(&self) {{{}}}
 "#,
         init_vdso_log_body
     );
 
     fn_init_vdso_vtable_str.push_str(&init_vdso_log_fn);
 
-    // 构建给内核和用户运行时使用的接口
-    let mut apis = vec![];
+    // 初始化其它模块：写入该模块的API，并在该模块中注册依赖接口、初始化日志
+    let mut module_setup = String::new();
+    if track_images {
+        let mut fields = vec!["base".to_string()];
+        if !traits.is_empty() {
+            fields.push("interfaces: Vec::new()".to_string());
+        }
+        if config.log {
+            fields.push("log: false".to_string());
+        }
+        module_setup.push_str(&format!(
+            r#"        // 找到该模块镜像中已完成的初始化，只补做尚未完成的部分
+        let index = match modules.images.iter().position(|image| image.base == base) {{
+            Some(index) => index,
+            None => {{
+                modules.images.push(ModuleImage {{ {} }});
+                modules.images.len() - 1
+            }}
+        }};
+        let image = &mut modules.images[index];
+"#,
+            fields.join(", ")
+        ));
+    }
+    if !traits.is_empty() {
+        module_setup.push_str(
+            "        // 在该模块中注册已注册的依赖接口\n        for (interface, args) in modules.interfaces.iter() {\n            if image.interfaces.contains(interface) {\n                continue;\n            }\n            match *interface {\n",
+        );
+        for t in traits.iter() {
+            let args = (0..t.fns.len())
+                .map(|index| format!("args[{}]", index))
+                .collect::<Vec<_>>()
+                .join(", ");
+            module_setup.push_str(&format!(
+                "                \"{}\" => {{\n                    if let Some(f) = own.init_vtable_{} {{\n                        f({});\n                    }}\n                }}\n",
+                t.name, t.name, args
+            ));
+        }
+        module_setup.push_str("                _ => {}\n            }\n            image.interfaces.push(interface);\n        }\n");
+    }
+    if config.log {
+        module_setup.push_str(
+            r#"        // 初始化该模块的日志
+        if !image.log {
+            if let Some(init_log) = own.init_log {
+                let logger = log::logger();
+                let fat_ptr: (usize, usize) = unsafe { core::mem::transmute(logger) };
+                init_log(fat_ptr);
+            }
+            image.log = true;
+        }
+"#,
+        );
+    }
+    fn_init_vdso_vtable_str.push_str(&MODULE_INIT_STR.replace("{module_setup}", &module_setup));
+    if !module_elfs.is_empty() {
+        fn_init_vdso_vtable_str.push_str(REQUIRE_MODULE_STR);
+    }
+
+    // 构建给内核和用户运行时使用的接口：VdsoInstance的方法，以及作用于默认实例的自由函数
+    let mut methods = vec![];
+    let mut apis = vec![];
+
+    // api部分
+    for f in fns.iter() {
+        if INSTANCE_METHODS.contains(&f.name.as_str()) {
+            panic!(
+                "vDSO API `{}` conflicts with a method of VdsoInstance, please rename it",
+                f.name
+            );
+        }
+        if let Some(other) = fns
+            .iter()
+            .find(|other| other.name == format!("try_{}", f.name))
+        {
+            panic!(
+                "vDSO API `{}` conflicts with the fallible wrapper of `{}`, please rename it",
+                other.name, f.name
+            );
+        }
+        let (unsafety, call, try_call) = if f.is_unsafe {
+            (
+                "unsafe ",
+                format!("unsafe {{ f({}) }}", f.call_args_str()),
+                format!("unsafe {{ self.try_{}({}) }}", f.name, f.call_args_str()),
+            )
+        } else {
+            (
+                "",
+                format!("f({})", f.call_args_str()),
+                format!("self.try_{}({})", f.name, f.call_args_str()),
+            )
+        };
+        let not_initialized = match &f.module {
+            Some(module) => format!(
+                "{} is not initialized, or module {} cannot be loaded",
+                f.name, module
+            ),
+            None if f.version == AbiVersion::BASE => format!("{} is not initialized", f.name),
+            None => format!(
+                "{} is not initialized, or the loaded vDSO does not provide {}",
+                f.name,
+                f.version.node_name()
+            ),
+        };
+        // 其它模块中的API在首次调用时加载所属的模块
+        let lookup = match &f.module {
+            Some(module) => format!(
+                r#"match self.active_table().{}() {{
+            Some(f) => f,
+            // 所属模块尚未初始化，加载该模块后重试
+            None if self.abi_version().is_some() => {{
+                self.require_module("{}")?;
+                self.active_table().{}().ok_or(VdsoError::NotInitialized("{}"))?
+            }}
+            None => return Err(VdsoError::NotInitialized("{}")),
+        }}"#,
+                f.name, module, f.name, f.name, f.name
+            ),
+            None => format!(
+                "self.active_table().{}().ok_or(VdsoError::NotInitialized(\"{}\"))?",
+                f.name, f.name
+            ),
+        };
+        let method_docs: String = f.docs.iter().map(|doc| format!("    {}\n", doc)).collect();
+        methods.push(format!(
+            r#"
+{}    pub {}fn {}{}{} {{
+        match {} {{
+            Ok(res) => res,
+            Err(_) => panic!("{}"),
+        }}
+    }}
+
+    /// 与`{}`相同，但在VTABLE中没有该API时返回`VdsoError::NotInitialized`，而不是panic。
+    pub {}fn try_{}{}{} {{
+        let f = {};
+        #[cfg(feature = "log")]
+        log::debug!("Calling {} at 0x{{:x}}.", f as *const () as usize);
+        let res = {};
+        #[cfg(feature = "log")]
+        log::debug!("Returned from {}.");
+        Ok(res)
+    }}
+"#,
+            method_docs,
+            unsafety,
+            f.name,
+            f.generics_str(),
+            f.method_params_str(),
+            try_call,
+            not_initialized,
+            f.name,
+            unsafety,
+            f.name,
+            f.generics_str(),
+            f.try_method_params_str(),
+            lookup,
+            f.name,
+            call,
+            f.name
+        ));
+
+        let docs: String = f.docs.iter().map(|doc| format!("{}\n", doc)).collect();
+        let (call, try_call) = if f.is_unsafe {
+            (
+                format!(
+                    "unsafe {{ VDSO_INSTANCE.{}({}) }}",
+                    f.name,
+                    f.call_args_str()
+                ),
+                format!(
+                    "unsafe {{ VDSO_INSTANCE.try_{}({}) }}",
+                    f.name,
+                    f.call_args_str()
+                ),
+            )
+        } else {
+            (
+                format!("VDSO_INSTANCE.{}({})", f.name, f.call_args_str()),
+                format!("VDSO_INSTANCE.try_{}({})", f.name, f.call_args_str()),
+            )
+        };
+        apis.push(format!(
+            r#"
+{}pub {}fn {}{}{} {{
+    {}
+}}
+
+/// 与`{}`相同，但在VTABLE中没有该API时返回`VdsoError::NotInitialized`，而不是panic。
+pub {}fn try_{}{}{} {{
+    {}
+}}
+"#,
+            docs,
+            unsafety,
+            f.name,
+            f.generics_str(),
+            f.params_str(),
+            call,
+            f.name,
+            unsafety,
+            f.name,
+            f.generics_str(),
+            f.try_params_str(),
+            try_call
+        ));
+    }
+
+    // trait的初始化api部分
+    for t in traits.iter() {
+        let init_fn_name = format!("init_vtable_{}", t.name);
+
+        let fn_args = t
+            .fns
+            .iter()
+            .map(|fn_name| format!("T::{} as *const () as usize", fn_name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let call_args = (0..t.fns.len())
+            .map(|index| format!("args[{}]", index))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        methods.push(format!(
+            r#"
+    pub fn {}<T:{}>(&self) {{
+        if self.try_{}::<T>().is_err() {{
+            panic!("{} is not initialized")
+        }}
+    }}
+
+    /// 与`{}`相同，但在VTABLE中没有该函数时返回`VdsoError::NotInitialized`，而不是panic。
+    ///
+    /// 该依赖接口也会在已初始化和之后初始化的其它模块中注册。
+    pub fn try_{}<T:{}>(&self) -> Result<(), VdsoError> {{
+        let f = self.active_table().{}().ok_or(VdsoError::NotInitialized("{}"))?;
+        let args = [{}];
+        #[cfg(feature = "log")]
+        log::debug!("Calling {} at 0x{{:x}}.", f as *const () as usize);
+        f({});
+        #[cfg(feature = "log")]
+        log::debug!("Returned from {}.");
+        let mut modules = self.modules.lock();
+        let ModuleState {{ loaded, interfaces, images }} = &mut *modules;
+        for (_, base, table) in loaded.iter() {{
+            let Some(image) = images.iter_mut().find(|image| image.base == *base) else {{
+                continue;
+            }};
+            if image.interfaces.contains(&"{}") {{
+                continue;
+            }}
+            if let Some(f) = table.{} {{
+                f({});
+            }}
+            image.interfaces.push("{}");
+        }}
+        interfaces.retain(|(interface, _)| *interface != "{}");
+        interfaces.push(("{}", args.to_vec()));
+        Ok(())
+    }}
+"#,
+            init_fn_name,
+            t.name,
+            init_fn_name,
+            init_fn_name,
+            init_fn_name,
+            init_fn_name,
+            t.name,
+            init_fn_name,
+            init_fn_name,
+            fn_args,
+            init_fn_name,
+            call_args,
+            init_fn_name,
+            t.name,
+            init_fn_name,
+            call_args,
+            t.name,
+            t.name,
+            t.name
+        ));
+
+        apis.push(format!(
+            r#"
+pub fn {}<T:{}>() {{
+    VDSO_INSTANCE.{}::<T>()
+}}
+
+/// 与`{}`相同，但在VTABLE中没有该函数时返回`VdsoError::NotInitialized`，而不是panic。
+pub fn try_{}<T:{}>() -> Result<(), VdsoError> {{
+    VDSO_INSTANCE.try_{}::<T>()
+}}
+"#,
+            init_fn_name, t.name, init_fn_name, init_fn_name, init_fn_name, t.name, init_fn_name
+        ));
+    }
+
+    // 异步api部分：将vDSO导出的各个入口包装为Future
+    for f in meta.async_fns.iter() {
+        if INSTANCE_METHODS.contains(&f.name.as_str())
+            || fns
+                .iter()
+                .any(|other| other.name == f.name || other.name == format!("try_{}", f.name))
+        {
+            panic!(
+                "vDSO async API `{}` conflicts with another API or a method of VdsoInstance, please rename it",
+                f.name
+            );
+        }
+        let future = format!("{}Future", camel_case(&f.name));
+        let output = f.ret.as_deref().unwrap_or("()");
+        let unsafety = if f.is_unsafe { "unsafe " } else { "" };
+        let call_args = f.call_args_str();
+        let init_args = if call_args.is_empty() {
+            "state".to_string()
+        } else {
+            format!("state, {}", call_args)
+        };
+        apis.push(format!(
+            r#"
+/// `{name}`返回的Future，见`VdsoInstance::{name}`。
+///
+/// Future本身保存在由该结构体持有的堆内存中，每次`poll`时由vDSO中的代码推进；
+/// 在完成前析构时，vDSO中的Future也会被析构。
+pub struct {future}<'a> {{
+    instance: &'a VdsoInstance,
+    state: *mut u8,
+    layout: core::alloc::Layout,
+    done: bool,
+}}
+
+impl core::future::Future for {future}<'_> {{
+    type Output = {output};
+
+    fn poll(mut self: core::pin::Pin<&mut Self>, cx: &mut core::task::Context<'_>) -> core::task::Poll<{output}> {{
+        assert!(!self.done, "`{name}` polled after completion");
+        let waker = cx.waker();
+        let mut output = core::mem::MaybeUninit::<{output}>::uninit();
+        let ready = unsafe {{
+            self.instance
+                .{name}_poll(self.state, waker.data(), waker.vtable(), output.as_mut_ptr())
+        }};
+        if ready {{
+            self.done = true;
+            core::task::Poll::Ready(unsafe {{ output.assume_init() }})
+        }} else {{
+            core::task::Poll::Pending
+        }}
+    }}
+}}
+
+impl Drop for {future}<'_> {{
+    fn drop(&mut self) {{
+        if !self.done {{
+            unsafe {{ self.instance.{name}_drop_state(self.state) }};
+        }}
+        if self.layout.size() != 0 {{
+            unsafe {{ alloc::alloc::dealloc(self.state, self.layout) }};
+        }}
+    }}
+}}
+"#,
+            name = f.name,
+            future = future,
+            output = output,
+        ));
+        let method_docs: String = f.docs.iter().map(|doc| format!("    {}\n", doc)).collect();
+        methods.push(format!(
+            r#"
+{method_docs}    pub {unsafety}fn {name}({method_args}) -> {future}<'_> {{
+        match {call} {{
+            Ok(future) => future,
+            Err(_) => panic!("{name} is not initialized"),
+        }}
+    }}
+
+    /// 与`{name}`相同，但在VTABLE中没有该API时返回`VdsoError::NotInitialized`，而不是panic。
+    pub {unsafety}fn try_{name}({method_args}) -> Result<{future}<'_>, VdsoError> {{
+        let size = self.try_{name}_state_size()?;
+        let align = self.try_{name}_state_align()?;
+        let layout = core::alloc::Layout::from_size_align(size, align).expect("invalid future layout");
+        // 大小为0的Future不需要分配内存，只需要对齐的非空指针
+        let state = if size == 0 {{
+            core::ptr::without_provenance_mut(align)
+        }} else {{
+            let state = unsafe {{ alloc::alloc::alloc(layout) }};
+            if state.is_null() {{
+                alloc::alloc::handle_alloc_error(layout);
+            }}
+            state
+        }};
+        if let Err(e) = unsafe {{ self.try_{name}_init_state({init_args}) }} {{
+            if size != 0 {{
+                unsafe {{ alloc::alloc::dealloc(state, layout) }};
+            }}
+            return Err(e);
+        }}
+        Ok({future} {{
+            instance: self,
+            state,
+            layout,
+            done: false,
+        }})
+    }}
+"#,
+            method_docs = method_docs,
+            unsafety = unsafety,
+            name = f.name,
+            method_args = f.method_args_decl_str(),
+            future = future,
+            call = if f.is_unsafe {
+                format!("unsafe {{ self.try_{}({}) }}", f.name, call_args)
+            } else {
+                format!("self.try_{}({})", f.name, call_args)
+            },
+            init_args = init_args,
+        ));
+        let docs: String = f.docs.iter().map(|doc| format!("{}\n", doc)).collect();
+        let (call, try_call) = if f.is_unsafe {
+            (
+                format!("unsafe {{ VDSO_INSTANCE.{}({}) }}", f.name, call_args),
+                format!("unsafe {{ VDSO_INSTANCE.try_{}({}) }}", f.name, call_args),
+            )
+        } else {
+            (
+                format!("VDSO_INSTANCE.{}({})", f.name, call_args),
+                format!("VDSO_INSTANCE.try_{}({})", f.name, call_args),
+            )
+        };
+        apis.push(format!(
+            r#"
+{docs}pub {unsafety}fn {name}({args}) -> {future}<'static> {{
+    {call}
+}}
+
+/// 与`{name}`相同，但在VTABLE中没有该API时返回`VdsoError::NotInitialized`，而不是panic。
+pub {unsafety}fn try_{name}({args}) -> Result<{future}<'static>, VdsoError> {{
+    {try_call}
+}}
+"#,
+            docs = docs,
+            unsafety = unsafety,
+            name = f.name,
+            args = f.args_decl_str(),
+            future = future,
+            call = call,
+            try_call = try_call,
+        ));
+    }
+
+    // 泛型api部分：通过密封的trait将各个类型实参分派到对应的实例
+    let mut sealed_traits = String::new();
+    for g in meta.generic_fns.iter() {
+        let f = &g.api;
+        if INSTANCE_METHODS.contains(&f.name.as_str())
+            || fns
+                .iter()
+                .any(|other| other.name == f.name || other.name == format!("try_{}", f.name))
+        {
+            panic!(
+                "vDSO generic API `{}` conflicts with another API or a method of VdsoInstance, please rename it",
+                f.name
+            );
+        }
+        let camel = camel_case(&f.name);
+        let instance_trait = format!("{}Instance", camel);
+        let unsafety = if f.is_unsafe { "unsafe " } else { "" };
+        let (unsafe_open, unsafe_close) = if f.is_unsafe {
+            ("unsafe { ", " }")
+        } else {
+            ("", "")
+        };
+        // 只有一个类型参数时为该类型实现trait，否则为类型实参组成的元组实现，并以关联类型给出各个类型实参
+        let single = g.params.len() == 1;
+        let in_trait: Vec<String> = if single {
+            vec!["Self".to_string()]
+        } else {
+            g.params.iter().map(|p| format!("Self::{}", p)).collect()
+        };
+        let trait_args: String = f
+            .args
+            .iter()
+            .map(|(name, ty)| format!(", {}: {}", name, substitute_type(ty, &g.params, &in_trait)))
+            .collect();
+        let trait_ret = match &f.ret {
+            Some(ret) => substitute_type(ret, &g.params, &in_trait),
+            None => "()".to_string(),
+        };
+        let trait_sig = format!(
+            "{}fn call(instance: &VdsoInstance{}) -> Result<{}, VdsoError>",
+            unsafety, trait_args, trait_ret
+        );
+        let assoc_types: String = if single {
+            String::new()
+        } else {
+            g.params
+                .iter()
+                .map(|p| format!("    type {};\n", p))
+                .collect()
+        };
+        sealed_traits.push_str(&format!("    pub trait {} {{}}\n", camel));
+        let mut content = format!(
+            r#"
+/// 可以作为`{name}`的类型参数的类型，只为vDSO中实例化了的类型实现。
+pub trait {instance_trait}: Sized + sealed::{camel} {{
+{assoc_types}    #[doc(hidden)]
+    {trait_sig};
+}}
+"#,
+            name = f.name,
+            instance_trait = instance_trait,
+            camel = camel,
+            assoc_types = assoc_types,
+            trait_sig = trait_sig,
+        );
+        for instance in g.instances.iter() {
+            let self_ty = if single {
+                instance.types[0].clone()
+            } else {
+                format!("({})", instance.types.join(", "))
+            };
+            let assoc_types: String = if single {
+                String::new()
+            } else {
+                g.params
+                    .iter()
+                    .zip(instance.types.iter())
+                    .map(|(p, ty)| format!("    type {} = {};\n", p, ty))
+                    .collect()
+            };
+            content.push_str(&format!(
+                r#"
+impl sealed::{camel} for {self_ty} {{}}
+
+impl {instance_trait} for {self_ty} {{
+{assoc_types}    {trait_sig} {{
+        {unsafe_open}instance.try_{symbol}({call_args}){unsafe_close}
+    }}
+}}
+"#,
+                camel = camel,
+                self_ty = self_ty,
+                instance_trait = instance_trait,
+                assoc_types = assoc_types,
+                trait_sig = trait_sig,
+                unsafe_open = unsafe_open,
+                unsafe_close = unsafe_close,
+                symbol = instance.symbol,
+                call_args = f.call_args_str(),
+            ));
+        }
+        apis.push(content);
+
+        let (generics, where_clause, dispatch) = if single {
+            (
+                format!("<{}: {}>", g.params[0], instance_trait),
+                String::new(),
+                format!("<{} as {}>::call", g.params[0], instance_trait),
+            )
+        } else {
+            let tuple = format!("({})", g.params.join(", "));
+            let bindings = g
+                .params
+                .iter()
+                .map(|p| format!("{} = {}", p, p))
+                .collect::<Vec<_>>()
+                .join(", ");
+            (
+                format!("<{}>", g.params.join(", ")),
+                format!(" where {}: {}<{}>", tuple, instance_trait, bindings),
+                format!("<{} as {}>::call", tuple, instance_trait),
+            )
+        };
+        let turbofish = format!("::<{}>", g.params.join(", "));
+        let call_args = f.call_args_str();
+        let self_call_args = if call_args.is_empty() {
+            "self".to_string()
+        } else {
+            format!("self, {}", call_args)
+        };
+        let method_docs: String = f.docs.iter().map(|doc| format!("    {}\n", doc)).collect();
+        methods.push(format!(
+            r#"
+{method_docs}    pub {unsafety}fn {name}{generics}{params}{where_clause} {{
+        match {unsafe_open}self.try_{name}{turbofish}({call_args}){unsafe_close} {{
+            Ok(res) => res,
+            Err(_) => panic!("{name} is not initialized"),
+        }}
+    }}
+
+    /// 与`{name}`相同，但在VTABLE中没有该API时返回`VdsoError::NotInitialized`，而不是panic。
+    pub {unsafety}fn try_{name}{generics}{try_params}{where_clause} {{
+        {unsafe_open}{dispatch}({self_call_args}){unsafe_close}
+    }}
+"#,
+            method_docs = method_docs,
+            unsafety = unsafety,
+            name = f.name,
+            generics = generics,
+            params = f.method_params_str(),
+            try_params = f.try_method_params_str(),
+            where_clause = where_clause,
+            unsafe_open = unsafe_open,
+            unsafe_close = unsafe_close,
+            turbofish = turbofish,
+            call_args = call_args,
+            dispatch = dispatch,
+            self_call_args = self_call_args,
+        ));
+        let docs: String = f.docs.iter().map(|doc| format!("{}\n", doc)).collect();
+        apis.push(format!(
+            r#"
+{docs}pub {unsafety}fn {name}{generics}{params}{where_clause} {{
+    {unsafe_open}VDSO_INSTANCE.{name}{turbofish}({call_args}){unsafe_close}
+}}
+
+/// 与`{name}`相同，但在VTABLE中没有该API时返回`VdsoError::NotInitialized`，而不是panic。
+pub {unsafety}fn try_{name}{generics}{try_params}{where_clause} {{
+    {unsafe_open}VDSO_INSTANCE.try_{name}{turbofish}({call_args}){unsafe_close}
+}}
+"#,
+            docs = docs,
+            unsafety = unsafety,
+            name = f.name,
+            generics = generics,
+            params = f.params_str(),
+            try_params = f.try_params_str(),
+            where_clause = where_clause,
+            unsafe_open = unsafe_open,
+            unsafe_close = unsafe_close,
+            turbofish = turbofish,
+            call_args = call_args,
+        ));
+    }
+    if !sealed_traits.is_empty() {
+        apis.push(format!(
+            "\n/// 泛型API的类型参数只能取vDSO中实例化了的类型，因此其trait不能在API库之外实现。\nmod sealed {{\n{}}}\n",
+            sealed_traits
+        ));
+    }
+
+    // 导出的方法部分：为每个类型实现一个trait，trait中的方法通过VTABLE调用为其导出的函数
+    let mut types: Vec<&str> = Vec::new();
+    for m in meta.methods.iter() {
+        if !types.contains(&m.ty.as_str()) {
+            types.push(&m.ty);
+        }
+    }
+    for ty in types {
+        let ty_methods: Vec<&ImplMethod> = meta.methods.iter().filter(|m| m.ty == ty).collect();
+        if let Some(m) = ty_methods.iter().find(|m| {
+            ty_methods
+                .iter()
+                .any(|other| format!("try_{}", other.api.name) == m.api.name)
+        }) {
+            panic!(
+                "vDSO method `{}::{}` conflicts with the `try_` version of another method, please rename it",
+                ty, m.api.name
+            );
+        }
+        let mut decls = String::new();
+        let mut impls = String::new();
+        for m in ty_methods {
+            let f = &m.api;
+            let unsafety = if f.is_unsafe { "unsafe " } else { "" };
+            let (unsafe_open, unsafe_close) = if f.is_unsafe {
+                ("unsafe { ", " }")
+            } else {
+                ("", "")
+            };
+            let (args_decl, call_args) = match &m.receiver {
+                Some(receiver) if f.args.is_empty() => (receiver.clone(), "self".to_string()),
+                Some(receiver) => (
+                    format!("{}, {}", receiver, f.args_decl_str()),
+                    format!("self, {}", f.call_args_str()),
+                ),
+                None => (f.args_decl_str(), f.call_args_str()),
+            };
+            let ret = match &f.ret {
+                Some(ret) => format!(" -> {}", ret),
+                None => String::new(),
+            };
+            let sig = format!(
+                "{}fn {}{}({}){}",
+                unsafety,
+                f.name,
+                f.generics_str(),
+                args_decl,
+                ret
+            );
+            let try_sig = format!(
+                "{}fn try_{}{}({}) -> Result<{}, VdsoError>",
+                unsafety,
+                f.name,
+                f.generics_str(),
+                args_decl,
+                f.ret.as_deref().unwrap_or("()")
+            );
+            let docs: String = f.docs.iter().map(|doc| format!("    {}\n", doc)).collect();
+            decls.push_str(&format!(
+                r#"
+{docs}    {sig};
+
+    /// 与`{name}`相同，但在VTABLE中没有该API时返回`VdsoError::NotInitialized`，而不是panic。
+    {try_sig};
+"#,
+                docs = docs,
+                sig = sig,
+                name = f.name,
+                try_sig = try_sig,
+            ));
+            impls.push_str(&format!(
+                r#"
+    {sig} {{
+        {unsafe_open}VDSO_INSTANCE.{symbol}({call_args}){unsafe_close}
+    }}
+
+    {try_sig} {{
+        {unsafe_open}VDSO_INSTANCE.try_{symbol}({call_args}){unsafe_close}
+    }}
+"#,
+                sig = sig,
+                try_sig = try_sig,
+                unsafe_open = unsafe_open,
+                unsafe_close = unsafe_close,
+                symbol = m.symbol(),
+                call_args = call_args,
+            ));
+        }
+        apis.push(format!(
+            r#"
+/// `{ty}`在vDSO中以`#[vdso_impl]`导出的方法，通过默认实例的VTABLE调用。
+pub trait {ty}Methods: Sized {{{decls}}}
+
+impl {ty}Methods for {ty} {{{impls}}}
+"#,
+            ty = ty,
+            decls = decls,
+            impls = impls,
+        ));
+    }
+
+    for method in methods.iter() {
+        fn_init_vdso_vtable_str.push_str(method);
+    }
+    fn_init_vdso_vtable_str.push_str("}\n");
+    fn_init_vdso_vtable_str.push_str(DEFAULT_INSTANCE_STR);
+    if !module_elfs.is_empty() {
+        fn_init_vdso_vtable_str.push_str(
+            r#"
+/// 为默认实例加载并初始化模块，见`VdsoInstance::require_module`。
+pub fn require_module(module: &str) -> Result<(), VdsoError> {
+    VDSO_INSTANCE.require_module(module)
+}
+"#,
+        );
+    }
+
+    // println!("apis: {:?}", apis);
+
+    let mut api_content = String::new();
+    if !meta.generic_fns.is_empty() || !meta.methods.is_empty() {
+        // 泛型API的实例名形如`max_of__u32`，为方法导出的函数名形如`Counter__bump`
+        api_content.push_str("#![allow(non_snake_case)]\n");
+    }
+    api_content.push_str(&pub_use_vdso_str);
+    api_content.push_str(&vdso_vtable_struct_str);
+    api_content.push_str(&static_vdso_vtable_str);
+    api_content.push_str(&fn_init_vdso_vtable_str);
+
+    for api in apis.iter() {
+        api_content.push_str(api);
+    }
+
+    api_content
+}
+
+/// 将`snake_case`的函数名转换为`CamelCase`的类型名。
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// 函数`f`在已映射的镜像中的地址，为`Option<usize>`类型的表达式。
+///
+/// 在运行时解析时按名称查找符号，所属版本高于协商结果`bound`的函数为`None`；否则使用构建时从`elf`中读取的偏移。
+fn addr_expr(config: &BuildConfig, elf: &xmas_elf::ElfFile, f: &ApiFn) -> String {
+    if config.resolve_at_runtime {
+        format!(
+            "if bound >= ({}, {}) {{ Some(resolve(\"{}\", \"{}\")?) }} else {{ None }}",
+            f.version.major,
+            f.version.minor,
+            f.name,
+            f.version.node_name()
+        )
+    } else {
+        let offset = symbol_offset(elf, &f.name, &f.version.node_name()).unwrap_or_else(|| {
+            panic!(
+                "Function {}@{} not found in .dynsym",
+                f.name,
+                f.version.node_name()
+            )
+        });
+        format!("Some(base as usize + 0x{:x})", offset)
+    }
+}
+
+/// 生成`resolve_module`和`merge_modules`。
+///
+/// 每个模块中除了自身的API，还有一份依赖接口的初始化函数和`init_log`，它们也从该模块中解析。
+fn resolve_module_content(
+    config: &BuildConfig,
+    module_elfs: &[(&str, xmas_elf::ElfFile)],
+    vtable_fns: &[ApiFn],
+) -> String {
+    let module_fns: Vec<&ApiFn> = vtable_fns.iter().filter(|f| f.module.is_some()).collect();
+    let shared_fns: Vec<&ApiFn> = vtable_fns
+        .iter()
+        .filter(|f| {
+            f.module.is_none() && (f.name == "init_log" || f.name.starts_with("init_vtable_"))
+        })
+        .collect();
+
+    let mut content = String::new();
+    if module_elfs.is_empty() {
+        content.push_str(
+            r#"
+/// 检查已映射的模块中的ABI指纹，并从中得到该模块的函数地址。该vDSO没有其它模块。
+unsafe fn resolve_module(_module: &str, _base: u64) -> Result<(&'static str, VdsoVTable), VdsoError> {
+    Err(VdsoError::UnknownModule)
+}
+
+/// 将`from`中属于其它模块的API写入`table`。该vDSO没有其它模块。
+fn merge_modules(_table: &mut VdsoVTable, _from: &VdsoVTable) {}
+"#,
+        );
+        return content;
+    }
+
+    content.push_str(&format!(
+        r#"
+/// 检查已映射的模块`module`中的ABI指纹，并从中得到该模块的API，以及该模块自己的依赖接口初始化函数和`init_log`的地址。
+///
+/// 返回模块名，以及存放这些函数的VTABLE，其余项为空。
+unsafe fn resolve_module(module: &str, base: u64) -> Result<(&'static str, VdsoVTable), VdsoError> {{
+    let name = VDSO_MODULE_NAMES
+        .iter()
+        .copied()
+        .find(|name| *name == module)
+        .ok_or(VdsoError::UnknownModule)?;
+    {}unsafe {{ check_fingerprints(base) }}?;
+"#,
+        if config.resolve_at_runtime { "let bound = " } else { "" }
+    ));
+    if config.resolve_at_runtime {
+        content.push_str(RESOLVE_SYMBOLS_STR);
+    }
+    content.push_str("\n    let mut table = VdsoVTable::EMPTY;\n    match name {\n");
+    for (module, elf) in module_elfs.iter() {
+        content.push_str(&format!("        \"{}\" => {{\n", module));
+        let fns = module_fns
+            .iter()
+            .filter(|f| f.module.as_deref() == Some(*module))
+            .chain(shared_fns.iter());
+        for f in fns {
+            content.push_str(&format!(
+                "            table.{} = ({}).map(|addr| unsafe {{ core::mem::transmute::<usize, {}>(addr) }});\n",
+                f.name,
+                addr_expr(config, elf, f),
+                f.fn_ptr_type()
+            ));
+        }
+        content.push_str("        }\n");
+    }
+    content.push_str(
+        r#"        _ => unreachable!(),
+    }
+    #[cfg(feature = "log")]
+    log::debug!("module {}: {:x?}", name, base);
+    Ok((name, table))
+}
+
+/// 将`from`中属于其它模块的API写入`table`，`from`中为空的项保持不变。
+fn merge_modules(table: &mut VdsoVTable, from: &VdsoVTable) {
+"#,
+    );
+    for f in module_fns.iter() {
+        content.push_str(&format!(
+            "    if from.{}.is_some() {{\n        table.{} = from.{};\n    }}\n",
+            f.name, f.name, f.name
+        ));
+    }
+    content.push_str("}\n");
+    content
+}
+
+/// `check_fingerprints`的开头部分，其余部分根据是否在运行时解析函数地址生成。
+const CHECK_FINGERPRINTS_STR: &str = r#"
+/// 检查已映射的vDSO镜像（主模块或其它模块）中的ABI指纹，返回协商出的ABI版本。
+unsafe fn check_fingerprints(base: u64) -> Result<(u32, u32), VdsoError> {
+    let fingerprints =
+        unsafe { VdsoFingerprints::from_image(base as usize) }.ok_or(VdsoError::MissingFingerprint)?;
+"#;
+
+/// `resolve_vtable`的开头部分。
+const RESOLVE_VTABLE_STR: &str = r#"
+/// 检查已映射的vDSO镜像中的ABI指纹，并从中得到主模块各个函数的地址，返回VTABLE和协商出的ABI版本。
+///
+/// 其它模块中的API在返回的VTABLE中为空。
+unsafe fn resolve_vtable(base: u64) -> Result<(VdsoVTable, (u32, u32)), VdsoError> {
+    let bound = unsafe { check_fingerprints(base) }?;
+"#;
+
+/// 在运行时解析函数地址时，读取已映射的镜像的动态符号表。
+const RESOLVE_SYMBOLS_STR: &str = r#"    let symbols = unsafe { DynSymTable::from_image(base as usize) }.ok_or(VdsoError::InvalidImage)?;
+    let resolve = |name: &'static str, version: &str| {
+        symbols.lookup_versioned(name, version).ok_or(VdsoError::SymbolNotFound(name))
+    };
+"#;
+
+/// `VdsoInstance`的定义。
+const INSTANCE_STR: &str = r#"
+/// `bound_versions`中表示未初始化的值
+const UNBOUND: u64 = u64::MAX;
+
+/// 一份vDSO实例，持有自己的VTABLE，以及在内核中加载的vDSO和vVAR。
+///
+/// 同一地址空间中可以存在多个实例，例如同时运行新旧两个版本的vDSO，或为每个容器加载一份vDSO。
+/// 各个实例的vVAR和私有数据互相独立。
+///
+/// 本库中的自由函数（如`map_so`、`init_vdso_vtable`和各个API）均作用于默认实例`VDSO_INSTANCE`。
+pub struct VdsoInstance {
+    /// 内核中加载的vDSO，在首次调用`map_so`时初始化。
+    pub(crate) kernel: Mutex<Option<KernelVdso>>,
+    /// VTABLE的两份副本。初始化时先写入未使用的一份，再切换`active`，
+    /// 使替换vDSO时，正在调用API的代码不会读到只更新了一半的VTABLE。
+    vtables: [AtomicVTable; 2],
+    /// 各份VTABLE初始化时与已映射的vDSO协商出的ABI版本，高32位为主版本号，低32位为次版本号。
+    bound_versions: [AtomicU64; 2],
+    /// 当前使用的VTABLE副本的下标。
+    active: AtomicUsize,
+    /// 串行化对VTABLE的写入。
+    init_lock: Mutex<()>,
+    /// 已初始化的其它模块，同时串行化模块的加载。
+    modules: Mutex<ModuleState>,
+}
+
+impl Default for VdsoInstance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VdsoInstance {
+    /// 创建一个尚未加载vDSO、也未初始化VTABLE的实例。
+    pub const fn new() -> Self {
+        Self {
+            kernel: Mutex::new(None),
+            vtables: [AtomicVTable::EMPTY, AtomicVTable::EMPTY],
+            bound_versions: [AtomicU64::new(UNBOUND), AtomicU64::new(UNBOUND)],
+            active: AtomicUsize::new(0),
+            init_lock: Mutex::new(()),
+            modules: Mutex::new(ModuleState::new()),
+        }
+    }
+
+    /// 当前使用的VTABLE副本。
+    fn active_table(&self) -> &AtomicVTable {
+        &self.vtables[self.active.load(Ordering::Acquire)]
+    }
+
+    /// 返回当前VTABLE的一份拷贝。
+    pub fn vtable(&self) -> VdsoVTable {
+        load_vtable(self.active_table())
+    }
+
+    /// 返回初始化时与已映射的vDSO协商出的ABI版本，未初始化时返回`None`。
+    ///
+    /// 所属版本高于该版本的API在VTABLE中为空，调用时会panic。
+    pub fn abi_version(&self) -> Option<(u32, u32)> {
+        let active = self.active.load(Ordering::Acquire);
+        match self.bound_versions[active].load(Ordering::Relaxed) {
+            UNBOUND => None,
+            version => Some(((version >> 32) as u32, version as u32)),
+        }
+    }
+}
+"#;
+
+/// `VdsoInstance`中初始化相关的方法。
+const INSTANCE_INIT_STR: &str = r#"
+impl VdsoInstance {
+    /// 在自身不加载vDSO，而是已经映射了vDSO的地址空间（通常是用户进程）中调用，传入vDSO的首地址以初始化VTABLE。
+    /// 
+    /// 在调用该实例的其余API前，需先调用此函数。
+    ///
+    /// 初始化前会检查vDSO镜像中的ABI指纹与`VDSO_FINGERPRINTS`是否一致。
+    /// 若不一致，说明该API库与已映射的so文件不是由同一份vDSO库构建的，此时不初始化VTABLE并返回错误。
+    ///
+    /// 若在运行时解析函数地址，则会与vDSO协商双方都支持的最新ABI版本，只比较该版本的指纹。
+    /// 所属版本高于协商结果的API不会被初始化，见`abi_version`。
+    ///
+    /// 该函数可以在多个核上并发调用，也可以重复调用：各次初始化依次生效，最后完成的一次决定VTABLE的内容。
+    /// 初始化完成后，在任意核上调用API都能看到完整的VTABLE。
+    /// 已通过`init_module`初始化的其它模块的API会被保留。
+    ///
+    /// # Safety
+    ///
+    /// `base`必须为已映射的vDSO镜像的首地址。
+    pub unsafe fn init_vdso_vtable(&self, base: u64) -> Result<(), VdsoError> {
+        let (mut table, bound) = unsafe { resolve_vtable(base) }?;
+        let _guard = self.init_lock.lock();
+        let active = self.active.load(Ordering::Relaxed);
+        merge_modules(&mut table, &load_vtable(&self.vtables[active]));
+        // 写入未使用的一份VTABLE，再以Release切换`active`，使读到新下标的核也能读到新的VTABLE
+        let next = active ^ 1;
+        store_vtable(&self.vtables[next], &table);
+        self.bound_versions[next].store(((bound.0 as u64) << 32) | bound.1 as u64, Ordering::Relaxed);
+        self.active.store(next, Ordering::Release);
+        Ok(())
+    }
+
+    /// 在加载vDSO的地址空间（通常是内核）中调用，同时加载vDSO和初始化VTABLE。
+    /// 
+    /// 在调用该实例的其余API前，需先调用此函数。
+    ///
+    /// 若so文件的ABI指纹与API库不一致，则返回错误，详见`init_vdso_vtable`。
+    pub fn load_and_init(&self, vspace: usize) -> Result<(), VdsoError> {
+        let vdso = self.map_so(vspace);
+        unsafe { self.init_vdso_vtable(vdso as _) }?;
+        self.init_vdso_log();
+        Ok(())
+    }
+"#;
+
+/// `VdsoInstance`中初始化其它模块的方法，`{module_setup}`处为在该模块中注册依赖接口、初始化日志的代码。
+const MODULE_INIT_STR: &str = r#"
+    /// 传入已映射的模块`module`的首地址，将该模块的API写入VTABLE。
+    ///
+    /// 与`init_vdso_vtable`相同，初始化前会检查模块中的ABI指纹。
+    /// 初始化后，会在该模块中注册已通过`init_vtable_*`注册的依赖接口，并初始化该模块的日志。
+    /// 重复初始化同一模块时，后一次覆盖前一次；若再次传入已初始化过的镜像，则不会在其中重复注册依赖接口和初始化日志。
+    ///
+    /// 若该API库中没有名为`module`的模块（见`VDSO_MODULE_NAMES`），则返回`VdsoError::UnknownModule`。
+    ///
+    /// # Safety
+    ///
+    /// `base`必须为已映射的模块`module`镜像的首地址。
+    pub unsafe fn init_module(&self, module: &str, base: u64) -> Result<(), VdsoError> {
+        let mut modules = self.modules.lock();
+        unsafe { self.init_module_locked(&mut modules, module, base) }
+    }
 
-    // api部分
-    for (name, args) in fns.iter() {
-        let re = regex::Regex::new(r#"\(([a-zA-Z0-9_:]?.*)\)"#).unwrap();
-        let mut fn_args = String::new();
-        for (_, [ident_ty]) in re.captures_iter(args).map(|c| c.extract()) {
-            // println!("{}: {}", name, args);
-            let ident_str: Vec<&str> = ident_ty
-                .split(",")
-                .map(|s| {
-                    let idx = s.find(":");
-                    if let Some(idx) = idx {
-                        let ident = s[..idx].trim();
-                        ident
-                    } else {
-                        ""
-                    }
-                })
-                .collect();
-            for ident in ident_str.iter() {
-                if ident.len() > 0 {
-                    fn_args.push_str(&format!("{}, ", ident));
-                }
-            }
-            fn_args = fn_args.trim_end_matches(", ").to_string();
-            // println!("{:?}", fn_args);
+    /// 在持有模块锁时初始化模块`module`。
+    unsafe fn init_module_locked(&self, modules: &mut ModuleState, module: &str, base: u64) -> Result<(), VdsoError> {
+        let (name, own) = unsafe { resolve_module(module, base) }?;
+        {
+            let _guard = self.init_lock.lock();
+            let active = self.active.load(Ordering::Relaxed);
+            let mut table = load_vtable(&self.vtables[active]);
+            merge_modules(&mut table, &own);
+            let next = active ^ 1;
+            store_vtable(&self.vtables[next], &table);
+            let bound = self.bound_versions[active].load(Ordering::Relaxed);
+            self.bound_versions[next].store(bound, Ordering::Relaxed);
+            self.active.store(next, Ordering::Release);
         }
+{module_setup}        modules.loaded.retain(|(loaded, _, _)| *loaded != name);
+        modules.loaded.push((name, base, own));
+        Ok(())
+    }
+"#;
 
-        apis.push(format!(
-            r#"
-pub fn {}{} {{
-    if let Some(f) = unsafe {{ VDSO_VTABLE.{} }} {{
-        #[cfg(feature = "log")]
-        log::debug!("Calling {} at 0x{{:x}}.", f as *const () as usize);
-        let res = f({});
-        #[cfg(feature = "log")]
-        log::debug!("Returned from {}.");
-        res
-    }} else {{
-        panic!("{} is not initialized")
-    }}
-}}
-"#,
-            name, args, name, name, fn_args, name, name
-        ));
+/// `VdsoInstance::require_module`，只在存在其它模块时生成。
+const REQUIRE_MODULE_STR: &str = r#"
+    /// 加载并初始化模块`module`：若该模块尚未初始化，则通过`ModuleIf::load_module`请求在当前地址空间中映射该模块，
+    /// 再以返回的首地址调用`init_module`。
+    ///
+    /// 主模块初始化后，调用某个模块的API时，若该模块尚未初始化，会自动调用此函数；
+    /// 程序也可以在启动时为需要的模块主动调用。
+    /// 加载期间持有该实例的模块锁，因此同时调用该函数的其它核会等待加载完成，而不会重复加载。
+    ///
+    /// 若`ModuleIf::load_module`未能映射该模块，则返回`VdsoError::ModuleUnavailable`。
+    pub fn require_module(&self, module: &str) -> Result<(), VdsoError> {
+        let name = VDSO_MODULE_NAMES
+            .iter()
+            .copied()
+            .find(|name| *name == module)
+            .ok_or(VdsoError::UnknownModule)?;
+        let mut modules = self.modules.lock();
+        if modules.loaded.iter().any(|(loaded, _, _)| *loaded == name) {
+            return Ok(());
+        }
+        let base = crate::loader::request_module(self, name).ok_or(VdsoError::ModuleUnavailable(name))?;
+        unsafe { self.init_module_locked(&mut modules, name, base as u64) }
     }
+"#;
 
-    // trait的初始化api部分
-    for (name, fns_name) in traits.iter() {
-        let init_fn_name = format!("init_vtable_{}", name);
+/// 作用于默认实例的自由函数。
+const DEFAULT_INSTANCE_STR: &str = r#"
+/// 默认的vDSO实例，本库中的自由函数均作用于该实例。
+pub static VDSO_INSTANCE: VdsoInstance = VdsoInstance::new();
 
-        let fn_args = fns_name
-            .iter()
-            .map(|fn_name| format!("T::{} as usize", fn_name))
-            .collect::<Vec<_>>()
-            .join(", ");
+/// 返回默认实例当前VTABLE的一份拷贝，见`VdsoInstance::vtable`。
+pub fn vdso_vtable() -> VdsoVTable {
+    VDSO_INSTANCE.vtable()
+}
 
-        apis.push(format!(
-            r#"
-pub fn {}<T:{}>() {{
-    if let Some(f) = unsafe {{ VDSO_VTABLE.{} }} {{
-        #[cfg(feature = "log")]
-        log::debug!("Calling {} at 0x{{:x}}.", f as *const () as usize);
-        let res = f({});
-        #[cfg(feature = "log")]
-        log::debug!("Returned from {}.");
-        res
-    }} else {{
-        panic!("{} is not initialized")
-    }}
-}}
-"#,
-            init_fn_name, name, init_fn_name, init_fn_name, fn_args, init_fn_name, init_fn_name
-        ));
-    }
+/// 返回默认实例协商出的ABI版本，见`VdsoInstance::abi_version`。
+pub fn vdso_abi_version() -> Option<(u32, u32)> {
+    VDSO_INSTANCE.abi_version()
+}
 
-    // println!("apis: {:?}", apis);
+/// 初始化默认实例的VTABLE，见`VdsoInstance::init_vdso_vtable`。
+///
+/// # Safety
+///
+/// 见`VdsoInstance::init_vdso_vtable`。
+pub unsafe fn init_vdso_vtable(base: u64) -> Result<(), VdsoError> {
+    unsafe { VDSO_INSTANCE.init_vdso_vtable(base) }
+}
 
-    let mut api_content = String::new();
-    api_content.push_str(&pub_use_vdso_str);
-    api_content.push_str(&vdso_vtable_struct_str);
-    api_content.push_str(&static_vdso_vtable_str);
-    api_content.push_str(&fn_init_vdso_vtable_str);
+/// 为默认实例加载vDSO并初始化VTABLE，见`VdsoInstance::load_and_init`。
+pub fn load_and_init(vspace: usize) -> Result<(), VdsoError> {
+    VDSO_INSTANCE.load_and_init(vspace)
+}
 
-    for api in apis.iter() {
-        api_content.push_str(api);
-    }
+/// 为默认实例初始化已映射的模块，见`VdsoInstance::init_module`。
+///
+/// # Safety
+///
+/// 见`VdsoInstance::init_module`。
+pub unsafe fn init_module(module: &str, base: u64) -> Result<(), VdsoError> {
+    unsafe { VDSO_INSTANCE.init_module(module, base) }
+}
+"#;
 
-    api_content
+/// `VdsoInstance`自身的方法名，API函数不能与之重名。
+const INSTANCE_METHODS: &[&str] = &[
+    "new",
+    "vtable",
+    "active_table",
+    "abi_version",
+    "init_vdso_vtable",
+    "load_and_init",
+    "init_vdso_log",
+    "map_so",
+    "load_into_kernel",
+    "unmap_so",
+    "clone_so",
+    "replace_so",
+    "init_module",
+    "init_module_locked",
+    "require_module",
+    "map_module",
+];
+
+const ERROR_RS: &str = r#"//! API库的错误类型。
+
+use core::fmt;
+
+/// 初始化或调用vDSO时可能发生的错误。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VdsoError {
+    /// 已映射的vDSO镜像中没有ABI指纹，可能不是由`build_vdso`构建的so文件。
+    MissingFingerprint,
+    /// 已映射的vDSO与API库的ABI指纹不一致，二者不是由同一份vDSO库构建的。
+    FingerprintMismatch {
+        /// API库中记录的指纹
+        expected: u64,
+        /// vDSO镜像中的指纹
+        found: u64,
+    },
+    /// 已映射的vDSO镜像不是合法的ELF文件，或缺少动态符号表、字符串表或哈希表。
+    InvalidImage,
+    /// 在已映射的vDSO镜像中找不到该名称的导出符号。
+    SymbolNotFound(&'static str),
+    /// 已映射的vDSO不支持API库所知的任何ABI版本。
+    ///
+    /// 未启用`resolve_at_runtime`时，API库只能使用构建时的so文件中的函数偏移，
+    /// 因此已映射的vDSO的最新版本与API库的不同时，也会返回该错误。
+    NoCommonVersion,
+    /// 调用的API在VTABLE中为空：VTABLE尚未初始化，或已映射的vDSO不提供该API所属的ABI版本。
+    NotInitialized(&'static str),
+    /// 内核尚未通过`map_so`加载vDSO。
+    NotLoaded,
+    /// 内核已经加载过vDSO，不能再调用`load_into_kernel`。
+    AlreadyLoaded,
+    /// 传入`load_into_kernel`的区域没有对齐到页，或小于`kernel_region_size()`。
+    InvalidRegion,
+    /// 该实例没有在指定的地址空间中映射vDSO。
+    NotMapped,
+    /// 替换vDSO时，新旧so文件中`VvarData`的布局不同，且没有提供迁移函数；或已加载了其它模块，无法迁移vVAR。
+    VvarLayoutMismatch,
+    /// API库中没有该名称的模块。
+    UnknownModule,
+    /// `ModuleIf::load_module`未能映射该模块。
+    ModuleUnavailable(&'static str),
 }
 
-const INIT_VDSO_VTABLE_STR: &str = r#"
-/// 在自身不加载vDSO，而是已经映射了vDSO的地址空间（通常是用户进程）中调用，传入vDSO的首地址以初始化VTABLE。
-/// 
-/// 在调用该库的其余API前，需先调用此函数。
-pub unsafe fn init_vdso_vtable(base: u64) {
+impl fmt::Display for VdsoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingFingerprint => write!(f, "vDSO image has no ABI fingerprint"),
+            Self::FingerprintMismatch { expected, found } => write!(
+                f,
+                "vDSO ABI fingerprint mismatch: expected {:#018x}, found {:#018x}",
+                expected, found
+            ),
+            Self::InvalidImage => write!(f, "invalid vDSO image"),
+            Self::SymbolNotFound(name) => write!(f, "vDSO symbol `{}` not found", name),
+            Self::NoCommonVersion => write!(f, "vDSO does not support any known ABI version"),
+            Self::NotInitialized(name) => write!(f, "vDSO API `{}` is not initialized", name),
+            Self::NotLoaded => write!(f, "vDSO is not loaded"),
+            Self::AlreadyLoaded => write!(f, "vDSO is already loaded"),
+            Self::InvalidRegion => write!(f, "reserved region is misaligned or too small for the vDSO"),
+            Self::NotMapped => write!(f, "vDSO is not mapped in this address space"),
+            Self::VvarLayoutMismatch => write!(f, "vVAR layout of the new vDSO is incompatible"),
+            Self::UnknownModule => write!(f, "unknown vDSO module"),
+            Self::ModuleUnavailable(name) => write!(f, "vDSO module `{}` cannot be loaded", name),
+        }
+    }
+}
 "#;
 
-fn loader_rs_content(config: &BuildConfig) -> String {
+fn loader_rs_content(config: &BuildConfig, meta: &VdsoMeta, modules: &[String]) -> String {
     let use_content = format!(
         r#"use alloc::string::ToString;
 use core::str::from_utf8;
 use crate_interface::{{call_interface, def_interface}};
 use include_bytes_aligned::include_bytes_aligned;
 pub use page_table_entry::MappingFlags;
-use {}::VvarData;
+use {}::{{VvarData, VvarKernelData}};
 use xmas_elf::program::SegmentData;
 use alloc::vec::Vec;
-use core::sync::atomic::{{AtomicPtr, Ordering}};
-use lazyinit::LazyInit;
-"#,
-        config.package_name
+use crate::api::{{VdsoInstance, VDSO_INSTANCE}};
+use crate::error::VdsoError;
+{}"#,
+        config.package_name,
+        if config.resolve_at_runtime {
+            "use crate::meta::VdsoMeta;\n"
+        } else {
+            ""
+        }
     );
 
     //     let interface_content = String::from(
@@ -523,14 +1733,13 @@ use lazyinit::LazyInit;
 /// 
 ///                                  ➡          ➡          `MemIf::map`将`PhysPagePtr`重新转化为`PhysPage`，并加入页表
 /// 
-/// 保证每个指针的生命周期从`MemIf::ppage_alloc`开始到`MemIf::map`结束，
-/// 且每个指针只会`map`一次，`map`之后即不再使用该指针。
+/// 保证每个指针的生命周期从`MemIf::ppage_alloc`或`MemIf::ppage_clone`开始，到`MemIf::map`或`MemIf::ppage_free`结束，
+/// 且每个指针只会`map`一次，`map`之后即不再使用该指针。`map`后的物理页在`MemIf::unmap`时释放。
 /// 
 /// 为了实现物理页的共享，内核的vdso加载到的物理页指针需要`clone`后在本库中暂存一份。
 /// 下次加载共享的物理页后，将暂存的指针再`clone`一次，并对`clone`后的指针调用`map`。
 /// 这样保证了库中暂存的指针仍有效。
-/// 虽然内核的物理页指针会被暂存一份，导致无法释放，但在内核加载的vdso页面本来就在内核关闭时才能释放，
-/// 因此没有问题。
+/// 内核暂存的物理页指针在内核加载的vdso被`replace_so`替换、且不再被任何地址空间映射时，通过`MemIf::ppage_free`释放。
 /// 
 /// 加载用户vdso时新分配的物理页则没有`clone`和暂存的过程，在`alloc`后即调用`map`。
 /// 因此不会影响`PhysPage`的生命周期管理。
@@ -544,6 +1753,8 @@ pub type PhysPagePtr = usize;
 ///     1. 在内核调用`map_so`（需保证是首次调用），加载和重定位so文件
 ///     `map_so`的行为：无论共享数据、代码还是私有数据，均会分配物理页并加载。
 ///     2. 在内核调用`init_vdso_vtable`，初始化内核空间中的`VDSO_VTABLE`。
+/// - 若内核地址空间中无法通过`valloc`分配虚存区域，可以改为预留一块已映射的区域，并调用`load_into_kernel`加载和初始化。
+///     `load_into_kernel`的行为：不分配虚存区域和物理页，而是将so文件加载到预留的区域中，并通过`ppage_of`获取其物理页，供后续的`map_so`共享。
 /// - 用户态初始化：
 ///     1. 在内核调用`map_so`（需保证是后续调用），加载和重定位so文件。
 ///     `map_so`的行为：对于共享数据和代码会映射到已分配的物理页；对于私有数据会重新分配物理页并加载。
@@ -556,6 +1767,11 @@ pub trait MemIf {
     /// 要求返回的地址也为config.page_size的整数倍。
     fn valloc(vspace: usize, size: usize) -> *mut u8;
 
+    /// 在地址空间中的指定位置分配用于vDSO和vVAR的虚存区域（不需同时分配物理页面）。
+    ///
+    /// 用于fork时使子进程中的vDSO与父进程位于相同的地址，保证该区域在`vspace`中未被占用。
+    fn valloc_at(vspace: usize, vaddr: *mut u8, size: usize);
+
     /// 分配多块用于vDSO和vVAR的连续物理页，返回`PhysPagePtr`。
     /// 
     /// 保证size为build_vdso传入的config.page_size的整数倍。
@@ -563,6 +1779,13 @@ pub trait MemIf {
     /// 若需要实现vDSO和vVAR在多地址空间的共享，则需要在分配时使这块空间可被共享（即，可被多次`map`）。
     fn ppage_alloc(size: usize) -> PhysPagePtr;
 
+    /// 获取`vspace`中从`vaddr`开始、大小为`size`的已映射区域所使用的物理页，返回`PhysPagePtr`。
+    ///
+    /// 用于`load_into_kernel`：返回的指针与`ppage_alloc`返回的指针相同，会被本库暂存、`clone`后交给`map`，并在不再使用时通过`ppage_free`释放。
+    ///
+    /// 保证vaddr对齐到build_vdso传入的config.page_size；size为config.page_size的整数倍。
+    fn ppage_of(vspace: usize, vaddr: *mut u8, size: usize) -> PhysPagePtr;
+
     /// 从`alloc`返回的虚存区域中，映射其中一块到某个物理页面并设置权限。
     /// 
     /// 被映射的物理页面可能和其它地址空间共享，也可能由这个地址空间独占。（由`shared`指定）
@@ -587,6 +1810,21 @@ pub trait MemIf {
     /// 
     /// 如果物理页不使用RAII管理，则可以直接返回参数。
     fn ppage_clone(ppage: PhysPagePtr) -> PhysPagePtr;
+
+    /// 释放一个没有被`map`的物理页指针，例如库中暂存的副本。
+    ///
+    /// 如果物理页使用RAII管理，则需将其转化回`PhysPage`并释放。
+    fn ppage_free(ppage: PhysPagePtr);
+
+    /// 解除`vspace`中从`vaddr`开始、大小为`size`的一块映射，并释放`map`时传入的物理页。
+    ///
+    /// 若物理页与其它地址空间共享，则只释放该地址空间持有的引用。
+    ///
+    /// 保证vaddr对齐到build_vdso传入的config.page_size，且与某次`map`的参数相同。
+    fn unmap(vspace: usize, vaddr: *mut u8, size: usize);
+
+    /// 释放`valloc`分配的虚存区域。调用前，区域中的映射均已通过`unmap`解除。
+    fn vfree(vspace: usize, vaddr: *mut u8, size: usize);
 }
 "#,
     );
@@ -594,13 +1832,60 @@ pub trait MemIf {
     let const_content = format!(
         r#"
 const PAGES_SIZE: usize = {};
-const VDSO: &[u8] = include_bytes_aligned!(8, "../../{}.so");
-const VDSO_SIZE: usize = ((VDSO.len() + PAGES_SIZE - 1) & (!(PAGES_SIZE - 1))) + PAGES_SIZE; // 额外加了一页，用于bss段等未出现在文件中的段
-const VVAR_SIZE: usize = (core::mem::size_of::<VvarData>() + PAGES_SIZE - 1) & (!(PAGES_SIZE - 1));
+pub(crate) const VDSO: &[u8] = include_bytes_aligned!(8, "../../{}.so");
+/// vVAR中用户进程只读的`VvarKernelData`所占的大小，位于vVAR的开头
+const VVAR_KERNEL_SIZE: usize = (core::mem::size_of::<VvarKernelData>() + PAGES_SIZE - 1) & (!(PAGES_SIZE - 1));
+const VVAR_SIZE: usize = VVAR_KERNEL_SIZE + ((core::mem::size_of::<VvarData>() + PAGES_SIZE - 1) & (!(PAGES_SIZE - 1)));
+
+/// 除主模块外的各个模块的名称和so文件。
+pub(crate) const VDSO_MODULES: &[(&str, &[u8])] = &[
+{}];
+
+// 检查VvarData和VvarKernelData的布局与so文件中记录的一致
+const _: () = assert!(core::mem::size_of::<VvarData>() == {});
+const _: () = assert!(core::mem::align_of::<VvarData>() == {});
+const _: () = assert!(core::mem::size_of::<VvarKernelData>() == {});
+const _: () = assert!(core::mem::align_of::<VvarKernelData>() == {});
 "#,
-        config.page_size, config.so_name
+        meta.page_size,
+        config.so_name,
+        modules
+            .iter()
+            .map(|module| format!(
+                "    (\"{}\", include_bytes_aligned!(8, \"../../{}.so\")),\n",
+                module,
+                so_name(config, Some(module))
+            ))
+            .collect::<String>(),
+        meta.vvar_size,
+        meta.vvar_align,
+        meta.vvar_kernel_size,
+        meta.vvar_kernel_align
     );
 
+    // 按需加载模块的接口，只在存在其它模块时生成
+    let module_if_content = if modules.is_empty() {
+        ""
+    } else {
+        r#"
+/// 按需加载模块时使用的接口，在使用API库的地址空间中实现。
+#[def_interface]
+pub trait ModuleIf {
+    /// 在当前地址空间中映射`instance`的模块`module`，返回该模块的首地址；无法映射时返回`None`。
+    ///
+    /// 调用某个模块的API时，若该模块尚未初始化，API库会调用此函数（见`VdsoInstance::require_module`）。
+    /// 在用户进程中，通常通过系统调用请求内核以该进程的地址空间调用`map_module`；
+    /// 在内核中，可以直接以内核地址空间调用`map_module`。
+    fn load_module(instance: &VdsoInstance, module: &str) -> Option<*mut u8>;
+}
+
+/// 通过`ModuleIf::load_module`请求映射模块。
+pub(crate) fn request_module(instance: &VdsoInstance, module: &str) -> Option<*mut u8> {
+    call_interface!(ModuleIf::load_module(instance, module))
+}
+"#
+    };
+
     //     let load_so_content = String::from(
     //         r#"
     // static KBASE: AtomicPtr<u8> = AtomicPtr::new(core::ptr::null_mut());
@@ -738,15 +2023,411 @@ const VVAR_SIZE: usize = (core::mem::size_of::<VvarData>() + PAGES_SIZE - 1) & (
     //     );
     let map_so_content = String::from(
         r#"
-/// 内核虚拟地址、内核物理页、大小、flags
-static KERNEL_VDSO_REGIONS: LazyInit<Vec<(usize, PhysPagePtr, usize, MappingFlags)>> = LazyInit::new();
+/// 一块已映射的区域：内核虚拟地址、内核物理页、大小、flags
+type Region = (usize, PhysPagePtr, usize, MappingFlags);
+
+/// 内核中加载的vDSO。
+pub(crate) struct KernelVdso {
+    /// 内核地址空间
+    vspace: usize,
+    /// 当前使用的so文件
+    image: &'static [u8],
+{vvar_layout_field}    /// 当前so文件的代数，每次`replace_so`成功后加一
+    generation: usize,
+    /// 当前so文件的各个区域，第一项为vVAR
+    regions: Vec<Region>,
+    /// 被`replace_so`替换下来的旧so文件的代数和区域。
+    ///
+    /// 其它地址空间可能仍在使用旧的代码，因此只有在映射了旧so文件的地址空间都调用`unmap_so`后才会释放。
+    retired: Vec<(usize, Vec<Region>)>,
+    /// 映射到其它地址空间的vDSO
+    mappings: Vec<Mapping>,
+    /// 已加载到内核中的其它模块的名称和区域，第一项为与主模块共享的vVAR
+    modules: Vec<(&'static str, Vec<Region>)>,
+    /// 第0代so文件是否加载在`load_into_kernel`传入的预留区域中。预留的区域由内核管理，不会被解除映射
+    reserved: bool,
+}
 
-/// - 第一次调用：加载并映射vdso。本次调用中，vspace需为当前地址空间。
-/// - 后续调用：将已加载的vdso映射到另一个地址空间。
-/// 
-/// 该函数的返回值为本次映射的vdso首地址（vspace中的虚拟地址）。
-pub fn map_so(vspace: usize) -> *mut u8 {
+impl KernelVdso {
+    /// 以首次加载到内核中的so文件的区域创建，并初始化vVAR。
+    fn new(vspace: usize, regions: Vec<Region>, reserved: bool) -> Self {
+        // 初始化vvar，只在首次加载时写入数据，后续调用时内核加载的vVAR页面已经包含了正确的数据。
+        // vVAR中可能包含较大的对象（如`VvarArena`），因此先清零，再原地初始化各项，而不在栈上构造整个结构体
+        unsafe {
+            core::ptr::write_bytes(regions[0].0 as *mut u8, 0, VVAR_SIZE);
+            VvarKernelData::init_in_place(regions[0].0 as *mut VvarKernelData);
+            VvarData::init_in_place((regions[0].0 + VVAR_KERNEL_SIZE) as *mut VvarData);
+        }
+        Self {
+            vspace,
+            image: VDSO,
+{vvar_layout_init}            generation: 0,
+            regions,
+            retired: Vec::new(),
+            mappings: Vec::new(),
+            modules: Vec::new(),
+            reserved,
+        }
+    }
+
+    /// 第`generation`代so文件在内核中的区域。
+    fn regions_of(&self, generation: usize) -> &[Region] {
+        if generation == self.generation {
+            &self.regions
+        } else {
+            &self.retired.iter().find(|(g, _)| *g == generation).unwrap().1
+        }
+    }
+}
+
+/// `map_so`或`map_module`映射到其它地址空间的一份vDSO。
+struct Mapping {
+    /// 地址空间
+    vspace: usize,
+    /// 映射的模块，`None`表示主模块
+    module: Option<&'static str>,
+    /// 映射的so文件的代数，只对主模块有意义
+    generation: usize,
+    /// 各个区域，第一项为vVAR。其中的物理页已在映射时交给`MemIf::map`，不再有效。
+    regions: Vec<Region>,
+}
+
+impl Mapping {
+    /// vDSO的首地址
+    fn base(&self) -> usize {
+        self.regions[0].0 + self.regions[0].2
+    }
+}
+
+/// vVAR区域的来源。
+enum VvarPages {
+    /// 分配指定大小的新物理页
+    Alloc(usize),
+    /// 映射已有的物理页及其大小
+    Shared(PhysPagePtr, usize),
+}
+
+impl VdsoInstance {
+    /// - 第一次调用：加载并映射vdso。本次调用中，vspace需为当前地址空间。
+    /// - 后续调用：将已加载的vdso映射到另一个地址空间。
+    /// 
+    /// 若调用过`replace_so`，则后续调用映射的是替换后的so文件。
+    /// 
+    /// 该函数的返回值为本次映射的vdso首地址（vspace中的虚拟地址）。
+    pub fn map_so(&self, vspace: usize) -> *mut u8 {
+        let mut kernel = self.kernel.lock();
+        match kernel.as_mut() {
+            None => {
+                // 首次调用，分配物理页并加载vVAR和vDSO
+                let (vdso, regions) = map_image(vspace, VDSO, VvarPages::Alloc(VVAR_SIZE), None, None);
+                *kernel = Some(KernelVdso::new(vspace, regions, false));
+                vdso
+            }
+            Some(kernel) => {
+                // 后续调用，映射已加载的vVAR和vDSO
+                let (_, vvar_ppage, vvar_size, _) = kernel.regions[0];
+                let (vdso, regions) = map_image(
+                    vspace,
+                    kernel.image,
+                    VvarPages::Shared(vvar_ppage, vvar_size),
+                    Some(&kernel.regions),
+                    None,
+                );
+                kernel.mappings.push(Mapping {
+                    vspace,
+                    module: None,
+                    generation: kernel.generation,
+                    regions,
+                });
+                vdso
+            }
+        }
+    }
+
+    /// 将vDSO加载到内核中预留的区域`region`中，并初始化VTABLE，返回vDSO的首地址。
+    ///
+    /// 用于内核地址空间中没有可供`MemIf::valloc`分配的空闲区域的情况，代替首次调用`map_so`或`load_and_init`。
+    /// `region`需为`vspace`（内核地址空间）中已映射为可读可写的区域，首地址对齐到页，大小至少为`kernel_region_size()`。
+    /// 本库不会为其分配虚存区域和物理页，而是通过`MemIf::ppage_of`获取其中的物理页，
+    /// 后续的`map_so`会将代码段、只读数据段和vVAR映射到这些物理页。各个段的权限会在加载后通过`MemIf::change_protect`设置。
+    /// 该区域由内核管理，本库不会解除其映射。
+    ///
+    /// 其它模块仍在首次调用`map_module`时通过`MemIf::valloc`加载到内核中。
+    ///
+    /// 若已经加载过vDSO，则返回`VdsoError::AlreadyLoaded`；若区域未对齐到页或小于`kernel_region_size()`，则返回`VdsoError::InvalidRegion`。
+    pub fn load_into_kernel(&self, vspace: usize, region: &'static mut [u8]) -> Result<*mut u8, VdsoError> {
+        let vdso = {
+            let mut kernel = self.kernel.lock();
+            if kernel.is_some() {
+                return Err(VdsoError::AlreadyLoaded);
+            }
+            let base = region.as_mut_ptr();
+            if base as usize & (PAGES_SIZE - 1) != 0 || region.len() < kernel_region_size() {
+                return Err(VdsoError::InvalidRegion);
+            }
+            let (vdso, regions) = map_image(vspace, VDSO, VvarPages::Alloc(VVAR_SIZE), None, Some(base));
+            *kernel = Some(KernelVdso::new(vspace, regions, true));
+            vdso
+        };
+        unsafe { self.init_vdso_vtable(vdso as _) }?;
+        self.init_vdso_log();
+        Ok(vdso)
+    }
+
+    /// 将模块`module`映射到`vspace`中，返回该模块的首地址（vspace中的虚拟地址）。
+    ///
+    /// 模块首次被映射时，会先加载到内核中。模块与主模块共享同一份vVAR，代码段和只读数据段在各个地址空间之间共享。
+    /// 若`vspace`为内核地址空间，则返回模块在内核中的首地址，不会重复映射。
+    ///
+    /// 映射后，需要在`vspace`中以返回的首地址调用`init_module`（或由`require_module`自动调用）。
+    /// 与`map_so`的映射相同，模块的映射可以通过`unmap_so`解除，并会被`clone_so`复制。
+    ///
+    /// 需先调用`map_so`加载主模块，否则返回`VdsoError::NotLoaded`；若没有名为`module`的模块，则返回`VdsoError::UnknownModule`。
+    pub fn map_module(&self, vspace: usize, module: &str) -> Result<*mut u8, VdsoError> {
+        let &(name, image) = VDSO_MODULES
+            .iter()
+            .find(|(name, _)| *name == module)
+            .ok_or(VdsoError::UnknownModule)?;
+        let mut guard = self.kernel.lock();
+        let kernel = guard.as_mut().ok_or(VdsoError::NotLoaded)?;
+        let (_, vvar_ppage, vvar_size, _) = kernel.regions[0];
+        let index = match kernel.modules.iter().position(|(loaded, _)| *loaded == name) {
+            Some(index) => index,
+            None => {
+                #[cfg(feature = "log")]
+                log::info!("loading vDSO module {}...", name);
+                let (_, regions) = map_image(kernel.vspace, image, VvarPages::Shared(vvar_ppage, vvar_size), None, None);
+                kernel.modules.push((name, regions));
+                kernel.modules.len() - 1
+            }
+        };
+        let kernel_regions = &kernel.modules[index].1;
+        if vspace == kernel.vspace {
+            return Ok((kernel_regions[0].0 + kernel_regions[0].2) as *mut u8);
+        }
+        let (vdso, regions) = map_image(
+            vspace,
+            image,
+            VvarPages::Shared(vvar_ppage, vvar_size),
+            Some(kernel_regions),
+            None,
+        );
+        kernel.mappings.push(Mapping {
+            vspace,
+            module: Some(name),
+            generation: kernel.generation,
+            regions,
+        });
+        Ok(vdso)
+    }
+
+    /// 解除`map_so`在`vspace`中首地址为`vdso`的映射，通常在进程退出时调用。
+    ///
+    /// 所有区域都会通过`MemIf::unmap`解除映射：私有数据的物理页随之释放，共享的代码段、只读数据段和vVAR的物理页只减少一个引用。
+    /// 之后通过`MemIf::vfree`释放`valloc`分配的虚存区域。
+    ///
+    /// 若被`replace_so`替换下来的旧so文件已不再被任何地址空间映射，则一并释放其在内核中的区域。
+    ///
+    /// 若该实例没有在`vspace`中以`vdso`为首地址映射vDSO（包括内核中首次调用`map_so`加载的vDSO），则返回`VdsoError::NotMapped`。
+    pub fn unmap_so(&self, vspace: usize, vdso: *mut u8) -> Result<(), VdsoError> {
+        let mut guard = self.kernel.lock();
+        let kernel = guard.as_mut().ok_or(VdsoError::NotLoaded)?;
+        let index = kernel
+            .mappings
+            .iter()
+            .position(|m| m.vspace == vspace && m.base() == vdso as usize)
+            .ok_or(VdsoError::NotMapped)?;
+        let mapping = kernel.mappings.swap_remove(index);
+        #[cfg(feature = "log")]
+        log::info!("unmapping vDSO: vspace: 0x{:016x}, vdso: 0x{:016x}", vspace, vdso as usize);
+        unmap_regions(vspace, &mapping.regions, false);
+
+        // 释放不再被映射的旧so文件
+        let mappings = &kernel.mappings;
+        let (unused, retired): (Vec<_>, Vec<_>) = core::mem::take(&mut kernel.retired)
+            .into_iter()
+            .partition(|(generation, _)| {
+                mappings
+                    .iter()
+                    .all(|m| m.module.is_some() || m.generation != *generation)
+            });
+        kernel.retired = retired;
+        for (generation, regions) in unused {
+            if kernel.reserved && generation == 0 {
+                // 预留的区域由内核管理，只释放暂存的物理页
+                for &(_, ppage, _, _) in regions.iter() {
+                    call_interface!(MemIf::ppage_free(ppage));
+                }
+            } else {
+                unmap_regions(kernel.vspace, &regions, true);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl VdsoInstance {
+    /// 在fork时调用，将父进程地址空间`parent_vspace`中的vDSO复制到子进程地址空间`child_vspace`中。该函数的返回值为子进程中的vdso首地址。
+    ///
+    /// 子进程中的vDSO与父进程位于相同的虚拟地址（通过`MemIf::valloc_at`分配），因此父进程中已初始化的`VDSO_VTABLE`在子进程中依然有效。
+    /// 代码段、只读数据段和vVAR映射到与父进程相同的物理页；私有数据段分配新的物理页，并复制父进程当前的内容，
+    /// 因此私有数据、已初始化的依赖接口和日志等均与父进程相同，子进程不需要重新初始化。
+    ///
+    /// 若父进程映射了多份vDSO（例如在`replace_so`后重新映射过），则复制最后一次映射的vDSO。
+    /// 父进程中通过`map_module`映射的其它模块也会被复制，每个模块复制最后一次映射的一份。
+    /// 若该实例没有在`parent_vspace`中映射vDSO，则返回`VdsoError::NotMapped`。
+    pub fn clone_so(&self, parent_vspace: usize, child_vspace: usize) -> Result<*mut u8, VdsoError> {
+        let mut guard = self.kernel.lock();
+        let kernel = guard.as_mut().ok_or(VdsoError::NotLoaded)?;
+        let main = kernel
+            .mappings
+            .iter()
+            .rposition(|m| m.vspace == parent_vspace && m.module.is_none())
+            .ok_or(VdsoError::NotMapped)?;
+        let mut parents = Vec::new();
+        parents.push(main);
+        for (index, m) in kernel.mappings.iter().enumerate().rev() {
+            let cloned = parents.iter().any(|&p| kernel.mappings[p].module == m.module);
+            if m.vspace == parent_vspace && !cloned {
+                parents.push(index);
+            }
+        }
+
+        let mut children = Vec::new();
+        for index in parents {
+            let parent = &kernel.mappings[index];
+            let shared = match parent.module {
+                Some(module) => &kernel.modules.iter().find(|(name, _)| *name == module).unwrap().1,
+                None => kernel.regions_of(parent.generation),
+            };
+            children.push(clone_mapping(parent_vspace, child_vspace, parent, shared));
+        }
+        let vdso = children[0].base() as *mut u8;
+        kernel.mappings.extend(children);
+        Ok(vdso)
+    }
+}
+
+/// 将父进程中的一份映射`parent`复制到子进程中，`shared`为其在内核中的区域。
+fn clone_mapping(parent_vspace: usize, child_vspace: usize, parent: &Mapping, shared: &[Region]) -> Mapping {
+    let vbase = parent.regions[0].0 as *mut u8;
+    let size = parent.regions.iter().map(|region| region.2).sum();
+    call_interface!(MemIf::valloc_at(child_vspace, vbase, size));
+    for (index, &(vaddr, _, size, flags)) in parent.regions.iter().enumerate() {
+        let vaddr = vaddr as *mut u8;
+        // 第一项为vVAR，与代码段和只读数据段一样共享；读写数据段为私有数据
+        let private = index != 0 && flags.contains(MappingFlags::WRITE);
+        let ppage = if private {
+            call_interface!(MemIf::ppage_alloc(size))
+        } else {
+            call_interface!(MemIf::ppage_clone(shared[index].1))
+        };
+        #[cfg(feature = "log")]
+        log::info!(
+            "clone: vspace: 0x{:016x} -> 0x{:016x}, vaddr: 0x{:016x}, ppage_struct_ptr: 0x{:016x}, size: 0x{:x} {:?}, shared: {}",
+            parent_vspace,
+            child_vspace,
+            vaddr as usize,
+            ppage,
+            size,
+            flags,
+            !private,
+        );
+        call_interface!(MemIf::map(child_vspace, vaddr, ppage, size, flags, !private));
+        if index == 0 && VVAR_KERNEL_SIZE > 0 {
+            // 与`map_image`相同，`VvarKernelData`所在的页在子进程中也只能读取
+            call_interface!(MemIf::change_protect(
+                child_vspace,
+                vaddr,
+                VVAR_KERNEL_SIZE,
+                MappingFlags::READ | MappingFlags::USER
+            ));
+        }
+        if private {
+            let src = call_interface!(MemIf::get_kernel_vaddr(parent_vspace, vaddr));
+            let dst = call_interface!(MemIf::get_kernel_vaddr(child_vspace, vaddr));
+            unsafe { core::ptr::copy_nonoverlapping(src as *const u8, dst, size) };
+        }
+    }
+
+    Mapping {
+        vspace: child_vspace,
+        module: parent.module,
+        generation: parent.generation,
+        regions: parent.regions.clone(),
+    }
+}
+
+/// 为默认实例复制父进程中的vDSO，见`VdsoInstance::clone_so`。
+pub fn clone_so(parent_vspace: usize, child_vspace: usize) -> Result<*mut u8, VdsoError> {
+    VDSO_INSTANCE.clone_so(parent_vspace, child_vspace)
+}
+
+/// 为默认实例将vDSO加载到内核中预留的区域并初始化VTABLE，见`VdsoInstance::load_into_kernel`。
+pub fn load_into_kernel(vspace: usize, region: &'static mut [u8]) -> Result<*mut u8, VdsoError> {
+    VDSO_INSTANCE.load_into_kernel(vspace, region)
+}
+
+/// `load_into_kernel`所需的预留区域大小：vVAR和vDSO各个段按页对齐后的总大小。
+pub fn kernel_region_size() -> usize {
     let vdso_elf = xmas_elf::ElfFile::new(VDSO).expect("Error parsing app ELF file.");
+    VVAR_SIZE + image_size(&vdso_elf)
+}
+
+/// 为默认实例映射其它模块，见`VdsoInstance::map_module`。
+pub fn map_module(vspace: usize, module: &str) -> Result<*mut u8, VdsoError> {
+    VDSO_INSTANCE.map_module(vspace, module)
+}
+
+/// 为默认实例解除vDSO的映射，见`VdsoInstance::unmap_so`。
+pub fn unmap_so(vspace: usize, vdso: *mut u8) -> Result<(), VdsoError> {
+    VDSO_INSTANCE.unmap_so(vspace, vdso)
+}
+
+/// 解除`map_image`映射的各个区域，并释放其虚存区域。
+///
+/// `stored`为`true`时，区域中的物理页为内核中暂存的副本，需要一并释放。
+fn unmap_regions(vspace: usize, regions: &[Region], stored: bool) {
+    for &(vaddr, ppage, size, _) in regions {
+        call_interface!(MemIf::unmap(vspace, vaddr as *mut u8, size));
+        if stored {
+            call_interface!(MemIf::ppage_free(ppage));
+        }
+    }
+    let size = regions.iter().map(|region| region.2).sum();
+    call_interface!(MemIf::vfree(vspace, regions[0].0 as *mut u8, size));
+}
+
+/// 为默认实例加载或映射vDSO，见`VdsoInstance::map_so`。
+pub fn map_so(vspace: usize) -> *mut u8 {
+    VDSO_INSTANCE.map_so(vspace)
+}
+
+/// vDSO镜像中各个段按页对齐后的总大小。
+fn image_size(elf: &xmas_elf::ElfFile) -> usize {
+    let segments = elf_parser::get_elf_segments(elf, Some(0));
+    segments.iter().map(|seg| ((seg.size + PAGES_SIZE - 1) / PAGES_SIZE) * PAGES_SIZE).sum()
+}
+
+/// 将`image`的vVAR和vDSO映射到`vspace`中。
+///
+/// - `shared`为`None`时，映射到内核：所有段均分配物理页并加载，返回的区域中存储了物理页的副本。
+/// - `shared`为内核中已加载的区域时，映射到用户空间：代码段和只读数据段映射到已加载的物理页，
+///   读写数据段重新分配物理页并加载。
+///
+/// `reserved`不为`None`时，`shared`需为`None`，`vvar`需为`VvarPages::Alloc`：
+/// 不分配虚存区域和物理页，而是加载到从`reserved`开始的、已映射为可读可写的区域中，并通过`MemIf::ppage_of`获取区域中的物理页。
+///
+/// 返回vDSO的首地址和各个区域，第一项为vVAR。
+fn map_image(
+    vspace: usize,
+    image: &[u8],
+    vvar: VvarPages,
+    shared: Option<&[Region]>,
+    reserved: Option<*mut u8>,
+) -> (*mut u8, Vec<Region>) {
+    let in_kernel = shared.is_none();
+    let vdso_elf = xmas_elf::ElfFile::new(image).expect("Error parsing app ELF file.");
     if let Some(interp) = vdso_elf
         .program_iter()
         .find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Interp))
@@ -762,34 +2443,39 @@ pub fn map_so(vspace: usize) -> *mut u8 {
         #[cfg(feature = "log")]
         log::debug!("Interpreter path: {:?}", _interp_path);
     }
-    let segments = elf_parser::get_elf_segments(&vdso_elf, Some(0));
-    let vdso_size: usize = segments.iter().map(|seg| ((seg.size + PAGES_SIZE - 1) / PAGES_SIZE) * PAGES_SIZE).sum();
+    let vdso_size = image_size(&vdso_elf);
+    let vvar_size = match vvar {
+        VvarPages::Alloc(size) | VvarPages::Shared(_, size) => size,
+    };
 
-    let vbase = call_interface!(MemIf::valloc(vspace, VVAR_SIZE + vdso_size));
+    let vbase = match reserved {
+        Some(vbase) => vbase,
+        None => call_interface!(MemIf::valloc(vspace, vvar_size + vdso_size)),
+    };
     let mut regions = Vec::new();
 
     // vVAR初始化
     #[cfg(feature = "log")]
     log::info!("mapping vVAR...");
     let vaddr = vbase;
+    let ppage = match vvar {
+        // 预留的区域已映射，只需获取其物理页
+        _ if reserved.is_some() => call_interface!(MemIf::ppage_of(vspace, vaddr, vvar_size)),
+        VvarPages::Alloc(size) => call_interface!(MemIf::ppage_alloc(size)),
+        VvarPages::Shared(origin_ppage, _) => call_interface!(MemIf::ppage_clone(origin_ppage)),
+    };
     // ppage用于映射
-    // ppage_store用于存储在KERNEL_VDSO_REGIONS中（只有首次调用时有意义）
-    let (ppage, ppage_store) = if !KERNEL_VDSO_REGIONS.is_inited() {
-        // 首次调用，分配物理页并加载vVAR
-        let ppage = call_interface!(MemIf::ppage_alloc(VVAR_SIZE));
-        let ppage_clone = call_interface!(MemIf::ppage_clone(ppage));
-        (ppage, ppage_clone)
+    // ppage_store用于存储在实例的`kernel`中（只有映射到内核时有意义）
+    let ppage_store = if in_kernel && reserved.is_none() {
+        call_interface!(MemIf::ppage_clone(ppage))
     } else {
-        // 后续调用，映射已加载的vVAR
-        let origin_ppage = KERNEL_VDSO_REGIONS.get().unwrap()[0].1;
-        let ppage = call_interface!(MemIf::ppage_clone(origin_ppage));
-        (ppage, ppage)
+        ppage
     };
-    let flags = if !KERNEL_VDSO_REGIONS.is_inited() {
-        // 首次调用，内核空间的vVAR不设置USER
+    let flags = if in_kernel {
+        // 内核空间的vVAR不设置USER
         MappingFlags::READ | MappingFlags::WRITE
     } else {
-        // 后续调用，用户空间的vVAR设置USER
+        // 用户空间的vVAR设置USER
         MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER
     };
     #[cfg(feature = "log")]
@@ -798,21 +2484,31 @@ pub fn map_so(vspace: usize) -> *mut u8 {
         vspace,
         vaddr as usize,
         ppage,
-        VVAR_SIZE,
+        vvar_size,
         flags
     );
-    call_interface!(MemIf::map(vspace, vaddr, ppage, VVAR_SIZE, flags, true));
-    // 初始化vvar，只在首次调用时写入数据，后续调用时内核加载的vVAR页面已经包含了正确的数据。
-    // 只在首次调用时，存储region信息
-    if !KERNEL_VDSO_REGIONS.is_inited() {
-        unsafe { (vaddr as *mut VvarData).write(VvarData::default()) };
-        regions.push((vaddr as usize, ppage_store, VVAR_SIZE, flags));
+    if reserved.is_none() {
+        call_interface!(MemIf::map(vspace, vaddr, ppage, vvar_size, flags, true));
+    }
+    if !in_kernel && VVAR_KERNEL_SIZE > 0 {
+        // `VvarKernelData`所在的页只允许内核写入，在用户空间中设为只读
+        let readonly = MappingFlags::READ | MappingFlags::USER;
+        #[cfg(feature = "log")]
+        log::info!(
+            "change_protect: vspace: 0x{:016x}, vaddr: 0x{:016x}, size: 0x{:x}, flags: {:?}",
+            vspace,
+            vaddr as usize,
+            VVAR_KERNEL_SIZE,
+            readonly
+        );
+        call_interface!(MemIf::change_protect(vspace, vaddr, VVAR_KERNEL_SIZE, readonly));
     }
+    regions.push((vaddr as usize, ppage_store, vvar_size, flags));
 
     // vDSO初始化
     #[cfg(feature = "log")]
     log::info!("mapping vDSO...");
-    let elf_base_addr = Some((vbase as usize) + VVAR_SIZE);
+    let elf_base_addr = Some((vbase as usize) + vvar_size);
     let segments = elf_parser::get_elf_segments(&vdso_elf, elf_base_addr);
     let relocate_pairs = elf_parser::get_relocate_pairs(&vdso_elf, elf_base_addr);
     let mut index = 1;
@@ -838,34 +2534,40 @@ pub fn map_so(vspace: usize) -> *mut u8 {
         assert!(segment.vaddr.as_usize() & (PAGES_SIZE - 1) == 0);
         let size = (segment.size + PAGES_SIZE - 1) & (!(PAGES_SIZE - 1));
         let vaddr = segment.vaddr.as_mut_ptr();
-        let (ppage, ppage_store) = if !KERNEL_VDSO_REGIONS.is_inited() {
-            // 首次调用，分配物理页并加载vDSO
-            let ppage = call_interface!(MemIf::ppage_alloc(size));
-            let ppage_clone = call_interface!(MemIf::ppage_clone(ppage));
-            (ppage, ppage_clone)
-        } else {
-            // 后续调用
-            if !segment.flags.contains(MappingFlags::WRITE) {
+        let (ppage, ppage_store) = match shared {
+            None if reserved.is_some() => {
+                // 映射到内核中预留的区域，区域已映射，只需获取其物理页
+                let ppage = call_interface!(MemIf::ppage_of(vspace, vaddr, size));
+                (ppage, ppage)
+            }
+            None => {
+                // 映射到内核，分配物理页并加载vDSO
+                let ppage = call_interface!(MemIf::ppage_alloc(size));
+                let ppage_clone = call_interface!(MemIf::ppage_clone(ppage));
+                (ppage, ppage_clone)
+            }
+            Some(shared) if !segment.flags.contains(MappingFlags::WRITE) => {
                 // 代码段/只读数据段，使用已加载的vDSO
-                let origin_ppage = KERNEL_VDSO_REGIONS.get().unwrap()[index].1;
+                let origin_ppage = shared[index].1;
                 let ppage = call_interface!(MemIf::ppage_clone(origin_ppage));
                 (ppage, ppage)
-            } else {
+            }
+            Some(_) => {
                 // 读写数据段，重新分配物理页，且后续需要加载和重定位
                 let ppage = call_interface!(MemIf::ppage_alloc(size));
                 (ppage, ppage)
             }
         };
-        let flags = if !KERNEL_VDSO_REGIONS.is_inited() {
-            // 首次调用，内核空间的vDSO不设置USER
+        let flags = if in_kernel {
+            // 内核空间的vDSO不设置USER
             segment.flags & !MappingFlags::USER
         } else {
-            // 后续调用，用户空间的vDSO设置USER
+            // 用户空间的vDSO设置USER
             segment.flags | MappingFlags::USER
         };
         // 首先需以WRITE和!USER权限映射，以便加载和重定位；加载和重定位完成后再设置为最终权限。
         let flags_with_write = flags | MappingFlags::WRITE & !MappingFlags::USER;
-        let shared = !segment.flags.contains(MappingFlags::WRITE);
+        let shared_ppage = !segment.flags.contains(MappingFlags::WRITE);
         #[cfg(feature = "log")]
         log::info!(
             "map: vspace: 0x{:016x}, vaddr: 0x{:016x}, ppage_struct_ptr: 0x{:016x}, size: 0x{:x} {:?}, shared: {}",
@@ -874,12 +2576,14 @@ pub fn map_so(vspace: usize) -> *mut u8 {
             ppage,
             size,
             flags_with_write,
-            shared,
+            shared_ppage,
         );
-        call_interface!(MemIf::map(vspace, vaddr, ppage, size, flags_with_write, shared));
-        if !KERNEL_VDSO_REGIONS.is_inited() || !segment.flags.contains(MappingFlags::EXECUTE) {
-            // “首次调用”或“后续调用的数据段”，加载和重定位vDSO
-            // 因为在“后续调用的数据段”情况下，虚拟地址不一定能直接访问，因此需要转化。
+        if reserved.is_none() {
+            call_interface!(MemIf::map(vspace, vaddr, ppage, size, flags_with_write, shared_ppage));
+        }
+        if in_kernel || !segment.flags.contains(MappingFlags::EXECUTE) {
+            // “映射到内核”或“映射到用户空间的数据段”，加载和重定位vDSO
+            // 因为在“映射到用户空间的数据段”情况下，虚拟地址不一定能直接访问，因此需要转化。
             if let Some(data) = segment.data {
                 assert!(data.len() <= size);
                 let src = data.as_ptr();
@@ -921,7 +2625,7 @@ pub fn map_so(vspace: usize) -> *mut u8 {
                 }
             }
         } else {
-            // 后续调用的代码段，确认代码段没有重定位
+            // 映射到用户空间的代码段，确认代码段没有重定位
             for relocate_pair in &relocate_pairs {
                 let relo_dst: usize = relocate_pair.dst.into();
                 if vaddr as usize <= relo_dst && relo_dst < vaddr as usize + size {
@@ -940,24 +2644,123 @@ pub fn map_so(vspace: usize) -> *mut u8 {
             );
             call_interface!(MemIf::change_protect(vspace, vaddr, size, flags));
         }
-        if !KERNEL_VDSO_REGIONS.is_inited() {
-            regions.push((vaddr as usize, ppage_store, size, flags));
-        }
+        regions.push((vaddr as usize, ppage_store, size, flags));
         index += 1;
     }
 
     #[cfg(feature = "log")]
     log::info!("mapping complete!");
 
-    if !KERNEL_VDSO_REGIONS.is_inited() {
-        KERNEL_VDSO_REGIONS.init_once(regions);
-    }
-
-    ((vbase as usize) + VVAR_SIZE) as _
+    (((vbase as usize) + vvar_size) as _, regions)
 }
 "#,
     );
+    // `VvarData`的布局只用于`replace_so`判断是否需要迁移vVAR
+    let map_so_content = if config.resolve_at_runtime {
+        map_so_content
+            .replace(
+                "{vvar_layout_field}",
+                "    /// 当前so文件中`VvarData`的大小和对齐\n    vvar_layout: (usize, usize),\n",
+            )
+            .replace(
+                "{vvar_layout_init}",
+                "            vvar_layout: (core::mem::size_of::<VvarData>(), core::mem::align_of::<VvarData>()),\n",
+            )
+    } else {
+        map_so_content
+            .replace("{vvar_layout_field}", "")
+            .replace("{vvar_layout_init}", "")
+    };
+
+    // 替换so文件后，只能在运行时按名称解析新镜像中的函数
+    let replace_so_content = if config.resolve_at_runtime {
+        r#"
+/// 迁移vVAR数据的函数，参数依次为旧的`VvarData`和新的`VvarData`所在的内存。
+///
+/// 调用时，新的`VvarData`已被清零。`VvarKernelData`的布局不会改变，会被原样复制到新的vVAR中。
+pub type VvarMigrate = fn(old: &[u8], new: &mut [u8]);
+
+impl VdsoInstance {
+    /// 在内核中将该实例已加载的vDSO替换为`image`，并切换内核空间中的VTABLE。该函数的返回值为新的vdso在内核中的首地址。
+    ///
+    /// 新的代码段和数据段会加载到新分配的物理页中，旧的代码段不会被释放，以免其它地址空间中正在进行的调用出错。
+    /// 对于vVAR：
+    ///
+    /// - 若新旧so文件中`VvarData`的大小和对齐相同，则新的vDSO继续使用原有的vVAR物理页，其中的数据保持不变；
+    /// - 否则，为新的vDSO分配新的vVAR，并调用`migrate`迁移数据。此时若`migrate`为`None`，则返回`VdsoError::VvarLayoutMismatch`。
+    ///   已加载的其它模块仍使用原有的vVAR，因此加载了其它模块后也会返回该错误。
+    ///
+    /// 新旧so文件中`VvarKernelData`的大小和对齐必须相同，否则返回`VdsoError::VvarLayoutMismatch`。
+    ///
+    /// 只有主模块会被替换，其它模块保持不变。
+    ///
+    /// 替换后，后续的`map_so`会映射新的so文件。已经映射了旧vDSO的地址空间需要由内核再调用一次`map_so`，
+    /// 并在该地址空间中以新的首地址调用`init_vdso_vtable`，此时VTABLE会被整体切换。
+    /// vDSO的私有数据不会迁移，因此各个地址空间需要重新调用`init_vtable_*`初始化依赖接口。
+    ///
+    /// 若`image`不是合法的vDSO镜像，或其ABI指纹与API库不一致，则返回错误，已加载的vDSO保持不变。
+    pub fn replace_so(&self, image: &'static [u8], migrate: Option<VvarMigrate>) -> Result<*mut u8, VdsoError> {
+        let mut guard = self.kernel.lock();
+        let kernel = guard.as_mut().ok_or(VdsoError::NotLoaded)?;
+        let meta = VdsoMeta::from_elf(image).ok_or(VdsoError::InvalidImage)?;
+        if meta.page_size != PAGES_SIZE {
+            return Err(VdsoError::InvalidImage);
+        }
+        let kernel_layout = (core::mem::size_of::<VvarKernelData>(), core::mem::align_of::<VvarKernelData>());
+        if (meta.vvar_kernel_size, meta.vvar_kernel_align) != kernel_layout {
+            return Err(VdsoError::VvarLayoutMismatch);
+        }
+        let vvar_layout = (meta.vvar_size, meta.vvar_align);
+        let (_, vvar_ppage, vvar_size, _) = kernel.regions[0];
+        let vvar = if vvar_layout == kernel.vvar_layout {
+            VvarPages::Shared(vvar_ppage, vvar_size)
+        } else if migrate.is_some() && kernel.modules.is_empty() {
+            VvarPages::Alloc(VVAR_KERNEL_SIZE + ((meta.vvar_size + PAGES_SIZE - 1) & (!(PAGES_SIZE - 1))))
+        } else {
+            return Err(VdsoError::VvarLayoutMismatch);
+        };
+
+        #[cfg(feature = "log")]
+        log::info!("replacing vDSO...");
+        let (vdso, regions) = map_image(kernel.vspace, image, vvar, None, None);
+        if let Some(migrate) = migrate.filter(|_| vvar_layout != kernel.vvar_layout) {
+            let (old_vvar, new_vvar) = (kernel.regions[0].0, regions[0].0);
+            unsafe { core::ptr::copy_nonoverlapping(old_vvar as *const u8, new_vvar as *mut u8, VVAR_KERNEL_SIZE) };
+            let old = unsafe { core::slice::from_raw_parts((old_vvar + VVAR_KERNEL_SIZE) as *const u8, kernel.vvar_layout.0) };
+            let new = unsafe { core::slice::from_raw_parts_mut((new_vvar + VVAR_KERNEL_SIZE) as *mut u8, meta.vvar_size) };
+            new.fill(0);
+            migrate(old, new);
+        }
+        if let Err(e) = unsafe { self.init_vdso_vtable(vdso as _) } {
+            // 新的so文件没有被使用过，可以直接释放
+            unmap_regions(kernel.vspace, &regions, true);
+            return Err(e);
+        }
+
+        let old_regions = core::mem::replace(&mut kernel.regions, regions);
+        kernel.retired.push((kernel.generation, old_regions));
+        kernel.generation += 1;
+        kernel.image = image;
+        kernel.vvar_layout = vvar_layout;
+        self.init_vdso_log();
+        Ok(vdso)
+    }
+}
+
+/// 替换默认实例中已加载的vDSO，见`VdsoInstance::replace_so`。
+pub fn replace_so(image: &'static [u8], migrate: Option<VvarMigrate>) -> Result<*mut u8, VdsoError> {
+    VDSO_INSTANCE.replace_so(image, migrate)
+}
+"#
+    } else {
+        ""
+    };
 
     // use_content + &interface_content + &const_content + &load_so_content + &map_so_content
-    use_content + &interface_content + &const_content + &map_so_content
+    use_content
+        + &interface_content
+        + module_if_content
+        + &const_content
+        + &map_so_content
+        + replace_so_content
 }
@@ -1,16 +1,34 @@
 use std::{fs, path::Path};
 
-use crate::BuildConfig;
+use crate::{
+    fingerprint::wrapper_fingerprint_content, meta::wrapper_meta_content, scan::VdsoItems,
+    BuildConfig,
+};
 
-pub(crate) fn gen_wrapper(config: &BuildConfig) {
+pub(crate) fn gen_wrapper(config: &BuildConfig, items: &VdsoItems) {
     let lib_path = Path::new(&config.out_dir).join("vdso_wrapper");
     let src_path = lib_path.join("src");
     fs::create_dir_all(&src_path).unwrap();
     let cargo_toml = cargo_toml_content(config);
-    let lib_rs = lib_rs_content(config);
+    let lib_rs = lib_rs_content(config)
+        + &wrapper_meta_content(config, items)
+        + &wrapper_fingerprint_content(config, items);
 
     fs::write(&lib_path.join("Cargo.toml"), cargo_toml).unwrap();
     fs::write(&src_path.join("lib.rs"), lib_rs).unwrap();
+    // 私有堆的大小由build.rs中的mut_cfg!常量决定
+    let build_rs_path = lib_path.join("build.rs");
+    if config.heap_size > 0 {
+        fs::write(&build_rs_path, build_rs_content(config)).unwrap();
+    } else if build_rs_path.exists() {
+        fs::remove_file(&build_rs_path).unwrap();
+    }
+}
+
+/// `vdso_helper`的路径。wrapper库通过它使用`mut_cfg!`和私有堆分配器。
+fn vdso_helper_dir() -> String {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../vdso_helper");
+    fs::canonicalize(dir).unwrap().display().to_string()
 }
 
 fn cargo_toml_content(config: &BuildConfig) -> String {
@@ -20,6 +38,19 @@ fn cargo_toml_content(config: &BuildConfig) -> String {
     }
     let absolute_src_dir = fs::canonicalize(Path::new(&config.src_dir)).unwrap();
     let features = if config.log { " \"log\" " } else { "" };
+    let heap_deps = if config.heap_size > 0 {
+        let vdso_helper_dir = vdso_helper_dir();
+        format!(
+            r#"vdso_helper = {{ path = "{0}" }}
+
+[build-dependencies]
+vdso_helper = {{ path = "{0}" }}
+"#,
+            vdso_helper_dir
+        )
+    } else {
+        String::new()
+    };
     format!(
         r#"[package]
 name = "vdso_wrapper"
@@ -37,7 +68,7 @@ panic = "abort"
 [dependencies]
 {} = {{ path = "{}", features = [{}] }}
 log = {{ version = "0.4", optional = true }}
-
+{}
 [features]
 log = ["dep:log"]
 default = [{}]
@@ -45,11 +76,36 @@ default = [{}]
         config.package_name,
         absolute_src_dir.display(),
         vdso_features,
+        heap_deps,
         features
     )
 }
 
+fn build_rs_content(config: &BuildConfig) -> String {
+    format!(
+        r#"fn main() {{
+    vdso_helper::mut_cfg! {{
+        /// vDSO私有堆的大小
+        const VDSO_HEAP_SIZE: usize = {};
+    }}
+}}
+"#,
+        config.heap_size
+    )
+}
+
 fn lib_rs_content(config: &BuildConfig) -> String {
+    let heap = if config.heap_size > 0 {
+        r#"
+vdso_helper::use_mut_cfg!();
+
+/// vDSO的私有堆，位于私有数据中，每个地址空间持有各自的堆。
+#[global_allocator]
+static VDSO_HEAP: vdso_helper::PrivateHeap<VDSO_HEAP_SIZE> = vdso_helper::PrivateHeap::new();
+"#
+    } else {
+        ""
+    };
     format!(
         r#"#![no_std]
 
@@ -67,8 +123,8 @@ fn panic(info: &core::panic::PanicInfo) -> ! {{
 pub fn panic_loop() -> ! {{
     loop {{}}
 }}
-
+{}
 "#,
-        config.package_name,
+        config.package_name, heap,
     )
 }
@@ -23,6 +23,18 @@ use gen_api::gen_api;
 mod gen_wrapper;
 use gen_wrapper::gen_wrapper;
 
+mod fingerprint;
+
+mod meta;
+
+mod resolve;
+
+mod scan;
+use scan::{scan_vdso, ApiFn, VdsoItems};
+
+mod version;
+use version::{abi_version, AbiVersion};
+
 /// 构建vdso的代码。在vdso外部代码的build.rs中调用该函数。
 ///
 /// # 参数
@@ -41,13 +53,28 @@ pub fn build_vdso(config: &BuildConfig) {
 
     // 生成链接脚本
     let out_path = Path::new(&config.out_dir).join("vdso_linker.lds");
-    let linker_script = gen_linker_script(&config.arch);
+    let linker_script = gen_linker_script(&config.arch, config.page_size);
     fs::write(&out_path, &linker_script).unwrap();
 
+    // 扫描vDSO库的API和依赖接口
+    let mut items = scan_vdso(config);
+    if config.log {
+        items.fns.push(ApiFn {
+            name: "init_log".into(),
+            docs: Vec::new(),
+            lifetimes: Vec::new(),
+            is_unsafe: false,
+            args: vec![("logger_fat_ptr".into(), "(usize, usize)".into())],
+            ret: None,
+            version: AbiVersion::BASE,
+            module: None,
+        });
+    }
+
     // 生成wrapper静态库
-    gen_wrapper(config);
+    gen_wrapper(config, &items);
 
-    build_so(config);
+    build_so(config, &items);
 
     gen_api(config);
 }
@@ -72,8 +99,12 @@ fn linker_program(arch: &str) -> &'static str {
     }
 }
 
-/// 生成链接脚本的代码
-fn gen_linker_script(arch: &str) -> String {
+/// 生成链接脚本的代码，各个段按`page_size`对齐
+fn gen_linker_script(arch: &str, page_size: usize) -> String {
+    assert!(
+        page_size.is_power_of_two(),
+        "page_size must be a power of two"
+    );
     // Copied and modified from https://github.com/AsyncModules/vsched/blob/e19b572714a6931972f1428e42d43cc34bcf47f2/vsched/build.rs
     let arch_lds = match arch {
         "riscv64" => "riscv",
@@ -83,25 +114,23 @@ fn gen_linker_script(arch: &str) -> String {
     };
     let linker_template = include_str!("link.ld");
     // let linker_template = include_str!("link_no_segment.ld");
-    let linker = linker_template.replace("{output_arch}", arch_lds);
+    let linker = linker_template
+        .replace("{output_arch}", arch_lds)
+        .replace("{page_size}", &format!("{:#x}", page_size));
     linker
 }
 
 /// 先编译为静态库，再单独链接成 so。
-fn build_so(config: &BuildConfig) {
+fn build_so(config: &BuildConfig, items: &VdsoItems) {
     // 获取输出目录和生成链接脚本路径
     let out_dir = Path::new(&config.out_dir);
     let absolute_script_dir = fs::canonicalize(out_dir.join("vdso_linker.lds"))
         .unwrap()
         .display()
         .to_string();
-    // 生成版本脚本
-    let version_script_path = out_dir.join("vdso_version.map");
-    fs::write(&version_script_path, version_script_content(config)).unwrap();
 
-    // 获取编译目标和链接器程序
+    // 获取编译目标
     let build_target = build_target(&config.arch);
-    let linker = linker_program(&config.arch);
     // 获取是否为release模式
     let build_mode = match config.mode.as_str() {
         "debug" => "",
@@ -166,6 +195,8 @@ fn build_so(config: &BuildConfig) {
     cargo
         .current_dir(&wrapper_dir)
         .env("ARCH", &config.arch)
+        // vdso_helper在编译期读取页大小，用于定位vVAR
+        .env("VDSO_PAGE_SIZE", config.page_size.to_string())
         .env("RUSTFLAGS", "-C force-frame-pointers=yes")
         .args(cargo_args);
     println!("----------------cargo command----------------");
@@ -188,27 +219,77 @@ fn build_so(config: &BuildConfig) {
         .with_extension("a")
         .display()
         .to_string();
+    // 链接主模块，以及各个按需加载的模块
+    link_so(config, items, &absolute_script_dir, &src_file, None);
+    for module in items.modules() {
+        link_so(
+            config,
+            items,
+            &absolute_script_dir,
+            &src_file,
+            Some(&module),
+        );
+    }
+}
+
+/// 模块`module`的so文件名（不含扩展名），`None`表示主模块。
+pub(crate) fn so_name(config: &BuildConfig, module: Option<&str>) -> String {
+    match module {
+        Some(module) => format!("{}_{}", config.so_name, module),
+        None => config.so_name.clone(),
+    }
+}
+
+/// 将wrapper静态库链接为模块`module`的so文件，只导出该模块的API，其余代码由`--gc-sections`移除。
+fn link_so(
+    config: &BuildConfig,
+    items: &VdsoItems,
+    script_path: &str,
+    src_file: &str,
+    module: Option<&str>,
+) {
+    let out_dir = Path::new(&config.out_dir);
+    let so_name = so_name(config, module);
+    // 生成版本脚本
+    let version_script_path = match module {
+        Some(module) => out_dir.join(format!("vdso_version_{}.map", module)),
+        None => out_dir.join("vdso_version.map"),
+    };
+    fs::write(
+        &version_script_path,
+        version_script_content(config, items, module),
+    )
+    .unwrap();
+
+    let linker = linker_program(&config.arch);
     // 目标so路径
-    let dst_file = Path::new(&config.out_dir)
-        .join(&config.so_name)
+    let dst_file = out_dir
+        .join(&so_name)
         .with_extension("so")
         .display()
         .to_string();
     let mut linker_cmd = Command::new(linker);
+    let max_page_size = format!("max-page-size={:#x}", config.page_size);
+    let common_page_size = format!("common-page-size={:#x}", config.page_size);
     // 链接命令参数
     linker_cmd.args([
+        "-z",
+        &max_page_size,
+        "-z",
+        &common_page_size,
         "-shared",
         "-soname",
-        &config.so_name,
+        &so_name,
         "-T",
-        &absolute_script_dir,
+        script_path,
         "--version-script",
         version_script_path
             .to_str()
             .expect("version script 不是有效 UTF-8"),
+        "--hash-style=both",
         "--gc-sections",
         "--whole-archive",
-        &src_file,
+        src_file,
         "--no-whole-archive",
         "-o",
         &dst_file,
@@ -225,61 +306,135 @@ fn build_so(config: &BuildConfig) {
     }
 }
 
-fn version_script_content(config: &BuildConfig) -> String {
-    let mut symbols = exported_symbols(config);
-    symbols.sort();
-    symbols.dedup();
+/// 生成版本脚本。
+///
+/// 每个ABI版本对应一个版本节点，较新的节点继承较旧的节点，所有未导出的符号均在最旧的节点中设为`local`。
+/// 各个模块使用相同的版本节点，但只导出属于模块`module`的API。
+fn version_script_content(config: &BuildConfig, items: &VdsoItems, module: Option<&str>) -> String {
+    let versions = items.versions(abi_version(config));
 
-    let mut content = String::from("vdso {\n    global:\n");
-    for symbol in symbols {
-        content.push_str("        ");
-        content.push_str(&symbol);
-        content.push_str(";\n");
+    let mut content = String::new();
+    for (index, version) in versions.iter().enumerate() {
+        let mut symbols = exported_symbols(items, *version, module);
+        symbols.sort();
+        symbols.dedup();
+
+        content.push_str(&version.node_name());
+        content.push_str(" {\n");
+        if !symbols.is_empty() {
+            content.push_str("    global:\n");
+        }
+        for symbol in symbols {
+            content.push_str("        ");
+            content.push_str(&symbol);
+            content.push_str(";\n");
+        }
+        if index == 0 {
+            content.push_str("    local:\n        *;\n};\n");
+        } else {
+            content.push_str(&format!("}} {};\n", versions[index - 1].node_name()));
+        }
     }
-    content.push_str("    local:\n        *;\n};\n");
     content
 }
 
-fn exported_symbols(config: &BuildConfig) -> Vec<String> {
+/// 模块`module`中属于版本`version`的导出符号。
+///
+/// 每个模块都有自己的私有数据，因此依赖接口的初始化函数和`init_log`在每个模块中都会导出。
+fn exported_symbols(items: &VdsoItems, version: AbiVersion, module: Option<&str>) -> Vec<String> {
     let mut symbols: Vec<String> = Vec::new();
-    symbols.push("panic_loop".into());
-    if config.log {
-        symbols.push("init_log".into());
+    if version == AbiVersion::BASE {
+        symbols.push("panic_loop".into());
+        symbols.extend(items.traits.iter().map(|t| t.init_fn().name));
+        if module.is_some() && items.fns.iter().any(|f| f.name == "init_log") {
+            symbols.push("init_log".into());
+        }
     }
+    symbols.extend(
+        items
+            .fns
+            .iter()
+            .chain(items.compat_fns.iter())
+            .filter(|f| f.version == version && f.module.as_deref() == module)
+            .map(|f| f.name.clone()),
+    );
 
-    let api_rs_path = Path::new(&config.src_dir).join("src").join("api.rs");
-    if let Ok(api_source) = fs::read_to_string(&api_rs_path) {
-        let re = regex::Regex::new(
-            r#"(?s)#\[unsafe\(no_mangle\)\]\s*pub\s+extern\s+\"C\"\s+fn\s+([A-Za-z0-9_]+)\s*\("#,
-        )
-        .unwrap();
-
-        let mut api_symbols: Vec<String> = re
-            .captures_iter(&api_source)
-            .map(|capture| capture[1].to_string())
-            .collect();
-        symbols.append(&mut api_symbols);
-
-        let re = regex::Regex::new(r#"extern \"C\" \{([^\{\}]+)\}"#).unwrap();
-        for (_, [extern_fns]) in re.captures_iter(&api_source).map(|c| c.extract()) {
-            let fns_re = regex::Regex::new(r#"fn ([a-zA-Z0-9_]+)\(\) -> !;"#).unwrap();
-            for (_, [name]) in fns_re.captures_iter(&extern_fns).map(|c| c.extract()) {
-                // println!("name: {}", name);
-                symbols.push(name.into());
-            }
+    symbols
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scan::InterfaceTrait;
+
+    const V1_1: AbiVersion = AbiVersion { major: 1, minor: 1 };
+
+    fn api(name: &str, version: AbiVersion, module: Option<&str>) -> ApiFn {
+        ApiFn {
+            version,
+            module: module.map(String::from),
+            ..ApiFn::with_usize_args(name, &[])
         }
     }
 
-    let interface_rs_path = Path::new(&config.src_dir).join("src").join("interface.rs");
-    if let Ok(interface_source) = fs::read_to_string(&interface_rs_path) {
-        let re = regex::Regex::new(r#"pub trait ([a-zA-Z0-9_]+) \{([^\{\}]+)\}"#).unwrap();
+    #[test]
+    fn version_script() {
+        let items = VdsoItems {
+            fns: vec![
+                api("get", AbiVersion::BASE, None),
+                api("version", V1_1, None),
+                api("added", V1_1, None),
+                api("ext_get", V1_1, Some("ext")),
+            ],
+            traits: vec![InterfaceTrait {
+                name: "WaitIf".to_string(),
+                fns: vec!["wait".to_string()],
+            }],
+            compat_fns: vec![api("version", AbiVersion::BASE, None)],
+            async_fns: Vec::new(),
+            generic_fns: Vec::new(),
+            methods: Vec::new(),
+        };
+        let mut config = BuildConfig::new(".", "version_test");
+        config.abi_version = "1.1".to_string();
 
-        let mut interface_symbols: Vec<String> = re
-            .captures_iter(&interface_source)
-            .map(|capture| format!("init_vtable_{}", capture.extract::<2>().1[0]))
-            .collect();
-        symbols.append(&mut interface_symbols);
+        // `since`的API位于新的节点中，`compat`的旧版本入口与基础API一起位于`VDSO_1.0`中；新节点继承旧节点
+        assert_eq!(
+            version_script_content(&config, &items, None),
+            "VDSO_1.0 {
+    global:
+        get;
+        init_vtable_WaitIf;
+        panic_loop;
+        version;
+    local:
+        *;
+};
+VDSO_1.1 {
+    global:
+        added;
+        version;
+} VDSO_1.0;
+"
+        );
+        // 单独的模块中只有该模块的API，但同样导出依赖接口的初始化函数；ABI版本更新后节点链也随之增长
+        config.abi_version = "1.2".to_string();
+        assert_eq!(
+            version_script_content(&config, &items, Some("ext")),
+            "VDSO_1.0 {
+    global:
+        init_vtable_WaitIf;
+        panic_loop;
+    local:
+        *;
+};
+VDSO_1.1 {
+    global:
+        ext_get;
+} VDSO_1.0;
+VDSO_1.2 {
+} VDSO_1.1;
+"
+        );
     }
-
-    symbols
 }
@@ -0,0 +1,562 @@
+//! vDSO元数据节的生成与解析。
+//!
+//! vDSO构建时，会在so文件的`.vdso_meta`节中嵌入元数据，包括：
+//!
+//! - 页大小、`VvarData`和`VvarKernelData`的大小和对齐；
+//! - 所有API函数的函数名、签名和文档注释；
+//! - 所有`#[vdso_async]`异步API的函数名、签名和文档注释；
+//! - 所有泛型API的签名及其实例；
+//! - 所有以`#[vdso_impl]`导出的方法的签名；
+//! - 所有`trait_interface!`依赖接口的trait名和函数表。
+//!
+//! 生成API库时，从链接好的so文件中读取元数据，而不再重新解析vDSO库的源代码。
+//! 生成的API库中也包含读取元数据的代码，以便在运行时检查so文件。
+//!
+//! 元数据节由固定长度的头部和描述文本组成，头部各字段均为小端序的`u64`：
+//!
+//! | 偏移 | 内容 |
+//! | ---- | ---- |
+//! | 0    | 魔数`b"VDSOMETA"` |
+//! | 8    | 格式版本 |
+//! | 16   | 页大小 |
+//! | 24   | `VvarData`的大小 |
+//! | 32   | `VvarData`的对齐 |
+//! | 40   | `VvarKernelData`的大小 |
+//! | 48   | `VvarKernelData`的对齐 |
+//! | 56   | 描述文本的长度 |
+//! | 64   | 描述文本（UTF-8） |
+//!
+//! 描述文本每行为一条记录，记录中的字段以`\t`分隔，字段中的`\\`、`\t`、`\n`会被转义：
+//!
+//! - `doc\t<文档注释>`：下一个`fn`、`async`、`generic`或`method`记录的一行文档注释；
+//! - `fn\t<函数名>\t<unsafe或空>\t<生命周期参数，以,分隔>\t<返回值类型或空>\t<ABI版本>\t<所属模块，主模块为空>[\t<参数名>\t<参数类型>]...`；
+//! - `async\t...`：`#[vdso_async]`标记的`async fn`，字段与`fn`记录相同，返回值类型为Future的输出类型。
+//!   它实际导出的各个入口另有对应的`fn`记录；
+//! - `generic\t...`：以`#[vdso_api(instances = "...")]`标记的泛型函数，字段与`fn`记录相同，
+//!   但生命周期参数一栏为类型参数（以,分隔），参数和返回值类型中包含类型参数；
+//! - `instance\t<泛型函数名>\t<导出的符号名>[\t<类型实参>]...`：上一个同名`generic`记录的一个实例，
+//!   实例另有对应的`fn`记录；
+//! - `method\t...`：以`#[vdso_impl]`导出的方法，字段与`fn`记录相同，但函数名一栏为`<类型名>::<方法名>`，
+//!   参数和返回值类型中保留`Self`，接收者记录为名为`self`的第一个参数，类型为接收者本身，如`&self`、`&'a mut self`或`self`。
+//!   为它导出的`<类型名>__<方法名>`函数另有对应的`fn`记录；
+//! - `trait\t<trait名>[\t<函数名>]...`。
+
+use xmas_elf::ElfFile;
+
+use crate::{
+    scan::{ApiFn, GenericFn, GenericInstance, ImplMethod, InterfaceTrait, VdsoItems},
+    version::AbiVersion,
+    BuildConfig,
+};
+
+/// 元数据所在的节名
+pub(crate) const META_SECTION: &str = ".vdso_meta";
+/// 元数据头部的魔数
+const META_MAGIC: &[u8; 8] = b"VDSOMETA";
+/// 元数据格式版本，修改格式时需要递增
+const META_VERSION: u64 = 7;
+/// 元数据头部的长度
+const META_HEADER_SIZE: usize = 64;
+
+/// 从so文件中读取的元数据。
+pub(crate) struct VdsoMeta {
+    pub page_size: usize,
+    pub vvar_size: usize,
+    pub vvar_align: usize,
+    pub vvar_kernel_size: usize,
+    pub vvar_kernel_align: usize,
+    pub fns: Vec<ApiFn>,
+    pub async_fns: Vec<ApiFn>,
+    pub generic_fns: Vec<GenericFn>,
+    pub methods: Vec<ImplMethod>,
+    pub traits: Vec<InterfaceTrait>,
+}
+
+impl VdsoMeta {
+    /// 除主模块外的所有模块名，按名称排序。
+    pub fn modules(&self) -> Vec<String> {
+        let mut modules: Vec<String> = self.fns.iter().filter_map(|f| f.module.clone()).collect();
+        modules.sort();
+        modules.dedup();
+        modules
+    }
+}
+
+/// 生成wrapper中定义元数据节的代码。
+pub(crate) fn wrapper_meta_content(config: &BuildConfig, items: &VdsoItems) -> String {
+    format!(
+        r#"
+/// vDSO元数据的描述文本，格式见`build_vdso`的`meta`模块。
+const VDSO_META_DESC: &str = {:?};
+
+#[repr(C)]
+struct VdsoMeta<const N: usize> {{
+    magic: [u8; 8],
+    version: u64,
+    page_size: u64,
+    vvar_size: u64,
+    vvar_align: u64,
+    vvar_kernel_size: u64,
+    vvar_kernel_align: u64,
+    desc_len: u64,
+    desc: [u8; N],
+}}
+
+const fn str_to_array<const N: usize>(s: &str) -> [u8; N] {{
+    let bytes = s.as_bytes();
+    let mut array = [0u8; N];
+    let mut i = 0;
+    while i < N {{
+        array[i] = bytes[i];
+        i += 1;
+    }}
+    array
+}}
+
+/// 嵌入so文件的元数据，供API库在构建时和运行时读取。
+#[used]
+#[link_section = "{}"]
+static VDSO_META: VdsoMeta<{{ VDSO_META_DESC.len() }}> = VdsoMeta {{
+    magic: *{},
+    version: {},
+    page_size: {},
+    vvar_size: core::mem::size_of::<VvarData>() as u64,
+    vvar_align: core::mem::align_of::<VvarData>() as u64,
+    vvar_kernel_size: core::mem::size_of::<VvarKernelData>() as u64,
+    vvar_kernel_align: core::mem::align_of::<VvarKernelData>() as u64,
+    desc_len: VDSO_META_DESC.len() as u64,
+    desc: str_to_array(VDSO_META_DESC),
+}};
+"#,
+        encode_desc(items),
+        META_SECTION,
+        byte_string(META_MAGIC),
+        META_VERSION,
+        config.page_size,
+    )
+}
+
+/// 从链接好的so文件中读取元数据。
+pub(crate) fn read_meta(elf: &ElfFile) -> VdsoMeta {
+    let section = elf
+        .find_section_by_name(META_SECTION)
+        .unwrap_or_else(|| panic!("{} section not found in vDSO", META_SECTION));
+    let data = section.raw_data(elf);
+    assert!(
+        data.len() >= META_HEADER_SIZE && &data[..8] == META_MAGIC,
+        "Invalid {} section",
+        META_SECTION
+    );
+    let field = |index: usize| {
+        let offset = 8 * index;
+        u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap()) as usize
+    };
+    let version = field(1) as u64;
+    assert!(
+        version == META_VERSION,
+        "Unsupported vDSO metadata version {} (expected {})",
+        version,
+        META_VERSION
+    );
+    let desc_len = field(7);
+    let desc = std::str::from_utf8(&data[META_HEADER_SIZE..META_HEADER_SIZE + desc_len])
+        .expect("vDSO metadata is not valid UTF-8");
+    let Desc {
+        fns,
+        async_fns,
+        generic_fns,
+        methods,
+        traits,
+    } = decode_desc(desc);
+    VdsoMeta {
+        page_size: field(2),
+        vvar_size: field(3),
+        vvar_align: field(4),
+        vvar_kernel_size: field(5),
+        vvar_kernel_align: field(6),
+        fns,
+        async_fns,
+        generic_fns,
+        methods,
+        traits,
+    }
+}
+
+fn encode_desc(items: &VdsoItems) -> String {
+    let mut desc = String::new();
+    for f in items.fns.iter() {
+        push_fn_record(&mut desc, "fn", f);
+    }
+    for f in items.async_fns.iter() {
+        push_fn_record(&mut desc, "async", f);
+    }
+    for g in items.generic_fns.iter() {
+        // 泛型函数没有生命周期参数，该栏用于记录类型参数
+        let api = ApiFn {
+            lifetimes: g.params.clone(),
+            ..g.api.clone()
+        };
+        push_fn_record(&mut desc, "generic", &api);
+        for instance in g.instances.iter() {
+            let mut fields = vec!["instance", g.api.name.as_str(), instance.symbol.as_str()];
+            fields.extend(instance.types.iter().map(|ty| ty.as_str()));
+            push_record(&mut desc, &fields);
+        }
+    }
+    for m in items.methods.iter() {
+        let mut api = ApiFn {
+            name: format!("{}::{}", m.ty, m.api.name),
+            ..m.api.clone()
+        };
+        if let Some(receiver) = &m.receiver {
+            api.args.insert(0, ("self".to_string(), receiver.clone()));
+        }
+        push_fn_record(&mut desc, "method", &api);
+    }
+    for t in items.traits.iter() {
+        let mut fields = vec!["trait", t.name.as_str()];
+        fields.extend(t.fns.iter().map(|f| f.as_str()));
+        push_record(&mut desc, &fields);
+    }
+    desc
+}
+
+fn push_fn_record(desc: &mut String, kind: &str, f: &ApiFn) {
+    for doc in f.docs.iter() {
+        push_record(desc, &["doc", doc]);
+    }
+    let unsafety = if f.is_unsafe { "unsafe" } else { "" };
+    let lifetimes = f.lifetimes.join(",");
+    let ret = f.ret.as_deref().unwrap_or("");
+    let version = f.version.to_string();
+    let module = f.module.as_deref().unwrap_or("");
+    let mut fields = vec![kind, &f.name, unsafety, &lifetimes, ret, &version, module];
+    for (name, ty) in f.args.iter() {
+        fields.push(name);
+        fields.push(ty);
+    }
+    push_record(desc, &fields);
+}
+
+/// 从描述文本中解码出的各类记录。
+struct Desc {
+    fns: Vec<ApiFn>,
+    async_fns: Vec<ApiFn>,
+    generic_fns: Vec<GenericFn>,
+    methods: Vec<ImplMethod>,
+    traits: Vec<InterfaceTrait>,
+}
+
+fn decode_desc(desc: &str) -> Desc {
+    let mut fns = Vec::new();
+    let mut async_fns = Vec::new();
+    let mut generic_fns: Vec<GenericFn> = Vec::new();
+    let mut methods = Vec::new();
+    let mut traits = Vec::new();
+    let mut docs = Vec::new();
+    for line in desc.lines() {
+        let fields: Vec<String> = line.split('\t').map(unescape).collect();
+        match fields[0].as_str() {
+            "doc" => docs.push(fields[1].clone()),
+            kind @ ("fn" | "async" | "generic" | "method") => {
+                let mut args: Vec<(String, String)> = fields[7..]
+                    .chunks(2)
+                    .map(|arg| (arg[0].clone(), arg[1].clone()))
+                    .collect();
+                let receiver = match args.first() {
+                    Some((name, _)) if kind == "method" && name == "self" => Some(args.remove(0).1),
+                    _ => None,
+                };
+                let f = ApiFn {
+                    name: fields[1].clone(),
+                    docs: std::mem::take(&mut docs),
+                    lifetimes: fields[3]
+                        .split(',')
+                        .filter(|l| !l.is_empty())
+                        .map(String::from)
+                        .collect(),
+                    is_unsafe: fields[2] == "unsafe",
+                    args,
+                    ret: Some(fields[4].clone()).filter(|ret| !ret.is_empty()),
+                    version: AbiVersion::parse(&fields[5]).unwrap_or_else(|e| panic!("{}", e)),
+                    module: Some(fields[6].clone()).filter(|module| !module.is_empty()),
+                };
+                match kind {
+                    "fn" => fns.push(f),
+                    "async" => async_fns.push(f),
+                    "method" => {
+                        let (ty, name) = f
+                            .name
+                            .split_once("::")
+                            .map(|(ty, name)| (ty.to_string(), name.to_string()))
+                            .unwrap_or_else(|| panic!("Invalid vDSO method record `{}`", f.name));
+                        methods.push(ImplMethod {
+                            ty,
+                            receiver,
+                            api: ApiFn { name, ..f },
+                        });
+                    }
+                    _ => generic_fns.push(GenericFn {
+                        params: f.lifetimes.clone(),
+                        api: ApiFn {
+                            lifetimes: Vec::new(),
+                            ..f
+                        },
+                        instances: Vec::new(),
+                    }),
+                }
+            }
+            "instance" => {
+                let generic_fn = generic_fns
+                    .iter_mut()
+                    .rev()
+                    .find(|g| g.api.name == fields[1])
+                    .unwrap_or_else(|| panic!("Instance of unknown generic API `{}`", fields[1]));
+                generic_fn.instances.push(GenericInstance {
+                    symbol: fields[2].clone(),
+                    types: fields[3..].to_vec(),
+                });
+            }
+            "trait" => traits.push(InterfaceTrait {
+                name: fields[1].clone(),
+                fns: fields[2..].to_vec(),
+            }),
+            other => panic!("Unknown vDSO metadata record `{}`", other),
+        }
+    }
+    Desc {
+        fns,
+        async_fns,
+        generic_fns,
+        methods,
+        traits,
+    }
+}
+
+fn push_record(desc: &mut String, fields: &[&str]) {
+    let fields: Vec<String> = fields.iter().map(|f| escape(f)).collect();
+    desc.push_str(&fields.join("\t"));
+    desc.push('\n');
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn byte_string(bytes: &[u8]) -> String {
+    format!("b\"{}\"", String::from_utf8_lossy(bytes))
+}
+
+/// 生成的API库中`meta.rs`的内容，用于在运行时读取元数据。
+pub(crate) const META_RS: &str = r#"//! 读取vDSO中的元数据节。
+//!
+//! 元数据描述了vDSO导出的API函数、异步API、泛型API的实例、导出的方法、依赖接口、`VvarData`和`VvarKernelData`的布局和页大小，
+//! 可以在不依赖vDSO源代码的情况下检查一个so文件。
+
+/// 元数据所在的节名
+pub const META_SECTION: &str = ".vdso_meta";
+const META_MAGIC: &[u8; 8] = b"VDSOMETA";
+const META_VERSION: u64 = 7;
+const META_HEADER_SIZE: usize = 64;
+
+/// vDSO的元数据。
+#[derive(Debug, Clone, Copy)]
+pub struct VdsoMeta<'a> {
+    /// 构建vDSO时使用的页大小
+    pub page_size: usize,
+    /// `VvarData`的大小
+    pub vvar_size: usize,
+    /// `VvarData`的对齐
+    pub vvar_align: usize,
+    /// `VvarKernelData`的大小
+    pub vvar_kernel_size: usize,
+    /// `VvarKernelData`的对齐
+    pub vvar_kernel_align: usize,
+    desc: &'a str,
+}
+
+impl<'a> VdsoMeta<'a> {
+    /// 从so文件的内容中读取元数据。若so文件不包含合法的元数据节，则返回`None`。
+    pub fn from_elf(image: &'a [u8]) -> Option<Self> {
+        let elf = xmas_elf::ElfFile::new(image).ok()?;
+        let section = elf.find_section_by_name(META_SECTION)?;
+        let offset = section.offset() as usize;
+        let size = section.size() as usize;
+        Self::from_section(image.get(offset..offset + size)?)
+    }
+
+    /// 从元数据节的内容中读取元数据。
+    pub fn from_section(data: &'a [u8]) -> Option<Self> {
+        if data.len() < META_HEADER_SIZE || &data[..8] != META_MAGIC {
+            return None;
+        }
+        let field = |index: usize| {
+            let offset = 8 * index;
+            u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+        };
+        if field(1) != META_VERSION {
+            return None;
+        }
+        let desc_len = field(7) as usize;
+        let desc = core::str::from_utf8(data.get(META_HEADER_SIZE..META_HEADER_SIZE + desc_len)?).ok()?;
+        Some(Self {
+            page_size: field(2) as usize,
+            vvar_size: field(3) as usize,
+            vvar_align: field(4) as usize,
+            vvar_kernel_size: field(5) as usize,
+            vvar_kernel_align: field(6) as usize,
+            desc,
+        })
+    }
+
+    /// vDSO导出的所有API函数，包括异步API实际导出的各个入口。
+    pub fn fns(&self) -> impl Iterator<Item = VdsoFnMeta<'a>> {
+        self.fn_records("fn")
+    }
+
+    /// vDSO中以`#[vdso_async]`标记的所有异步API，`ret`为Future的输出类型。
+    pub fn async_fns(&self) -> impl Iterator<Item = VdsoFnMeta<'a>> {
+        self.fn_records("async")
+    }
+
+    /// vDSO中以`#[vdso_impl]`导出的所有方法，`name`形如`类型名::方法名`，接收者为名为`self`的第一个参数。
+    pub fn methods(&self) -> impl Iterator<Item = VdsoFnMeta<'a>> {
+        self.fn_records("method")
+    }
+
+    /// 类型为`kind`的函数记录。
+    fn fn_records(&self, kind: &'static str) -> impl Iterator<Item = VdsoFnMeta<'a>> {
+        self.records(kind).map(|record| {
+            let mut fields = record.splitn(7, '\t');
+            VdsoFnMeta {
+                name: fields.next().unwrap_or(""),
+                is_unsafe: fields.next() == Some("unsafe"),
+                lifetimes: fields.next().unwrap_or(""),
+                ret: fields.next().unwrap_or(""),
+                version: fields.next().unwrap_or(""),
+                module: fields.next().unwrap_or(""),
+                args: fields.next().unwrap_or(""),
+            }
+        })
+    }
+
+    /// vDSO中泛型API的所有实例。
+    pub fn instances(&self) -> impl Iterator<Item = VdsoGenericInstanceMeta<'a>> {
+        self.records("instance").map(|record| {
+            let mut fields = record.splitn(3, '\t');
+            VdsoGenericInstanceMeta {
+                name: fields.next().unwrap_or(""),
+                symbol: fields.next().unwrap_or(""),
+                types: fields.next().unwrap_or(""),
+            }
+        })
+    }
+
+    /// vDSO中由`trait_interface!`声明的所有依赖接口。
+    pub fn interfaces(&self) -> impl Iterator<Item = VdsoInterfaceMeta<'a>> {
+        self.records("trait").map(|record| {
+            let (name, fns) = record.split_once('\t').unwrap_or((record, ""));
+            VdsoInterfaceMeta { name, fns }
+        })
+    }
+
+    /// 类型为`kind`的记录，返回记录中类型之后的部分。
+    fn records(&self, kind: &'static str) -> impl Iterator<Item = &'a str> {
+        self.desc.lines().filter_map(move |line| {
+            let (record_kind, record) = line.split_once('\t').unwrap_or((line, ""));
+            (record_kind == kind).then_some(record)
+        })
+    }
+}
+
+/// 元数据中的一个API函数。
+#[derive(Debug, Clone, Copy)]
+pub struct VdsoFnMeta<'a> {
+    /// 函数名，也是so文件中导出的符号名
+    pub name: &'a str,
+    /// 是否为`unsafe`函数
+    pub is_unsafe: bool,
+    /// 所属的ABI版本，如`1.0`
+    pub version: &'a str,
+    /// 所属的模块，主模块为空字符串
+    pub module: &'a str,
+    lifetimes: &'a str,
+    ret: &'a str,
+    args: &'a str,
+}
+
+impl<'a> VdsoFnMeta<'a> {
+    /// 生命周期参数
+    pub fn lifetimes(&self) -> impl Iterator<Item = &'a str> {
+        self.lifetimes.split(',').filter(|l| !l.is_empty())
+    }
+
+    /// 参数名和参数类型
+    pub fn args(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        let mut fields = self.args.split('\t').filter(|f| !f.is_empty());
+        core::iter::from_fn(move || Some((fields.next()?, fields.next()?)))
+    }
+
+    /// 返回值类型，无返回值时为`None`
+    pub fn ret(&self) -> Option<&'a str> {
+        Some(self.ret).filter(|ret| !ret.is_empty())
+    }
+}
+
+/// 元数据中泛型API的一个实例。
+#[derive(Debug, Clone, Copy)]
+pub struct VdsoGenericInstanceMeta<'a> {
+    /// 泛型函数名
+    pub name: &'a str,
+    /// 该实例导出的符号名，其签名可在`fns`中找到
+    pub symbol: &'a str,
+    types: &'a str,
+}
+
+impl<'a> VdsoGenericInstanceMeta<'a> {
+    /// 类型实参，顺序与泛型函数的类型参数相同
+    pub fn types(&self) -> impl Iterator<Item = &'a str> {
+        self.types.split('\t').filter(|ty| !ty.is_empty())
+    }
+}
+
+/// 元数据中的一个依赖接口。
+#[derive(Debug, Clone, Copy)]
+pub struct VdsoInterfaceMeta<'a> {
+    /// trait名
+    pub name: &'a str,
+    fns: &'a str,
+}
+
+impl<'a> VdsoInterfaceMeta<'a> {
+    /// trait中的函数名，顺序与`init_vtable_*`的参数顺序相同
+    pub fn fns(&self) -> impl Iterator<Item = &'a str> {
+        self.fns.split('\t').filter(|f| !f.is_empty())
+    }
+}
+
+/// 本API库所绑定的so文件的元数据。
+pub fn vdso_meta() -> VdsoMeta<'static> {
+    VdsoMeta::from_elf(crate::loader::VDSO).expect("vDSO metadata not found")
+}
+"#;
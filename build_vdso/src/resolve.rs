@@ -0,0 +1,279 @@
+//! 在运行时解析vDSO导出符号的代码。
+//!
+//! 默认情况下，生成的API库在构建时从so文件的`.dynsym`节中读取各个函数的偏移，并写入`init_vdso_vtable`中。
+//! 若启用了[`BuildConfig::resolve_at_runtime`](crate::BuildConfig::resolve_at_runtime)，
+//! API库会在`init_vdso_vtable`中遍历已映射的vDSO镜像的动态段、哈希表和动态符号表，按名称解析每个函数。
+//! 这样，只要函数签名不变（即ABI指纹一致），重新构建的so文件也可以与旧的API库配合使用。
+//!
+//! 解析时会检查符号版本（`DT_VERSYM`和`DT_VERDEF`），每个函数都绑定到其所属版本节点中的定义。
+
+use xmas_elf::{
+    sections::{SectionData, SHN_UNDEF},
+    symbol_table::Entry,
+    ElfFile,
+};
+
+/// 在链接好的so文件中查找版本节点`version`中名为`name`的导出符号，返回其偏移。
+///
+/// 用于在构建时生成API库，相当于在运行时调用生成代码中的`DynSymTable::lookup_versioned`。
+pub(crate) fn symbol_offset(elf: &ElfFile, name: &str, version: &str) -> Option<u64> {
+    let dynsym = match elf.find_section_by_name(".dynsym")?.get_data(elf) {
+        Ok(SectionData::DynSymbolTable64(dynsym)) => dynsym,
+        _ => panic!("Invalid data in .dynsym section"),
+    };
+    let versym = elf
+        .find_section_by_name(".gnu.version")
+        .map(|s| s.raw_data(elf));
+    let verdef = elf
+        .find_section_by_name(".gnu.version_d")
+        .map(|s| s.raw_data(elf));
+    let u16_at = |data: &[u8], offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+    let u32_at = |data: &[u8], offset: usize| {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    };
+    // 版本索引对应的版本节点名
+    let version_name = |ndx: u16| -> Option<&str> {
+        let verdef = verdef?;
+        let mut offset = 0;
+        loop {
+            if u16_at(verdef, offset + 4) == ndx {
+                let aux = offset + u32_at(verdef, offset + 12) as usize;
+                return elf.get_dyn_string(u32_at(verdef, aux)).ok();
+            }
+            let next = u32_at(verdef, offset + 16) as usize;
+            if next == 0 {
+                return None;
+            }
+            offset += next;
+        }
+    };
+    dynsym.iter().enumerate().find_map(|(index, sym)| {
+        if sym.get_name(elf).ok()? != name || sym.shndx() == SHN_UNDEF {
+            return None;
+        }
+        let matches = match versym {
+            None => true,
+            Some(versym) => version_name(u16_at(versym, index * 2) & 0x7fff) == Some(version),
+        };
+        matches.then_some(sym.value())
+    })
+}
+
+/// 生成的API库中`resolve.rs`的内容。
+pub(crate) const RESOLVE_RS: &str = r#"//! 在已映射的vDSO镜像中按名称解析导出符号。
+//!
+//! 支持`DT_GNU_HASH`和`DT_HASH`两种哈希表，优先使用`DT_GNU_HASH`。
+//! 若镜像中包含符号版本信息，可以按版本查找符号。
+
+const PT_DYNAMIC: u32 = 2;
+const DT_NULL: u64 = 0;
+const DT_HASH: u64 = 4;
+const DT_STRTAB: u64 = 5;
+const DT_SYMTAB: u64 = 6;
+const DT_GNU_HASH: u64 = 0x6fff_fef5;
+const DT_VERSYM: u64 = 0x6fff_fff0;
+const DT_VERDEF: u64 = 0x6fff_fffc;
+const VERSYM_HIDDEN: u16 = 0x8000;
+const SHN_UNDEF: u16 = 0;
+const SYM_SIZE: usize = 24;
+
+unsafe fn read<T: Copy>(addr: usize) -> T {
+    unsafe { core::ptr::read_unaligned(addr as *const T) }
+}
+
+/// 已映射的vDSO镜像的动态符号表。
+#[derive(Debug, Clone, Copy)]
+pub struct DynSymTable {
+    base: usize,
+    symtab: usize,
+    strtab: usize,
+    hash: Option<usize>,
+    gnu_hash: Option<usize>,
+    versym: Option<usize>,
+    verdef: Option<usize>,
+}
+
+impl DynSymTable {
+    /// 从已映射的vDSO镜像中找到动态段，并读取符号表、字符串表和哈希表的位置。
+    ///
+    /// 若镜像不是合法的ELF文件，或缺少动态符号表、字符串表或哈希表，则返回`None`。
+    ///
+    /// # Safety
+    ///
+    /// `base`必须为已映射的vDSO镜像的首地址，且ELF头、动态段、符号表、字符串表和哈希表均可读。
+    pub unsafe fn from_image(base: usize) -> Option<Self> {
+        let ident: [u8; 4] = unsafe { read(base) };
+        if ident != *b"\x7fELF" {
+            return None;
+        }
+        let phoff: u64 = unsafe { read(base + 32) };
+        let phentsize: u16 = unsafe { read(base + 54) };
+        let phnum: u16 = unsafe { read(base + 56) };
+        let dynamic = (0..phnum as usize).find_map(|i| {
+            let phdr = base + phoff as usize + i * phentsize as usize;
+            let p_type: u32 = unsafe { read(phdr) };
+            let p_vaddr: u64 = unsafe { read(phdr + 16) };
+            (p_type == PT_DYNAMIC).then_some(base + p_vaddr as usize)
+        })?;
+
+        let mut table = Self {
+            base,
+            symtab: 0,
+            strtab: 0,
+            hash: None,
+            gnu_hash: None,
+            versym: None,
+            verdef: None,
+        };
+        let mut entry = dynamic;
+        loop {
+            let tag: u64 = unsafe { read(entry) };
+            let value: u64 = unsafe { read(entry + 8) };
+            // 动态段中的地址一般为相对于镜像首地址的偏移，但也可能已被加载器修正为绝对地址
+            let addr = if (value as usize) < base {
+                base + value as usize
+            } else {
+                value as usize
+            };
+            match tag {
+                DT_NULL => break,
+                DT_SYMTAB => table.symtab = addr,
+                DT_STRTAB => table.strtab = addr,
+                DT_HASH => table.hash = Some(addr),
+                DT_GNU_HASH => table.gnu_hash = Some(addr),
+                DT_VERSYM => table.versym = Some(addr),
+                DT_VERDEF => table.verdef = Some(addr),
+                _ => {}
+            }
+            entry += 16;
+        }
+        if table.symtab == 0 || table.strtab == 0 || (table.hash.is_none() && table.gnu_hash.is_none()) {
+            return None;
+        }
+        Some(table)
+    }
+
+    /// 按名称查找导出符号的默认版本，返回其在当前地址空间中的地址。若符号不存在或未定义，则返回`None`。
+    pub fn lookup(&self, name: &str) -> Option<usize> {
+        self.find(name, |index| self.version_matches(index, None))
+    }
+
+    /// 按名称和版本节点（如`VDSO_1.1`）查找导出符号，返回其在当前地址空间中的地址。
+    ///
+    /// 若镜像中没有符号版本信息，则等同于[`Self::lookup`]。
+    pub fn lookup_versioned(&self, name: &str, version: &str) -> Option<usize> {
+        self.find(name, |index| self.version_matches(index, Some(version)))
+    }
+
+    /// 在哈希表中查找名称为`name`、且满足`accept`的符号。
+    fn find(&self, name: &str, accept: impl Fn(usize) -> bool) -> Option<usize> {
+        let accept = |index: usize| self.sym_name(index) == name.as_bytes() && accept(index);
+        let index = match self.gnu_hash {
+            Some(gnu_hash) => self.gnu_lookup(gnu_hash, name, accept),
+            None => self.sysv_lookup(self.hash?, name, accept),
+        }?;
+        self.sym_addr(index)
+    }
+
+    /// 第`index`个符号的版本是否为`version`；`version`为`None`时，检查其是否为默认版本。
+    fn version_matches(&self, index: usize, version: Option<&str>) -> bool {
+        let Some(versym) = self.versym else {
+            return true;
+        };
+        let versym: u16 = unsafe { read(versym + index * 2) };
+        let Some(version) = version else {
+            return versym & VERSYM_HIDDEN == 0;
+        };
+        let Some(mut verdef) = self.verdef else {
+            return false;
+        };
+        loop {
+            let vd_ndx: u16 = unsafe { read(verdef + 4) };
+            let vd_aux: u32 = unsafe { read(verdef + 12) };
+            let vd_next: u32 = unsafe { read(verdef + 16) };
+            if vd_ndx == versym & !VERSYM_HIDDEN {
+                let vda_name: u32 = unsafe { read(verdef + vd_aux as usize) };
+                return self.str_at(vda_name) == version.as_bytes();
+            }
+            if vd_next == 0 {
+                return false;
+            }
+            verdef += vd_next as usize;
+        }
+    }
+
+    /// 返回第`index`个符号的名称。
+    fn sym_name(&self, index: usize) -> &[u8] {
+        let sym = self.symtab + index * SYM_SIZE;
+        let st_name: u32 = unsafe { read(sym) };
+        self.str_at(st_name)
+    }
+
+    /// 返回字符串表中偏移为`offset`的字符串。
+    fn str_at(&self, offset: u32) -> &[u8] {
+        let start = self.strtab + offset as usize;
+        let mut len = 0;
+        while unsafe { read::<u8>(start + len) } != 0 {
+            len += 1;
+        }
+        unsafe { core::slice::from_raw_parts(start as *const u8, len) }
+    }
+
+    /// 返回第`index`个符号的地址。
+    fn sym_addr(&self, index: usize) -> Option<usize> {
+        let sym = self.symtab + index * SYM_SIZE;
+        let st_shndx: u16 = unsafe { read(sym + 6) };
+        let st_value: u64 = unsafe { read(sym + 8) };
+        (st_shndx != SHN_UNDEF).then_some(self.base + st_value as usize)
+    }
+
+    fn sysv_lookup(&self, hash_table: usize, name: &str, accept: impl Fn(usize) -> bool) -> Option<usize> {
+        let mut hash: u32 = 0;
+        for &byte in name.as_bytes() {
+            hash = (hash << 4).wrapping_add(byte as u32);
+            let high = hash & 0xf000_0000;
+            if high != 0 {
+                hash ^= high >> 24;
+            }
+            hash &= !high;
+        }
+        let nbucket: u32 = unsafe { read(hash_table) };
+        let buckets = hash_table + 8;
+        let chains = buckets + nbucket as usize * 4;
+        let mut index: u32 = unsafe { read(buckets + (hash % nbucket) as usize * 4) };
+        while index != 0 {
+            if accept(index as usize) {
+                return Some(index as usize);
+            }
+            index = unsafe { read(chains + index as usize * 4) };
+        }
+        None
+    }
+
+    fn gnu_lookup(&self, hash_table: usize, name: &str, accept: impl Fn(usize) -> bool) -> Option<usize> {
+        let mut hash: u32 = 5381;
+        for &byte in name.as_bytes() {
+            hash = hash.wrapping_mul(33).wrapping_add(byte as u32);
+        }
+        let nbuckets: u32 = unsafe { read(hash_table) };
+        let symoffset: u32 = unsafe { read(hash_table + 4) };
+        let bloom_size: u32 = unsafe { read(hash_table + 8) };
+        let buckets = hash_table + 16 + bloom_size as usize * 8;
+        let chains = buckets + nbuckets as usize * 4;
+        let mut index: u32 = unsafe { read(buckets + (hash % nbuckets) as usize * 4) };
+        if index < symoffset {
+            return None;
+        }
+        loop {
+            let chain_hash: u32 = unsafe { read(chains + (index - symoffset) as usize * 4) };
+            if (chain_hash | 1) == (hash | 1) && accept(index as usize) {
+                return Some(index as usize);
+            }
+            if chain_hash & 1 != 0 {
+                return None;
+            }
+            index += 1;
+        }
+    }
+}
+"#;
@@ -0,0 +1,1908 @@
+//! 扫描vDSO库的源代码，找出需要导出的API和依赖接口。
+//!
+//! 从`src/lib.rs`出发沿模块树遍历所有源文件，收集：
+//!
+//! - 任意模块中以`#[vdso_api]`标记的函数；
+//! - 任意模块中以`#[vdso_async]`标记的`async fn`，以及它实际导出的各个入口；
+//! - 任意模块中以`#[vdso_api(instances = "...")]`标记的泛型函数，以及它的各个实例；
+//! - 任意模块中以`#[vdso_impl]`标记的`impl`块中的`pub`方法，以及为它们导出的函数；
+//! - `api`模块（及其子模块）中以`#[unsafe(no_mangle)]`声明的`extern "C"`函数；
+//! - `api`模块中`extern "C" {}`块内声明的`fn xxx() -> !;`函数；
+//! - 任意模块中由`trait_interface!`声明的依赖接口。
+//!
+//! `#[vdso_api]`的`since`、`compat`和`symbol`参数（`#[vdso_async]`和`#[vdso_impl]`只支持`since`）决定了API所属的ABI版本，见[`crate::version`]；
+//! `module`参数决定了API所属的模块，每个模块链接为单独的so文件。
+//!
+//! 源代码使用`syn`解析，并根据[`BuildConfig`]求值`#[cfg(...)]`属性，被禁用的项不会被导出。
+//! 无法通过C ABI导出的API（例如未列出实例的泛型函数）会导致构建失败，错误信息中包含函数名和所在文件。
+//!
+//! 扫描时同时收集vDSO库中定义的结构体、枚举、联合体和类型别名，用于检查API的参数和返回值类型能否通过C ABI传递：
+//! 元组、非指针类型的`Option`和`Result`、`&str`、切片以及未声明`#[repr(C)]`的类型都会导致构建失败。
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use quote::ToTokens;
+use syn::{
+    parse::Parser, punctuated::Punctuated, Attribute, FnArg, ForeignItem, GenericParam, Generics,
+    ImplItem, Item, ItemImpl, ItemMacro, ItemTrait, Meta, Pat, ReturnType, Signature, Token,
+    TraitItem, Visibility,
+};
+
+use crate::{
+    version::{abi_version, AbiVersion},
+    BuildConfig,
+};
+
+/// vDSO库中的一个API函数。
+#[derive(Clone)]
+pub(crate) struct ApiFn {
+    /// 函数名，也是so文件中导出的符号名
+    pub name: String,
+    /// 函数上的文档注释，每一项为一行`#[doc = "..."]`
+    pub docs: Vec<String>,
+    /// 函数声明的生命周期参数，如`'a`
+    pub lifetimes: Vec<String>,
+    /// 是否为`unsafe`函数
+    pub is_unsafe: bool,
+    /// 参数名和参数类型
+    pub args: Vec<(String, String)>,
+    /// 返回值类型，无返回值时为`None`
+    pub ret: Option<String>,
+    /// 所属的ABI版本
+    pub version: AbiVersion,
+    /// 所属的模块，`None`表示主模块
+    pub module: Option<String>,
+}
+
+impl ApiFn {
+    /// 参数类型均为`usize`、无返回值的函数，用于`trait_interface!`生成的`init_vtable_*`等函数。
+    pub fn with_usize_args(name: &str, arg_names: &[String]) -> Self {
+        Self {
+            name: name.to_string(),
+            docs: Vec::new(),
+            lifetimes: Vec::new(),
+            is_unsafe: false,
+            args: arg_names
+                .iter()
+                .map(|arg| (arg.clone(), "usize".to_string()))
+                .collect(),
+            ret: None,
+            version: AbiVersion::BASE,
+            module: None,
+        }
+    }
+
+    /// 生命周期参数列表，如`<'a>`；无生命周期参数时为空字符串。
+    pub fn generics_str(&self) -> String {
+        if self.lifetimes.is_empty() {
+            String::new()
+        } else {
+            format!("<{}>", self.lifetimes.join(", "))
+        }
+    }
+
+    /// 参数列表和返回值，如`(a: usize) -> usize`。
+    pub fn params_str(&self) -> String {
+        format!("({}){}", self.args_decl_str(), self.ret_str())
+    }
+
+    /// 作为`VdsoInstance`的方法时的参数列表和返回值，如`(&self, a: usize) -> usize`。
+    pub fn method_params_str(&self) -> String {
+        format!("({}){}", self.method_args_decl_str(), self.ret_str())
+    }
+
+    /// `try_`版本的参数列表和返回值，如`(a: usize) -> Result<usize, VdsoError>`。
+    pub fn try_params_str(&self) -> String {
+        format!("({}){}", self.args_decl_str(), self.try_ret_str())
+    }
+
+    /// `try_`版本作为`VdsoInstance`的方法时的参数列表和返回值，如`(&self, a: usize) -> Result<usize, VdsoError>`。
+    pub fn try_method_params_str(&self) -> String {
+        format!("({}){}", self.method_args_decl_str(), self.try_ret_str())
+    }
+
+    /// 参数声明列表，如`a: usize, b: usize`。
+    pub fn args_decl_str(&self) -> String {
+        self.args
+            .iter()
+            .map(|(name, ty)| format!("{}: {}", name, ty))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// 作为`VdsoInstance`的方法时的参数声明列表，如`&self, a: usize`。
+    pub fn method_args_decl_str(&self) -> String {
+        let args: String = self
+            .args
+            .iter()
+            .map(|(name, ty)| format!(", {}: {}", name, ty))
+            .collect();
+        format!("&self{}", args)
+    }
+
+    /// 调用时传入的实参列表，如`a, b`。
+    pub fn call_args_str(&self) -> String {
+        self.args
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// 指向该函数的函数指针类型，如`for<'a> unsafe extern "C" fn(&'a usize) -> usize`。
+    pub fn fn_ptr_type(&self) -> String {
+        let for_lifetimes = if self.lifetimes.is_empty() {
+            String::new()
+        } else {
+            format!("for<{}> ", self.lifetimes.join(", "))
+        };
+        let unsafety = if self.is_unsafe { "unsafe " } else { "" };
+        let args = self
+            .args
+            .iter()
+            .map(|(_, ty)| ty.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            "{}{}extern \"C\" fn({}){}",
+            for_lifetimes,
+            unsafety,
+            args,
+            self.ret_str()
+        )
+    }
+
+    fn ret_str(&self) -> String {
+        match &self.ret {
+            Some(ret) => format!(" -> {}", ret),
+            None => String::new(),
+        }
+    }
+
+    fn try_ret_str(&self) -> String {
+        format!(
+            " -> Result<{}, VdsoError>",
+            self.ret.as_deref().unwrap_or("()")
+        )
+    }
+
+    /// `#[vdso_async]`函数实际导出的各个入口，与`vdso_async`宏生成的函数一一对应。
+    pub fn async_entries(&self) -> Vec<ApiFn> {
+        let state = || ("state".to_string(), "*mut u8".to_string());
+        let output = format!("*mut {}", self.ret.as_deref().unwrap_or("()"));
+        vec![
+            self.async_entry(
+                "state_size",
+                format!(" `{}`返回的Future的大小。", self.name),
+                false,
+                Vec::new(),
+                Some("usize"),
+            ),
+            self.async_entry(
+                "state_align",
+                format!(" `{}`返回的Future的对齐。", self.name),
+                false,
+                Vec::new(),
+                Some("usize"),
+            ),
+            self.async_entry(
+                "init_state",
+                format!(" 调用`{}`，将返回的Future写入`state`。", self.name),
+                true,
+                [state()]
+                    .into_iter()
+                    .chain(self.args.iter().cloned())
+                    .collect(),
+                None,
+            ),
+            self.async_entry(
+                "poll",
+                format!(
+                    " 推进`state`中由`{}`返回的Future，完成时将结果写入`output`并返回`true`。",
+                    self.name
+                ),
+                true,
+                vec![
+                    state(),
+                    ("waker_data".to_string(), "*const ()".to_string()),
+                    (
+                        "waker_vtable".to_string(),
+                        "*const core::task::RawWakerVTable".to_string(),
+                    ),
+                    ("output".to_string(), output),
+                ],
+                Some("bool"),
+            ),
+            self.async_entry(
+                "drop_state",
+                format!(" 析构`state`中由`{}`返回的、尚未完成的Future。", self.name),
+                true,
+                vec![state()],
+                None,
+            ),
+        ]
+    }
+
+    fn async_entry(
+        &self,
+        suffix: &str,
+        doc: String,
+        is_unsafe: bool,
+        args: Vec<(String, String)>,
+        ret: Option<&str>,
+    ) -> ApiFn {
+        ApiFn {
+            name: format!("{}_{}", self.name, suffix),
+            docs: vec![format!("#[doc = {:?}]", doc)],
+            lifetimes: Vec::new(),
+            is_unsafe,
+            args,
+            ret: ret.map(String::from),
+            version: self.version,
+            module: self.module.clone(),
+        }
+    }
+}
+
+/// 以`#[vdso_api(instances = "...")]`标记的泛型API。
+#[derive(Clone)]
+pub(crate) struct GenericFn {
+    /// 泛型函数的签名，参数和返回值类型中包含类型参数，`lifetimes`为空
+    pub api: ApiFn,
+    /// 类型参数名
+    pub params: Vec<String>,
+    /// 各个实例
+    pub instances: Vec<GenericInstance>,
+}
+
+/// 泛型API的一个实例。
+#[derive(Clone)]
+pub(crate) struct GenericInstance {
+    /// 导出的符号名
+    pub symbol: String,
+    /// 类型实参，与[`GenericFn::params`]一一对应
+    pub types: Vec<String>,
+}
+
+impl GenericFn {
+    /// 实例`instance`实际导出的函数，与`vdso_api`宏为其生成的函数相同。
+    pub fn instance_fn(&self, instance: &GenericInstance) -> ApiFn {
+        let substitute = |ty: &String| substitute_type(ty, &self.params, &instance.types);
+        ApiFn {
+            name: instance.symbol.clone(),
+            docs: vec!["#[doc(hidden)]".to_string()],
+            args: self
+                .api
+                .args
+                .iter()
+                .map(|(name, ty)| (name.clone(), substitute(ty)))
+                .collect(),
+            ret: self.api.ret.as_ref().map(substitute),
+            ..self.api.clone()
+        }
+    }
+}
+
+/// 将类型`ty`中的类型参数`params`替换为对应的`with`，如将`Option<T>`替换为`Option<u32>`。
+pub(crate) fn substitute_type(ty: &str, params: &[String], with: &[String]) -> String {
+    struct Substitute<'a>(&'a [String], &'a [String]);
+    impl syn::visit_mut::VisitMut for Substitute<'_> {
+        fn visit_type_mut(&mut self, ty: &mut syn::Type) {
+            if let syn::Type::Path(syn::TypePath { qself: None, path }) = ty {
+                if let Some(index) = self.0.iter().position(|param| path.is_ident(param)) {
+                    *ty = syn::parse_str(&self.1[index]).unwrap();
+                    return;
+                }
+            }
+            syn::visit_mut::visit_type_mut(self, ty);
+        }
+    }
+    let mut ty: syn::Type = syn::parse_str(ty).unwrap();
+    syn::visit_mut::VisitMut::visit_type_mut(&mut Substitute(params, with), &mut ty);
+    ty.to_token_stream().to_string()
+}
+
+/// 以`#[vdso_impl]`标记的`impl`块中导出的一个方法。
+#[derive(Clone)]
+pub(crate) struct ImplMethod {
+    /// 类型名
+    pub ty: String,
+    /// 接收者，如`&self`、`&'a mut self`、`self`，关联函数为`None`
+    pub receiver: Option<String>,
+    /// 方法的签名，`name`为方法名，`args`中不含接收者，参数和返回值类型中的`Self`保持不变
+    pub api: ApiFn,
+}
+
+impl ImplMethod {
+    /// 为该方法导出的符号名：`<类型名>__<方法名>`，规则需与`vdso_impl`宏中的相同。
+    pub fn symbol(&self) -> String {
+        format!("{}__{}", self.ty, self.api.name)
+    }
+
+    /// `vdso_impl`宏为该方法导出的函数：接收者作为第一个参数`this`，类型中的`Self`替换为类型名。
+    pub fn shim_fn(&self) -> ApiFn {
+        let params = ["Self".to_string()];
+        let with = [self.ty.clone()];
+        let substitute = |ty: &String| substitute_type(ty, &params, &with);
+        // 接收者总是以`self`结尾，如`&'a mut self`对应的参数类型为`&'a mut <类型名>`
+        let this = self.receiver.as_ref().map(|receiver| {
+            let ty = format!("{}Self", receiver.strip_suffix("self").unwrap());
+            ("this".to_string(), substitute(&ty))
+        });
+        ApiFn {
+            name: self.symbol(),
+            docs: vec!["#[doc(hidden)]".to_string()],
+            args: this
+                .into_iter()
+                .chain(
+                    self.api
+                        .args
+                        .iter()
+                        .map(|(name, ty)| (name.clone(), substitute(ty))),
+                )
+                .collect(),
+            ret: self.api.ret.as_ref().map(substitute),
+            ..self.api.clone()
+        }
+    }
+}
+
+/// vDSO库中由`trait_interface!`声明的一个依赖接口。
+pub(crate) struct InterfaceTrait {
+    /// trait名
+    pub name: String,
+    /// trait中各个函数的函数名，按声明顺序排列
+    pub fns: Vec<String>,
+}
+
+impl InterfaceTrait {
+    /// 在vDSO中注册该接口的函数`init_vtable_$name`。
+    pub fn init_fn(&self) -> ApiFn {
+        ApiFn::with_usize_args(&format!("init_vtable_{}", self.name), &self.fns)
+    }
+}
+
+/// 扫描vDSO库得到的全部API和依赖接口。
+pub(crate) struct VdsoItems {
+    pub fns: Vec<ApiFn>,
+    pub traits: Vec<InterfaceTrait>,
+    /// 以`#[vdso_api(compat = "...", symbol = "...")]`保留的旧版本入口，`name`为导出的符号名。
+    ///
+    /// 它们只出现在版本脚本和ABI指纹中，不会生成调用接口。
+    pub compat_fns: Vec<ApiFn>,
+    /// 以`#[vdso_async]`标记的`async fn`，`ret`为Future的输出类型。
+    ///
+    /// 它们实际导出的入口（见[`ApiFn::async_entries`]）已加入`fns`中。
+    pub async_fns: Vec<ApiFn>,
+    /// 以`#[vdso_api(instances = "...")]`标记的泛型函数。
+    ///
+    /// 它们的各个实例（见[`GenericFn::instance_fn`]）已加入`fns`中。
+    pub generic_fns: Vec<GenericFn>,
+    /// 以`#[vdso_impl]`标记的`impl`块中导出的方法。
+    ///
+    /// 为它们导出的函数（见[`ImplMethod::shim_fn`]）已加入`fns`中。
+    pub methods: Vec<ImplMethod>,
+}
+
+impl VdsoItems {
+    /// 除主模块外的所有模块名，按名称排序。
+    pub fn modules(&self) -> Vec<String> {
+        let mut modules: Vec<String> = self
+            .fns
+            .iter()
+            .chain(self.compat_fns.iter())
+            .filter_map(|f| f.module.clone())
+            .collect();
+        modules.sort();
+        modules.dedup();
+        modules
+    }
+
+    /// 版本脚本中的所有版本节点，从旧到新排列，最新的为`abi_version`。
+    pub fn versions(&self, abi_version: AbiVersion) -> Vec<AbiVersion> {
+        let mut versions: Vec<AbiVersion> = self
+            .fns
+            .iter()
+            .chain(self.compat_fns.iter())
+            .map(|f| f.version)
+            .chain([AbiVersion::BASE, abi_version])
+            .collect();
+        versions.sort();
+        versions.dedup();
+        versions
+    }
+}
+
+/// 扫描`config.src_dir`中的vDSO库。
+///
+/// 若存在无法导出的API，则panic，并列出所有出错的函数。
+pub(crate) fn scan_vdso(config: &BuildConfig) -> VdsoItems {
+    let src_dir = Path::new(&config.src_dir).join("src");
+    let mut scanner = Scanner {
+        config,
+        items: VdsoItems {
+            fns: Vec::new(),
+            traits: Vec::new(),
+            compat_fns: Vec::new(),
+            async_fns: Vec::new(),
+            generic_fns: Vec::new(),
+            methods: Vec::new(),
+        },
+        versioned: Vec::new(),
+        type_defs: HashMap::new(),
+        errors: Vec::new(),
+    };
+    scanner.scan_file(&src_dir.join("lib.rs"), &src_dir, &[]);
+    scanner.check_versions();
+    scanner.check_ffi();
+    if !scanner.errors.is_empty() {
+        panic!(
+            "Invalid vDSO API declarations:\n{}",
+            scanner.errors.join("\n")
+        );
+    }
+    scanner.items
+}
+
+struct Scanner<'a> {
+    config: &'a BuildConfig,
+    items: VdsoItems,
+    /// 以`since`指定了版本的API
+    versioned: Vec<String>,
+    /// vDSO库中定义的类型，按类型名索引
+    type_defs: HashMap<String, TypeDef>,
+    errors: Vec<String>,
+}
+
+impl Scanner<'_> {
+    /// 检查各个API的版本：不能超过`abi_version`；旧版本入口必须有更新的、以`since`声明的当前定义。
+    fn check_versions(&mut self) {
+        let abi_version = abi_version(self.config);
+        for f in self.items.fns.iter().chain(self.items.compat_fns.iter()) {
+            if f.version > abi_version {
+                self.errors.push(format!(
+                    "  `{}`: version {} is newer than the ABI version {}",
+                    f.name, f.version, abi_version
+                ));
+            }
+        }
+        for (index, compat) in self.items.compat_fns.iter().enumerate() {
+            let current = self.items.fns.iter().find(|f| f.name == compat.name);
+            let error = match current {
+                None => Some("no current definition of this symbol is exported".to_string()),
+                Some(_) if !self.versioned.contains(&compat.name) => Some(
+                    "the current definition of this symbol must specify `since`".to_string(),
+                ),
+                Some(current) if current.version <= compat.version => Some(format!(
+                    "the current definition (version {}) must be newer than the compatibility entry",
+                    current.version
+                )),
+                Some(_)
+                    if self.items.compat_fns[..index]
+                        .iter()
+                        .any(|f| f.name == compat.name && f.version == compat.version) =>
+                {
+                    Some("duplicate compatibility entry".to_string())
+                }
+                Some(_) => None,
+            };
+            if let Some(error) = error {
+                self.errors.push(format!(
+                    "  `{}@{}`: {}",
+                    compat.name,
+                    compat.version.node_name(),
+                    error
+                ));
+            }
+        }
+    }
+
+    /// 检查各个API的参数和返回值类型能否通过C ABI传递。
+    ///
+    /// 泛型API的实例和`#[vdso_impl]`方法的导出函数中，类型参数和`Self`已经替换为实际的类型。
+    fn check_ffi(&mut self) {
+        for f in self.items.fns.iter().chain(self.items.compat_fns.iter()) {
+            for (name, ty) in f.args.iter() {
+                if let Err(e) = check_ffi_type_str(ty, FfiPosition::Arg, &self.type_defs) {
+                    self.errors.push(format!(
+                        "  `{}`: parameter `{}` has type `{}`, which is not FFI-safe: {}",
+                        f.name, name, ty, e
+                    ));
+                }
+            }
+            if let Some(ty) = &f.ret {
+                if let Err(e) = check_ffi_type_str(ty, FfiPosition::Ret, &self.type_defs) {
+                    self.errors.push(format!(
+                        "  `{}`: return type `{}` is not FFI-safe: {}",
+                        f.name, ty, e
+                    ));
+                }
+            }
+        }
+    }
+
+    /// 记录vDSO库中定义的类型。同名的类型只记录第一个。
+    fn add_type_def(&mut self, attrs: &[Attribute], name: &syn::Ident, def: TypeDef) {
+        if self.cfg_enabled_quiet(attrs) {
+            self.type_defs.entry(name.to_string()).or_insert(def);
+        }
+    }
+
+    /// 解析一个源文件。
+    ///
+    /// - `dir`: 该文件中`mod xxx;`声明的子模块所在的目录
+    /// - `mod_path`: 该文件对应的模块路径（不含crate根）
+    fn scan_file(&mut self, path: &Path, dir: &Path, mod_path: &[String]) {
+        println!("cargo:rerun-if-changed={}", path.display());
+        let source = fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e));
+        let file = syn::parse_file(&source)
+            .unwrap_or_else(|e| panic!("Failed to parse {}: {}", path.display(), e));
+        self.scan_items(&file.items, path, dir, mod_path);
+    }
+
+    fn scan_items(&mut self, file_items: &[Item], file: &Path, dir: &Path, mod_path: &[String]) {
+        let in_api_mod = mod_path.first().is_some_and(|m| m == "api");
+        for item in file_items {
+            match item {
+                Item::Fn(item_fn) if has_attr(&item_fn.attrs, "vdso_async") => {
+                    if self.cfg_enabled(&item_fn.attrs, file, &item_fn.sig.ident) {
+                        match parse_async_fn(&item_fn.sig, &item_fn.attrs) {
+                            Ok((api_fn, since)) => {
+                                if since {
+                                    self.versioned.push(api_fn.name.clone());
+                                }
+                                self.items.fns.extend(api_fn.async_entries());
+                                self.items.async_fns.push(api_fn);
+                            }
+                            Err(e) => self.errors.push(format!(
+                                "  `{}` ({}): {}",
+                                item_fn.sig.ident,
+                                file.display(),
+                                e
+                            )),
+                        }
+                    }
+                }
+                Item::Fn(item_fn) if has_instances(&item_fn.attrs) => {
+                    if self.cfg_enabled(&item_fn.attrs, file, &item_fn.sig.ident) {
+                        match parse_generic_fn(&item_fn.sig, &item_fn.attrs) {
+                            Ok(generic_fn) => {
+                                self.items.fns.extend(
+                                    generic_fn
+                                        .instances
+                                        .iter()
+                                        .map(|instance| generic_fn.instance_fn(instance)),
+                                );
+                                self.items.generic_fns.push(generic_fn);
+                            }
+                            Err(e) => self.errors.push(format!(
+                                "  `{}` ({}): {}",
+                                item_fn.sig.ident,
+                                file.display(),
+                                e
+                            )),
+                        }
+                    }
+                }
+                Item::Fn(item_fn) => {
+                    let is_api = has_attr(&item_fn.attrs, "vdso_api")
+                        || (in_api_mod
+                            && is_no_mangle(&item_fn.attrs)
+                            && item_fn.sig.abi.is_some());
+                    if is_api && self.cfg_enabled(&item_fn.attrs, file, &item_fn.sig.ident) {
+                        let parsed =
+                            parse_api_fn(&item_fn.sig, &item_fn.attrs).and_then(|mut api_fn| {
+                                api_fn.module = vdso_api_module(&item_fn.attrs)?;
+                                Ok((api_fn, vdso_api_version(&item_fn.attrs)?))
+                            });
+                        match parsed {
+                            Ok((api_fn, ApiVersion::Base)) => self.items.fns.push(api_fn),
+                            Ok((mut api_fn, ApiVersion::Since(version))) => {
+                                api_fn.version = version;
+                                self.versioned.push(api_fn.name.clone());
+                                self.items.fns.push(api_fn);
+                            }
+                            Ok((mut api_fn, ApiVersion::Compat { symbol, version })) => {
+                                api_fn.name = symbol;
+                                api_fn.version = version;
+                                self.items.compat_fns.push(api_fn);
+                            }
+                            Err(e) => self.errors.push(format!(
+                                "  `{}` ({}): {}",
+                                item_fn.sig.ident,
+                                file.display(),
+                                e
+                            )),
+                        }
+                    }
+                }
+                Item::Impl(item_impl) if has_attr(&item_impl.attrs, "vdso_impl") => {
+                    let name = item_impl.self_ty.to_token_stream();
+                    if self.cfg_enabled(&item_impl.attrs, file, &name) {
+                        self.scan_impl(item_impl, file);
+                    }
+                }
+                Item::ForeignMod(foreign) if in_api_mod => {
+                    if !self.cfg_enabled(&foreign.attrs, file, &"extern block") {
+                        continue;
+                    }
+                    for foreign_item in &foreign.items {
+                        if let ForeignItem::Fn(foreign_fn) = foreign_item {
+                            let sig = &foreign_fn.sig;
+                            let diverges = matches!(&sig.output, ReturnType::Type(_, ty) if matches!(**ty, syn::Type::Never(_)));
+                            if sig.inputs.is_empty()
+                                && diverges
+                                && self.cfg_enabled(&foreign_fn.attrs, file, &sig.ident)
+                            {
+                                self.items.fns.push(ApiFn {
+                                    name: sig.ident.to_string(),
+                                    docs: doc_attrs(&foreign_fn.attrs),
+                                    lifetimes: Vec::new(),
+                                    is_unsafe: false,
+                                    args: Vec::new(),
+                                    ret: Some("!".into()),
+                                    version: AbiVersion::BASE,
+                                    module: None,
+                                });
+                            }
+                        }
+                    }
+                }
+                Item::Macro(item_macro) => {
+                    let Some(item_trait) = parse_trait_interface(item_macro, file) else {
+                        continue;
+                    };
+                    if !self.cfg_enabled(&item_macro.attrs, file, &item_trait.ident) {
+                        continue;
+                    }
+                    self.items.traits.push(InterfaceTrait {
+                        name: item_trait.ident.to_string(),
+                        fns: item_trait
+                            .items
+                            .iter()
+                            .filter_map(|trait_item| match trait_item {
+                                TraitItem::Fn(trait_fn) => Some(trait_fn.sig.ident.to_string()),
+                                _ => None,
+                            })
+                            .collect(),
+                    });
+                }
+                Item::Struct(item_struct) => {
+                    let def = TypeDef::Aggregate {
+                        is_enum: false,
+                        repr_c: has_repr_c(&item_struct.attrs, false),
+                        params: type_params(&item_struct.generics),
+                        fields: item_struct.fields.iter().map(|f| f.ty.clone()).collect(),
+                    };
+                    self.add_type_def(&item_struct.attrs, &item_struct.ident, def);
+                }
+                Item::Union(item_union) => {
+                    let def = TypeDef::Aggregate {
+                        is_enum: false,
+                        repr_c: has_repr_c(&item_union.attrs, false),
+                        params: type_params(&item_union.generics),
+                        fields: item_union
+                            .fields
+                            .named
+                            .iter()
+                            .map(|f| f.ty.clone())
+                            .collect(),
+                    };
+                    self.add_type_def(&item_union.attrs, &item_union.ident, def);
+                }
+                Item::Enum(item_enum) => {
+                    let def = TypeDef::Aggregate {
+                        is_enum: true,
+                        repr_c: has_repr_c(&item_enum.attrs, true),
+                        params: type_params(&item_enum.generics),
+                        fields: item_enum
+                            .variants
+                            .iter()
+                            .flat_map(|v| v.fields.iter().map(|f| f.ty.clone()))
+                            .collect(),
+                    };
+                    self.add_type_def(&item_enum.attrs, &item_enum.ident, def);
+                }
+                Item::Type(item_type) => {
+                    let def = TypeDef::Alias {
+                        params: type_params(&item_type.generics),
+                        ty: (*item_type.ty).clone(),
+                    };
+                    self.add_type_def(&item_type.attrs, &item_type.ident, def);
+                }
+                Item::Mod(item_mod) => {
+                    if !self.cfg_enabled(&item_mod.attrs, file, &item_mod.ident) {
+                        continue;
+                    }
+                    let name = item_mod.ident.to_string();
+                    let mut sub_mod_path = mod_path.to_vec();
+                    sub_mod_path.push(name.clone());
+                    let path_attr = path_attr(&item_mod.attrs);
+                    match &item_mod.content {
+                        Some((_, sub_items)) => {
+                            let sub_dir = dir.join(path_attr.unwrap_or(name));
+                            self.scan_items(sub_items, file, &sub_dir, &sub_mod_path);
+                        }
+                        None => {
+                            let (sub_file, sub_dir) = resolve_mod_file(dir, &name, path_attr);
+                            self.scan_file(&sub_file, &sub_dir, &sub_mod_path);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// 解析以`#[vdso_impl]`标记的`impl`块，收集其中的`pub`方法。
+    fn scan_impl(&mut self, item_impl: &ItemImpl, file: &Path) {
+        let ty = match parse_impl_type(item_impl) {
+            Ok(ty) => ty,
+            Err(e) => {
+                self.errors.push(format!(
+                    "  `impl {}` ({}): {}",
+                    item_impl.self_ty.to_token_stream(),
+                    file.display(),
+                    e
+                ));
+                return;
+            }
+        };
+        for impl_item in item_impl.items.iter() {
+            let ImplItem::Fn(method) = impl_item else {
+                continue;
+            };
+            if !matches!(method.vis, Visibility::Public(_))
+                || !self.cfg_enabled(&method.attrs, file, &method.sig.ident)
+            {
+                continue;
+            }
+            match parse_impl_method(&ty, &method.sig, &method.attrs, &item_impl.attrs) {
+                Ok((method, since)) => {
+                    let shim = method.shim_fn();
+                    if since {
+                        self.versioned.push(shim.name.clone());
+                    }
+                    self.items.fns.push(shim);
+                    self.items.methods.push(method);
+                }
+                Err(e) => self.errors.push(format!(
+                    "  `{}::{}` ({}): {}",
+                    ty,
+                    method.sig.ident,
+                    file.display(),
+                    e
+                )),
+            }
+        }
+    }
+
+    /// 求值`attrs`中所有的`#[cfg(...)]`，全部为真时返回`true`。
+    ///
+    /// 无法求值的条件会记录为错误，并视为`true`。
+    fn cfg_enabled(&mut self, attrs: &[Attribute], file: &Path, item: &dyn ToString) -> bool {
+        let mut enabled = true;
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("cfg")) {
+            let result = attr
+                .parse_args::<Meta>()
+                .map_err(|e| e.to_string())
+                .and_then(|meta| self.eval_cfg(&meta));
+            match result {
+                Ok(value) => enabled &= value,
+                Err(e) => self.errors.push(format!(
+                    "  `{}` ({}): cannot evaluate {}: {}",
+                    item.to_string(),
+                    file.display(),
+                    attr.to_token_stream(),
+                    e
+                )),
+            }
+        }
+        enabled
+    }
+
+    /// 与[`Scanner::cfg_enabled`]相同，但无法求值的条件不记录为错误。
+    ///
+    /// 用于收集类型定义：类型本身不是API，其上的`#[cfg(...)]`不应导致构建失败。
+    fn cfg_enabled_quiet(&self, attrs: &[Attribute]) -> bool {
+        attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("cfg"))
+            .all(|attr| {
+                attr.parse_args::<Meta>()
+                    .map_err(|e| e.to_string())
+                    .and_then(|meta| self.eval_cfg(&meta))
+                    .unwrap_or(true)
+            })
+    }
+
+    fn eval_cfg(&self, meta: &Meta) -> Result<bool, String> {
+        match meta {
+            Meta::List(list) => {
+                let nested = list
+                    .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                    .map_err(|e| e.to_string())?;
+                let mut values = nested.iter().map(|meta| self.eval_cfg(meta));
+                if list.path.is_ident("all") {
+                    values.try_fold(true, |acc, v| Ok(acc & v?))
+                } else if list.path.is_ident("any") {
+                    values.try_fold(false, |acc, v| Ok(acc | v?))
+                } else if list.path.is_ident("not") {
+                    if nested.len() != 1 {
+                        return Err("`not` takes exactly one predicate".into());
+                    }
+                    Ok(!values.next().unwrap()?)
+                } else {
+                    Err(format!(
+                        "unsupported predicate `{}`",
+                        list.path.to_token_stream()
+                    ))
+                }
+            }
+            Meta::NameValue(name_value) => {
+                let value = match &name_value.value {
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(s),
+                        ..
+                    }) => s.value(),
+                    _ => return Err("expected a string literal".into()),
+                };
+                let key = name_value.path.to_token_stream().to_string();
+                match key.as_str() {
+                    "feature" => Ok(self.config.features.contains(&value)),
+                    "target_arch" => Ok(self.config.arch == value),
+                    "target_os" => Ok(value == "none"),
+                    "target_family" => Ok(false),
+                    "target_env" => Ok(value.is_empty()),
+                    "target_vendor" => Ok(value == "unknown"),
+                    "target_pointer_width" => Ok(value == "64"),
+                    "panic" => Ok(value == "abort"),
+                    _ => Err(format!("unsupported cfg key `{}`", key)),
+                }
+            }
+            Meta::Path(path) => {
+                if path.is_ident("debug_assertions") {
+                    Ok(self.config.mode == "debug")
+                } else if ["test", "doc", "unix", "windows"]
+                    .iter()
+                    .any(|name| path.is_ident(name))
+                {
+                    Ok(false)
+                } else {
+                    Err(format!("unsupported cfg `{}`", path.to_token_stream()))
+                }
+            }
+        }
+    }
+}
+
+/// 从函数签名中解析API，签名无法通过C ABI导出时返回错误原因。
+fn parse_api_fn(sig: &Signature, attrs: &[Attribute]) -> Result<ApiFn, String> {
+    if let Some(abi) = &sig.abi {
+        if abi.name.as_ref().is_some_and(|name| name.value() != "C") {
+            return Err("only the \"C\" calling convention is supported".into());
+        }
+    }
+    if sig.asyncness.is_some() {
+        return Err("`async fn` cannot be exported".into());
+    }
+    if sig.constness.is_some() {
+        return Err("`const fn` cannot be exported".into());
+    }
+    if sig.variadic.is_some() {
+        return Err("variadic functions cannot be exported".into());
+    }
+    let mut lifetimes = Vec::new();
+    for param in &sig.generics.params {
+        match param {
+            GenericParam::Lifetime(lifetime) => {
+                if !lifetime.bounds.is_empty() {
+                    return Err("lifetime bounds are not supported".into());
+                }
+                lifetimes.push(lifetime.lifetime.to_string());
+            }
+            GenericParam::Type(ty) => {
+                return Err(format!(
+                    "generic type parameter `{}` is not supported, list the instances with `#[vdso_api(instances = \"...\")]`",
+                    ty.ident
+                ))
+            }
+            GenericParam::Const(c) => {
+                return Err(format!("const generic parameter `{}` is not supported", c.ident))
+            }
+        }
+    }
+    if sig.generics.where_clause.is_some() && !lifetimes.is_empty() {
+        return Err("`where` clauses are not supported".into());
+    }
+
+    let mut args = Vec::new();
+    for (index, input) in sig.inputs.iter().enumerate() {
+        let pat_type = match input {
+            FnArg::Receiver(_) => return Err("`self` parameters are not supported".into()),
+            FnArg::Typed(pat_type) => pat_type,
+        };
+        let name = match &*pat_type.pat {
+            Pat::Ident(pat_ident) if pat_ident.subpat.is_none() => pat_ident.ident.to_string(),
+            Pat::Wild(_) => format!("arg{}", index),
+            pat => {
+                return Err(format!(
+                    "unsupported pattern `{}` in parameter {}",
+                    pat.to_token_stream(),
+                    index
+                ))
+            }
+        };
+        check_type(&pat_type.ty)?;
+        args.push((name, pat_type.ty.to_token_stream().to_string()));
+    }
+    let ret = match &sig.output {
+        ReturnType::Default => None,
+        ReturnType::Type(_, ty) => {
+            check_type(ty)?;
+            Some(ty.to_token_stream().to_string())
+        }
+    };
+
+    Ok(ApiFn {
+        name: sig.ident.to_string(),
+        docs: doc_attrs(attrs),
+        lifetimes,
+        is_unsafe: sig.unsafety.is_some(),
+        args,
+        ret,
+        version: AbiVersion::BASE,
+        module: None,
+    })
+}
+
+/// 解析`#[vdso_async]`标记的函数，同时返回是否以`since`指定了版本。
+fn parse_async_fn(sig: &Signature, attrs: &[Attribute]) -> Result<(ApiFn, bool), String> {
+    if sig.asyncness.is_none() {
+        return Err("`#[vdso_async]` can only be used on `async fn`".into());
+    }
+    let mut sync_sig = sig.clone();
+    sync_sig.asyncness = None;
+    let mut api_fn = parse_api_fn(&sync_sig, attrs)?;
+    if !api_fn.lifetimes.is_empty() {
+        return Err("`#[vdso_async]` functions cannot have lifetime parameters".into());
+    }
+    if sig.inputs.iter().any(|input| {
+        matches!(input, FnArg::Typed(pat_type) if matches!(*pat_type.ty, syn::Type::Reference(_)))
+    }) {
+        return Err("the future outlives the call, so parameters cannot be references".into());
+    }
+    api_fn.module = vdso_api_module(attrs)?;
+    let since = match vdso_api_version(attrs)? {
+        ApiVersion::Base => false,
+        ApiVersion::Since(version) => {
+            api_fn.version = version;
+            true
+        }
+        ApiVersion::Compat { .. } => {
+            return Err("`#[vdso_async]` does not support `compat` or `symbol`".into())
+        }
+    };
+    Ok((api_fn, since))
+}
+
+/// 解析以`#[vdso_api(instances = "...")]`标记的泛型函数。
+fn parse_generic_fn(sig: &Signature, attrs: &[Attribute]) -> Result<GenericFn, String> {
+    let mut params = Vec::new();
+    for param in &sig.generics.params {
+        match param {
+            GenericParam::Type(ty) => params.push(ty.ident.to_string()),
+            GenericParam::Lifetime(lifetime) => {
+                return Err(format!(
+                    "lifetime parameter `{}` is not supported in generic APIs",
+                    lifetime.lifetime
+                ))
+            }
+            GenericParam::Const(c) => {
+                return Err(format!(
+                    "const generic parameter `{}` is not supported",
+                    c.ident
+                ))
+            }
+        }
+    }
+    if params.is_empty() {
+        return Err("`instances` can only be used on generic functions".into());
+    }
+    // 类型参数的约束只影响vDSO中的实例化，去掉泛型参数后按普通API解析签名
+    let mut plain_sig = sig.clone();
+    plain_sig.generics = Generics::default();
+    let mut api = parse_api_fn(&plain_sig, attrs)?;
+    api.module = vdso_api_module(attrs)?;
+    match vdso_api_version(attrs)? {
+        ApiVersion::Base => {}
+        ApiVersion::Since(version) => api.version = version,
+        ApiVersion::Compat { .. } => {
+            return Err("`instances` cannot be used together with `compat` or `symbol`".into())
+        }
+    }
+
+    let list = api_attr_value(attrs, "instances")?.unwrap_or_default();
+    let types = Punctuated::<syn::Type, Token![,]>::parse_terminated
+        .parse_str(&list)
+        .map_err(|e| format!("invalid instance list: {}", e))?;
+    if types.is_empty() {
+        return Err("the instance list is empty".into());
+    }
+    let mut instances: Vec<GenericInstance> = Vec::new();
+    for ty in types {
+        let types: Vec<String> = match ty {
+            _ if params.len() == 1 => vec![ty.to_token_stream().to_string()],
+            syn::Type::Tuple(tuple) if tuple.elems.len() == params.len() => tuple
+                .elems
+                .iter()
+                .map(|ty| ty.to_token_stream().to_string())
+                .collect(),
+            ty => {
+                return Err(format!(
+                    "expected a tuple of {} types for each instance, found `{}`",
+                    params.len(),
+                    ty.to_token_stream()
+                ))
+            }
+        };
+        for ty in types.iter() {
+            check_type(&syn::parse_str(ty).unwrap())?;
+        }
+        let symbol = instance_symbol(&api.name, &types);
+        if instances.iter().any(|instance| instance.symbol == symbol) {
+            return Err(format!("duplicate instance symbol `{}`", symbol));
+        }
+        instances.push(GenericInstance { symbol, types });
+    }
+    Ok(GenericFn {
+        api,
+        params,
+        instances,
+    })
+}
+
+/// `#[vdso_impl]`标记的`impl`块所实现的类型名，块不能为trait实现或带有泛型参数。
+fn parse_impl_type(item_impl: &ItemImpl) -> Result<String, String> {
+    if item_impl.trait_.is_some() {
+        return Err("`#[vdso_impl]` can only be used on inherent `impl` blocks".into());
+    }
+    if !item_impl.generics.params.is_empty() || item_impl.generics.where_clause.is_some() {
+        return Err("`#[vdso_impl]` cannot be used on generic `impl` blocks".into());
+    }
+    match &*item_impl.self_ty {
+        syn::Type::Path(syn::TypePath { qself: None, path }) if path.get_ident().is_some() => {
+            Ok(path.get_ident().unwrap().to_string())
+        }
+        _ => Err("the type of a `#[vdso_impl]` block must be a plain type name".into()),
+    }
+}
+
+/// 解析`#[vdso_impl]`块中类型`ty`的一个方法，同时返回是否以`since`指定了版本。
+///
+/// `impl_attrs`为`impl`块上的属性，版本和模块由其中的`#[vdso_impl(...)]`决定。
+fn parse_impl_method(
+    ty: &str,
+    sig: &Signature,
+    attrs: &[Attribute],
+    impl_attrs: &[Attribute],
+) -> Result<(ImplMethod, bool), String> {
+    // 去掉接收者后按普通API解析签名
+    let mut plain_sig = sig.clone();
+    let mut receiver = None;
+    if let Some(FnArg::Receiver(self_arg)) = sig.inputs.first() {
+        if self_arg.colon_token.is_some() {
+            return Err("only `self`, `&self` and `&mut self` receivers are supported".into());
+        }
+        // 按值接收时`mut self`的`mut`只影响方法内部，调用方看到的都是`self`
+        receiver = Some(match &self_arg.reference {
+            Some((_, lifetime)) => {
+                let lifetime = lifetime.as_ref().map(|l| format!("{} ", l));
+                let mutability = self_arg.mutability.map(|_| "mut ");
+                format!(
+                    "&{}{}self",
+                    lifetime.unwrap_or_default(),
+                    mutability.unwrap_or_default()
+                )
+            }
+            None => "self".to_string(),
+        });
+        plain_sig.inputs = sig.inputs.iter().skip(1).cloned().collect();
+    }
+    let mut api = parse_api_fn(&plain_sig, attrs)?;
+    api.module = vdso_api_module(impl_attrs)?;
+    let since = match vdso_api_version(impl_attrs)? {
+        ApiVersion::Base => false,
+        ApiVersion::Since(version) => {
+            api.version = version;
+            true
+        }
+        ApiVersion::Compat { .. } => {
+            return Err("`#[vdso_impl]` does not support `compat` or `symbol`".into())
+        }
+    };
+    Ok((
+        ImplMethod {
+            ty: ty.to_string(),
+            receiver,
+            api,
+        },
+        since,
+    ))
+}
+
+/// 实例导出的符号名：`<函数名>__<类型实参>`，规则需与`vdso_api`宏中的相同。
+///
+/// 类型实参中除字母、数字外的字符替换为`_`（连续的只保留一个，并去掉首尾的`_`），多个类型实参之间以`__`分隔。
+fn instance_symbol(name: &str, types: &[String]) -> String {
+    let mut symbol = name.to_string();
+    for ty in types {
+        let mut mangled = String::new();
+        for c in ty.chars() {
+            if c.is_ascii_alphanumeric() {
+                mangled.push(c);
+            } else if !mangled.is_empty() && !mangled.ends_with('_') {
+                mangled.push('_');
+            }
+        }
+        symbol.push_str("__");
+        symbol.push_str(mangled.trim_end_matches('_'));
+    }
+    symbol
+}
+
+/// 由`#[vdso_api(...)]`的参数指定的版本。
+enum ApiVersion {
+    /// 未指定版本，属于[`AbiVersion::BASE`]
+    Base,
+    /// `since = "..."`
+    Since(AbiVersion),
+    /// `compat = "...", symbol = "..."`
+    Compat { symbol: String, version: AbiVersion },
+}
+
+/// 读取`#[vdso_api(...)]`、`#[vdso_async(...)]`或`#[vdso_impl(...)]`的参数。参数的合法性已由宏检查，这里只需取出其中的值。
+fn vdso_api_version(attrs: &[Attribute]) -> Result<ApiVersion, String> {
+    let Some(attr) = api_attr(attrs) else {
+        return Ok(ApiVersion::Base);
+    };
+    if matches!(attr.meta, Meta::Path(_)) {
+        return Ok(ApiVersion::Base);
+    }
+    let args = attr
+        .parse_args_with(Punctuated::<syn::MetaNameValue, Token![,]>::parse_terminated)
+        .map_err(|e| e.to_string())?;
+    let mut since = None;
+    let mut compat = None;
+    let mut symbol = None;
+    for arg in args {
+        let value = match &arg.value {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(s),
+                ..
+            }) => s.value(),
+            _ => return Err("expected a string literal in `#[vdso_api]`".into()),
+        };
+        if arg.path.is_ident("since") {
+            since = Some(AbiVersion::parse(&value)?);
+        } else if arg.path.is_ident("compat") {
+            compat = Some(AbiVersion::parse(&value)?);
+        } else if arg.path.is_ident("symbol") {
+            symbol = Some(value);
+        }
+    }
+    Ok(match (since, compat, symbol) {
+        (Some(version), _, _) => ApiVersion::Since(version),
+        (None, Some(version), Some(symbol)) => ApiVersion::Compat { symbol, version },
+        _ => ApiVersion::Base,
+    })
+}
+
+/// 读取`#[vdso_api(module = "...")]`等指定的模块名，未指定时为`None`。
+fn vdso_api_module(attrs: &[Attribute]) -> Result<Option<String>, String> {
+    api_attr_value(attrs, "module")
+}
+
+/// 读取`#[vdso_api]`、`#[vdso_async]`或`#[vdso_impl]`中名为`name`的参数的值，未指定时为`None`。
+fn api_attr_value(attrs: &[Attribute], name: &str) -> Result<Option<String>, String> {
+    let Some(attr) = api_attr(attrs) else {
+        return Ok(None);
+    };
+    if matches!(attr.meta, Meta::Path(_)) {
+        return Ok(None);
+    }
+    let args = attr
+        .parse_args_with(Punctuated::<syn::MetaNameValue, Token![,]>::parse_terminated)
+        .map_err(|e| e.to_string())?;
+    match args.iter().find(|arg| arg.path.is_ident(name)) {
+        None => Ok(None),
+        Some(syn::MetaNameValue {
+            value:
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(s),
+                    ..
+                }),
+            ..
+        }) => Ok(Some(s.value())),
+        Some(_) => Err("expected a string literal in `#[vdso_api]`".into()),
+    }
+}
+
+/// 是否为以`#[vdso_api(instances = "...")]`标记的泛型API。
+fn has_instances(attrs: &[Attribute]) -> bool {
+    has_attr(attrs, "vdso_api") && !matches!(api_attr_value(attrs, "instances"), Ok(None))
+}
+
+/// 找到`#[vdso_api]`、`#[vdso_async]`或`#[vdso_impl]`属性。
+fn api_attr(attrs: &[Attribute]) -> Option<&Attribute> {
+    attrs.iter().find(|attr| {
+        attr.path().segments.last().is_some_and(|seg| {
+            seg.ident == "vdso_api" || seg.ident == "vdso_async" || seg.ident == "vdso_impl"
+        })
+    })
+}
+
+/// 检查类型中是否包含无法在API库中重现的部分。
+fn check_type(ty: &syn::Type) -> Result<(), String> {
+    struct Checker(Result<(), String>);
+    impl<'ast> syn::visit::Visit<'ast> for Checker {
+        fn visit_type_impl_trait(&mut self, ty: &'ast syn::TypeImplTrait) {
+            self.0 = Err(format!(
+                "`{}` cannot cross the vDSO boundary",
+                ty.to_token_stream()
+            ));
+        }
+        fn visit_type_infer(&mut self, _: &'ast syn::TypeInfer) {
+            self.0 = Err("inferred type `_` is not allowed".into());
+        }
+        fn visit_type_macro(&mut self, ty: &'ast syn::TypeMacro) {
+            self.0 = Err(format!(
+                "type macro `{}` is not supported",
+                ty.to_token_stream()
+            ));
+        }
+    }
+    let mut checker = Checker(Ok(()));
+    syn::visit::visit_type(&mut checker, ty);
+    checker.0
+}
+
+/// vDSO库中定义的类型，用于检查API的参数和返回值类型。
+enum TypeDef {
+    /// 结构体、枚举或联合体
+    Aggregate {
+        /// 是否为枚举
+        is_enum: bool,
+        /// 是否以`#[repr(C)]`或`#[repr(transparent)]`（枚举也可以为整数类型）指定了内存布局
+        repr_c: bool,
+        /// 类型参数名
+        params: Vec<String>,
+        /// 各个字段的类型，枚举为所有变体的字段
+        fields: Vec<syn::Type>,
+    },
+    /// 类型别名
+    Alias {
+        /// 类型参数名
+        params: Vec<String>,
+        /// 别名对应的类型
+        ty: syn::Type,
+    },
+}
+
+/// 类型在API签名中出现的位置。
+#[derive(Clone, Copy, PartialEq)]
+enum FfiPosition {
+    /// 参数
+    Arg,
+    /// 返回值
+    Ret,
+    /// `#[repr(C)]`类型的字段
+    Field,
+}
+
+/// 类型别名展开和字段检查的最大深度，避免循环定义的别名导致无限递归
+const MAX_FFI_DEPTH: usize = 32;
+
+/// C语言基本类型的别名，如`core::ffi::c_int`
+const C_TYPES: &[&str] = &[
+    "c_char",
+    "c_schar",
+    "c_uchar",
+    "c_short",
+    "c_ushort",
+    "c_int",
+    "c_uint",
+    "c_long",
+    "c_ulong",
+    "c_longlong",
+    "c_ulonglong",
+    "c_float",
+    "c_double",
+    "c_size_t",
+    "c_ssize_t",
+];
+
+/// 检查以字符串表示的类型能否通过C ABI传递，见[`check_ffi_type`]。
+fn check_ffi_type_str(
+    ty: &str,
+    position: FfiPosition,
+    defs: &HashMap<String, TypeDef>,
+) -> Result<(), String> {
+    let ty = syn::parse_str(ty).map_err(|e| format!("cannot parse the type: {}", e))?;
+    check_ffi_type(&ty, position, defs, 0)
+}
+
+/// 检查类型能否通过C ABI传递，不能时返回原因。
+///
+/// 指针和引用只检查被指向的类型是否为动态大小类型（切片、`str`、trait对象），不检查其内存布局。
+fn check_ffi_type(
+    ty: &syn::Type,
+    position: FfiPosition,
+    defs: &HashMap<String, TypeDef>,
+    depth: usize,
+) -> Result<(), String> {
+    if depth > MAX_FFI_DEPTH {
+        return Err("the type is nested too deeply".into());
+    }
+    match ty {
+        syn::Type::Paren(paren) => check_ffi_type(&paren.elem, position, defs, depth),
+        syn::Type::Group(group) => check_ffi_type(&group.elem, position, defs, depth),
+        syn::Type::Never(_) if position == FfiPosition::Ret => Ok(()),
+        syn::Type::Tuple(tuple) if tuple.elems.is_empty() && position == FfiPosition::Ret => Ok(()),
+        syn::Type::Tuple(tuple) if tuple.elems.is_empty() => {
+            Err("`()` can only be used as a return type".into())
+        }
+        syn::Type::Tuple(_) => {
+            Err("tuples have no stable layout, use a `#[repr(C)]` struct instead".into())
+        }
+        syn::Type::Ptr(ptr) => check_pointee(&ptr.elem),
+        syn::Type::Reference(reference) => check_pointee(&reference.elem),
+        syn::Type::BareFn(bare_fn) => check_ffi_fn(bare_fn, defs, depth),
+        syn::Type::Array(array) if position == FfiPosition::Field => {
+            check_ffi_type(&array.elem, FfiPosition::Field, defs, depth + 1)
+        }
+        syn::Type::Array(_) => Err(
+            "arrays cannot be passed by value, pass a pointer or wrap it in a `#[repr(C)]` struct"
+                .into(),
+        ),
+        syn::Type::Slice(_) | syn::Type::TraitObject(_) => {
+            Err("dynamically sized types cannot be passed by value".into())
+        }
+        syn::Type::Path(syn::TypePath { qself: None, path }) => {
+            check_ffi_path(path, position, defs, depth)
+        }
+        _ => Err("the type is not supported".into()),
+    }
+}
+
+/// 指针和引用只能指向固定大小的类型，指向切片、`str`和trait对象的是宽指针。
+fn check_pointee(ty: &syn::Type) -> Result<(), String> {
+    match ty {
+        syn::Type::Paren(paren) => check_pointee(&paren.elem),
+        syn::Type::Group(group) => check_pointee(&group.elem),
+        syn::Type::Slice(_) => {
+            Err("pointers to slices are fat pointers, pass a pointer and a length instead".into())
+        }
+        syn::Type::TraitObject(_) => Err("pointers to trait objects are fat pointers".into()),
+        syn::Type::Path(syn::TypePath { qself: None, path }) if path.is_ident("str") => {
+            Err("`&str` is a fat pointer, pass a pointer and a length instead".into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// 函数指针需要使用C调用约定，其参数和返回值同样需要能通过C ABI传递。
+fn check_ffi_fn(
+    bare_fn: &syn::TypeBareFn,
+    defs: &HashMap<String, TypeDef>,
+    depth: usize,
+) -> Result<(), String> {
+    let is_c = bare_fn
+        .abi
+        .as_ref()
+        .is_some_and(|abi| abi.name.as_ref().is_none_or(|name| name.value() == "C"));
+    if !is_c {
+        return Err("function pointers must use `extern \"C\"`".into());
+    }
+    for input in bare_fn.inputs.iter() {
+        check_ffi_type(&input.ty, FfiPosition::Arg, defs, depth + 1)?;
+    }
+    match &bare_fn.output {
+        ReturnType::Default => Ok(()),
+        ReturnType::Type(_, ty) => check_ffi_type(ty, FfiPosition::Ret, defs, depth + 1),
+    }
+}
+
+/// 检查以路径表示的类型：基本类型、`Option`、`Result`、`NonNull`等，以及vDSO库中定义的类型。
+fn check_ffi_path(
+    path: &syn::Path,
+    position: FfiPosition,
+    defs: &HashMap<String, TypeDef>,
+    depth: usize,
+) -> Result<(), String> {
+    let last = path.segments.last().unwrap();
+    let name = last.ident.to_string();
+    let args: Vec<&syn::Type> = match &last.arguments {
+        syn::PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                syn::GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    match name.as_str() {
+        "u8" | "u16" | "u32" | "u64" | "usize" | "i8" | "i16" | "i32" | "i64" | "isize"
+        | "f32" | "f64" | "bool" => Ok(()),
+        "u128" | "i128" => Err("128-bit integers have no stable C ABI".into()),
+        "char" => Err("`char` has no C equivalent, use `u32` instead".into()),
+        "str" => Err("`str` is dynamically sized, pass a pointer and a length instead".into()),
+        _ if C_TYPES.contains(&name.as_str()) => Ok(()),
+        "NonNull" => args.first().map_or(Ok(()), |ty| check_pointee(ty)),
+        _ if name.starts_with("NonZero") => Ok(()),
+        "PhantomData" if position == FfiPosition::Field => Ok(()),
+        "Option" if args.len() == 1 && is_non_null(args[0]) => {
+            check_ffi_type(args[0], FfiPosition::Field, defs, depth + 1)
+        }
+        "Option" => Err(
+            "`Option` is only FFI-safe around references, `extern \"C\"` function pointers, `NonNull` and `NonZero` integers"
+                .into(),
+        ),
+        "Result" if args.len() == 2 && is_non_null(args[0]) && is_unit(args[1]) => {
+            check_ffi_type(args[0], FfiPosition::Field, defs, depth + 1)
+        }
+        "Result" => Err(
+            "`Result` is only FFI-safe as `Result<T, ()>` where `T` is a reference, an `extern \"C\"` function pointer, `NonNull` or a `NonZero` integer"
+                .into(),
+        ),
+        _ => match defs.get(&name) {
+            Some(TypeDef::Aggregate {
+                is_enum,
+                repr_c,
+                params,
+                fields,
+            }) => {
+                if !repr_c {
+                    return Err(format!("`{}` is not `#[repr(C)]`", name));
+                }
+                // 没有字段的结构体大小为0，C中没有对应的类型
+                if !is_enum && fields.is_empty() && position != FfiPosition::Field {
+                    return Err(format!("`{}` has no fields", name));
+                }
+                let with = type_args(&args);
+                for field in fields.iter() {
+                    let field = substitute_type(&field.to_token_stream().to_string(), params, &with);
+                    check_ffi_type_str(&field, FfiPosition::Field, defs)
+                        .map_err(|e| format!("field of type `{}` in `{}`: {}", field, name, e))?;
+                }
+                Ok(())
+            }
+            Some(TypeDef::Alias { params, ty }) => {
+                let ty = substitute_type(&ty.to_token_stream().to_string(), params, &type_args(&args));
+                check_ffi_type(&syn::parse_str(&ty).unwrap(), position, defs, depth + 1)
+            }
+            None => Err(format!(
+                "`{}` is not defined in the vDSO crate, so its layout cannot be checked",
+                path.to_token_stream()
+            )),
+        },
+    }
+}
+
+/// 是否为不可能为空的指针类型，`Option`和`Result`包装这些类型时使用空值表示`None`或`Err`。
+fn is_non_null(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Reference(_) | syn::Type::BareFn(_) => true,
+        syn::Type::Paren(paren) => is_non_null(&paren.elem),
+        syn::Type::Path(syn::TypePath { qself: None, path }) => {
+            path.segments.last().is_some_and(|seg| {
+                seg.ident == "NonNull" || seg.ident.to_string().starts_with("NonZero")
+            })
+        }
+        _ => false,
+    }
+}
+
+/// 是否为`()`。
+fn is_unit(ty: &syn::Type) -> bool {
+    matches!(ty, syn::Type::Tuple(tuple) if tuple.elems.is_empty())
+}
+
+/// 类型实参的字符串形式，用于[`substitute_type`]。
+fn type_args(args: &[&syn::Type]) -> Vec<String> {
+    args.iter()
+        .map(|ty| ty.to_token_stream().to_string())
+        .collect()
+}
+
+/// 类型定义中的类型参数名。
+fn type_params(generics: &Generics) -> Vec<String> {
+    generics
+        .type_params()
+        .map(|param| param.ident.to_string())
+        .collect()
+}
+
+/// 是否以`#[repr(...)]`指定了可以通过C ABI传递的内存布局。
+///
+/// 结构体和联合体需要为`C`或`transparent`，枚举还可以为整数类型，如`#[repr(u8)]`。
+fn has_repr_c(attrs: &[Attribute], is_enum: bool) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("repr"))
+        .filter_map(|attr| {
+            attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                .ok()
+        })
+        .flatten()
+        .any(|meta| {
+            let Some(ident) = meta.path().get_ident() else {
+                return false;
+            };
+            ident == "C"
+                || ident == "transparent"
+                || (is_enum
+                    && [
+                        "u8", "u16", "u32", "u64", "usize", "i8", "i16", "i32", "i64", "isize",
+                    ]
+                    .iter()
+                    .any(|int| ident == int))
+        })
+}
+
+/// 找到`mod name;`声明对应的源文件，返回源文件路径和该模块的子模块目录。
+fn resolve_mod_file(dir: &Path, name: &str, path_attr: Option<String>) -> (PathBuf, PathBuf) {
+    if let Some(path_attr) = path_attr {
+        let file = dir.join(path_attr);
+        let sub_dir = file.parent().unwrap().to_path_buf();
+        return (file, sub_dir);
+    }
+    let file = dir.join(format!("{}.rs", name));
+    if file.exists() {
+        return (file, dir.join(name));
+    }
+    let file = dir.join(name).join("mod.rs");
+    if file.exists() {
+        return (file, dir.join(name));
+    }
+    panic!(
+        "Cannot find source file of module `{}` in {}",
+        name,
+        dir.display()
+    );
+}
+
+/// 若宏调用为`trait_interface! { pub trait ... }`，则解析其中的trait定义。
+fn parse_trait_interface(item_macro: &ItemMacro, file: &Path) -> Option<ItemTrait> {
+    let last = item_macro.mac.path.segments.last()?;
+    if last.ident != "trait_interface" {
+        return None;
+    }
+    let item_trait = syn::parse2::<ItemTrait>(item_macro.mac.tokens.clone()).unwrap_or_else(|e| {
+        panic!(
+            "Failed to parse trait in trait_interface! ({}): {}",
+            file.display(),
+            e
+        );
+    });
+    Some(item_trait)
+}
+
+/// 属性路径的最后一段是否为`name`，从而同时匹配`#[vdso_api]`和`#[vdso_helper::vdso_api]`。
+fn has_attr(attrs: &[Attribute], name: &str) -> bool {
+    attrs.iter().any(|attr| {
+        attr.path()
+            .segments
+            .last()
+            .is_some_and(|seg| seg.ident == name)
+    })
+}
+
+/// 是否带有`#[no_mangle]`或`#[unsafe(no_mangle)]`。
+fn is_no_mangle(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        if attr.path().is_ident("no_mangle") {
+            return true;
+        }
+        if attr.path().is_ident("unsafe") {
+            if let Ok(inner) = attr.parse_args::<syn::Path>() {
+                return inner.is_ident("no_mangle");
+            }
+        }
+        false
+    })
+}
+
+/// 收集文档注释，保留为`#[doc = "..."]`的形式。
+fn doc_attrs(attrs: &[Attribute]) -> Vec<String> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .map(|attr| attr.to_token_stream().to_string())
+        .collect()
+}
+
+/// 获取`#[path = "..."]`属性的值。
+fn path_attr(attrs: &[Attribute]) -> Option<String> {
+    attrs.iter().find_map(|attr| {
+        if !attr.path().is_ident("path") {
+            return None;
+        }
+        match &attr.meta {
+            Meta::NameValue(syn::MetaNameValue {
+                value:
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(s),
+                        ..
+                    }),
+                ..
+            }) => Some(s.value()),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use super::*;
+
+    /// 将`source`作为`src/lib.rs`写入临时目录并扫描，扫描失败时返回错误信息。
+    fn scan_source(name: &str, source: &str) -> Result<VdsoItems, String> {
+        scan_source_with_features(name, source, &[])
+    }
+
+    /// 与[`scan_source`]相同，扫描时启用`features`。
+    fn scan_source_with_features(
+        name: &str,
+        source: &str,
+        features: &[&str],
+    ) -> Result<VdsoItems, String> {
+        let dir =
+            std::env::temp_dir().join(format!("build_vdso_scan_{}_{}", name, std::process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src").join("lib.rs"), source).unwrap();
+        let mut config = BuildConfig::new(dir.to_str().unwrap(), "scan_test");
+        config.features = features.iter().map(|f| f.to_string()).collect();
+        let result = panic::catch_unwind(|| scan_vdso(&config)).map_err(|e| {
+            e.downcast_ref::<String>()
+                .cloned()
+                .unwrap_or_else(|| "unknown panic".to_string())
+        });
+        fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    #[test]
+    fn ffi_safe_types_are_accepted() {
+        let items = scan_source(
+            "ffi_ok",
+            r#"
+            #[repr(C)]
+            pub struct Pair { pub a: u32, pub b: [u8; 4] }
+            #[repr(u8)]
+            pub enum Kind { A, B }
+            pub type Callback = extern "C" fn(usize) -> bool;
+
+            #[vdso_api]
+            pub fn ok(
+                a: Option<&usize>,
+                b: Result<&mut Pair, ()>,
+                c: *const [u8; 16],
+                d: Callback,
+                e: core::ffi::c_int,
+                f: Kind,
+            ) -> Pair {
+                loop {}
+            }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(items.fns.len(), 1);
+    }
+
+    #[test]
+    fn ffi_unsafe_types_are_rejected() {
+        let errors = scan_source(
+            "ffi_err",
+            r#"
+            pub struct Plain { pub i: usize }
+            #[repr(C)]
+            pub struct Wrapper { pub inner: Plain }
+
+            #[vdso_api]
+            pub fn tuple(a: (usize, usize)) {}
+            #[vdso_api]
+            pub fn option() -> Option<usize> { None }
+            #[vdso_api]
+            pub fn result(r: Result<usize, ()>) {}
+            #[vdso_api]
+            pub fn string(s: &str) {}
+            #[vdso_api]
+            pub fn slice(s: &[u8]) {}
+            #[vdso_api]
+            pub fn plain(p: Plain) {}
+            #[vdso_api]
+            pub fn nested() -> Wrapper { loop {} }
+            "#,
+        )
+        .err()
+        .unwrap();
+        for expected in [
+            "`tuple`: parameter `a` has type `(usize , usize)`, which is not FFI-safe: tuples",
+            "`option`: return type `Option < usize >` is not FFI-safe",
+            "`result`: parameter `r` has type `Result < usize , () >`",
+            "`string`: parameter `s` has type `& str`",
+            "`slice`: parameter `s` has type `& [u8]`",
+            "`plain`: parameter `p` has type `Plain`, which is not FFI-safe: `Plain` is not `#[repr(C)]`",
+            "`nested`: return type `Wrapper` is not FFI-safe: field of type `Plain` in `Wrapper`",
+        ] {
+            assert!(errors.contains(expected), "missing `{}` in:\n{}", expected, errors);
+        }
+    }
+
+    fn names(items: &VdsoItems) -> Vec<&str> {
+        items.fns.iter().map(|f| f.name.as_str()).collect()
+    }
+
+    #[test]
+    fn where_clause_with_braces() {
+        let items = scan_source(
+            "where_clause",
+            r#"
+            #[vdso_api]
+            pub fn padded(a: usize) -> usize
+            where
+                [(); { 4 + 4 }]: Sized,
+            {
+                a
+            }
+
+            #[vdso_api]
+            pub fn after() {}
+            "#,
+        )
+        .unwrap();
+        assert_eq!(names(&items), ["padded", "after"]);
+        let padded = &items.fns[0];
+        assert_eq!(padded.args, [("a".to_string(), "usize".to_string())]);
+        assert_eq!(padded.ret.as_deref(), Some("usize"));
+    }
+
+    #[test]
+    fn comment_markers_in_string_literals() {
+        let items = scan_source(
+            "string_literals",
+            r#"
+            #[vdso_api]
+            pub fn url() -> *const u8 {
+                "https://example.com/*".as_ptr()
+            }
+
+            #[vdso_api]
+            pub fn after(s: *const u8) -> usize {
+                let _ = "// not a comment";
+                0
+            }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(names(&items), ["url", "after"]);
+        assert_eq!(
+            items.fns[1].args,
+            [("s".to_string(), "* const u8".to_string())]
+        );
+    }
+
+    #[test]
+    fn cfg_gated_items() {
+        let source = r#"
+            #[cfg(feature = "extra")]
+            #[vdso_api]
+            pub fn extra() {}
+
+            #[cfg(not(feature = "extra"))]
+            #[vdso_api]
+            pub fn fallback() {}
+
+            #[cfg(all(feature = "extra", target_arch = "riscv64"))]
+            mod gated {
+                #[vdso_api]
+                pub fn nested() {}
+            }
+
+            #[cfg(test)]
+            #[vdso_api]
+            pub fn only_in_tests() {}
+        "#;
+        let items = scan_source("cfg_off", source).unwrap();
+        assert_eq!(names(&items), ["fallback"]);
+        let items = scan_source_with_features("cfg_on", source, &["extra"]).unwrap();
+        assert_eq!(names(&items), ["extra", "nested"]);
+    }
+
+    #[test]
+    fn unsafe_extern_c() {
+        let items = scan_source(
+            "unsafe_extern",
+            r#"
+            mod api {
+                #[unsafe(no_mangle)]
+                pub unsafe extern "C" fn raw_write(ptr: *mut u8, value: u8) {
+                    unsafe { ptr.write(value) }
+                }
+
+                #[unsafe(no_mangle)]
+                pub extern "C" fn safe_call() -> usize {
+                    0
+                }
+
+                unsafe extern "C" {
+                    fn vdso_exit() -> !;
+                }
+            }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(names(&items), ["raw_write", "safe_call", "vdso_exit"]);
+        assert!(items.fns[0].is_unsafe);
+        assert!(!items.fns[1].is_unsafe);
+        assert_eq!(items.fns[2].ret.as_deref(), Some("!"));
+    }
+
+    #[test]
+    fn doc_comments_are_kept() {
+        let items = scan_source(
+            "doc_comments",
+            r#"
+            /// 第一行，包含`"引号"`。
+            ///
+            /// 第三行
+            #[vdso_api]
+            pub fn documented() {}
+
+            /** 块注释 */
+            #[vdso_api]
+            pub fn block_doc() {}
+            "#,
+        )
+        .unwrap();
+        // 生成API库时原样输出`docs`中的每一项，因此需要能重新解析为相同的文档注释
+        let docs = |f: &ApiFn| -> Vec<String> {
+            f.docs
+                .iter()
+                .map(|doc| {
+                    let attr = Attribute::parse_outer
+                        .parse_str(doc)
+                        .unwrap()
+                        .pop()
+                        .unwrap();
+                    match attr.meta {
+                        Meta::NameValue(syn::MetaNameValue {
+                            value:
+                                syn::Expr::Lit(syn::ExprLit {
+                                    lit: syn::Lit::Str(s),
+                                    ..
+                                }),
+                            ..
+                        }) => s.value(),
+                        _ => panic!("`{}` is not a doc comment", doc),
+                    }
+                })
+                .collect()
+        };
+        assert_eq!(
+            docs(&items.fns[0]),
+            [" 第一行，包含`\"引号\"`。", "", " 第三行"]
+        );
+        assert_eq!(docs(&items.fns[1]), [" 块注释 "]);
+    }
+}
@@ -0,0 +1,52 @@
+//! vDSO的ABI版本与符号版本节点。
+//!
+//! 每个ABI版本对应版本脚本中的一个版本节点`VDSO_<major>.<minor>`，较新的节点继承较旧的节点。
+//! 未指定版本的API属于[`AbiVersion::BASE`]。
+
+use std::fmt;
+
+use crate::BuildConfig;
+
+/// ABI版本号，形如`1.0`。
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub(crate) struct AbiVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl AbiVersion {
+    /// 未指定版本的API所属的版本
+    pub const BASE: Self = Self { major: 1, minor: 0 };
+
+    /// 解析形如`1.0`的版本号。
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (major, minor) = s
+            .split_once('.')
+            .ok_or_else(|| format!("invalid ABI version `{}`, expected `<major>.<minor>`", s))?;
+        let parse_part = |part: &str| {
+            part.parse::<u32>()
+                .map_err(|_| format!("invalid ABI version `{}`, expected `<major>.<minor>`", s))
+        };
+        Ok(Self {
+            major: parse_part(major)?,
+            minor: parse_part(minor)?,
+        })
+    }
+
+    /// 版本脚本中的版本节点名，如`VDSO_1.0`。
+    pub fn node_name(&self) -> String {
+        format!("VDSO_{}", self)
+    }
+}
+
+/// 解析[`BuildConfig::abi_version`]，格式错误时panic。
+pub(crate) fn abi_version(config: &BuildConfig) -> AbiVersion {
+    AbiVersion::parse(&config.abi_version)
+        .unwrap_or_else(|e| panic!("Invalid BuildConfig::abi_version: {}", e))
+}
+
+impl fmt::Display for AbiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
@@ -19,5 +19,8 @@ fn main() {
     // config.toolchain = String::from("nightly-2025-09-30");
     config.verbose = 2;
     config.log = true;
+    config.abi_version = String::from("1.1");
+    config.heap_size = 0x4000;
+    config.resolve_at_runtime = std::env::var_os("CARGO_FEATURE_RESOLVE_AT_RUNTIME").is_some();
     build_vdso(&config);
 }
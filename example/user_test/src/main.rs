@@ -1,4 +1,14 @@
-use std::{fmt::Arguments, mem};
+use std::{
+    fmt::Arguments,
+    future::Future,
+    mem,
+    pin::pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Wake, Waker},
+};
 
 // use crate::map::map_vdso;
 // use libvdsoexample::{interface::TestIf, *};
@@ -10,6 +20,29 @@ use memmap2::MmapMut;
 
 struct MemImpl;
 
+/// 以memfd模拟物理页时，`PhysPagePtr`的低32位为memfd，高32位为页在memfd中的偏移
+const PPAGE_OFFSET_SHIFT: usize = 32;
+
+/// 已映射的区域：首地址、大小、物理页，用于实现`ppage_of`
+static MAPPED: Mutex<Vec<(usize, usize, PhysPagePtr)>> = Mutex::new(Vec::new());
+
+/// `unmap`和`vfree`被调用的次数，用于检查`unmap_so`是否释放了映射
+static UNMAP_COUNT: AtomicUsize = AtomicUsize::new(0);
+static VFREE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// 模拟子进程的地址空间：子进程中的地址`vaddr`对应本进程中的`vaddr - CHILD_OFFSET`
+const CHILD_VSPACE: usize = 2;
+const CHILD_OFFSET: usize = 1 << 40;
+
+/// 将`vspace`中的地址转换为本进程中实际映射的地址
+fn host_addr(vspace: usize, vaddr: *mut u8) -> *mut u8 {
+    if vspace == CHILD_VSPACE {
+        vaddr.wrapping_sub(CHILD_OFFSET)
+    } else {
+        vaddr
+    }
+}
+
 // #[crate_interface::impl_interface]
 // impl MemIf for MemImpl {
 //     #[doc = " 分配用于vDSO和vVAR的空间，返回指向首地址的指针。"]
@@ -53,13 +86,51 @@ impl MemIf for MemImpl {
         ptr
     }
 
+    #[doc = " 在地址空间中的指定位置分配用于vDSO和vVAR的虚存区域（不需同时分配物理页面）。"]
+    #[doc = " "]
+    #[doc = " 用于fork时使子进程中的vDSO与父进程位于相同的地址，保证该区域在`vspace`中未被占用。"]
+    fn valloc_at(vspace: usize, vaddr: *mut u8, size: usize) {
+        let vaddr = host_addr(vspace, vaddr);
+        let ptr = unsafe {
+            libc::mmap(
+                vaddr as _,
+                size,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED_NOREPLACE,
+                -1,
+                0,
+            )
+        };
+        if ptr != vaddr as _ {
+            panic!("vdso: mmap fixed res failed");
+        }
+    }
+
     #[doc = " 分配多块用于vDSO和vVAR的连续物理页，返回`PhysPagePtr`。"]
     #[doc = " "]
     #[doc = " 保证size为build_vdso传入的config.page_size的整数倍。"]
     #[doc = ""]
     #[doc = " 若需要实现vDSO和vVAR在多地址空间的共享，则需要在分配时使这块空间可被共享（即，可被多次`map`）。"]
-    fn ppage_alloc(_size: usize) -> PhysPagePtr {
-        0
+    fn ppage_alloc(size: usize) -> PhysPagePtr {
+        // 以memfd模拟物理页，使同一物理页被多次`map`时真正共享
+        unsafe {
+            let fd = libc::memfd_create(c"vdso_ppage".as_ptr(), 0);
+            if fd < 0 || libc::ftruncate(fd, size as _) != 0 {
+                panic!("vdso: memfd_create res failed");
+            }
+            fd as PhysPagePtr
+        }
+    }
+
+    #[doc = " 获取`vspace`中从`vaddr`开始、大小为`size`的已映射区域所使用的物理页，返回`PhysPagePtr`。"]
+    fn ppage_of(vspace: usize, vaddr: *mut u8, size: usize) -> PhysPagePtr {
+        let vaddr = host_addr(vspace, vaddr) as usize;
+        let mapped = MAPPED.lock().unwrap();
+        let &(start, _, ppage) = mapped
+            .iter()
+            .find(|&&(start, len, _)| start <= vaddr && vaddr + size <= start + len)
+            .expect("vdso: region is not mapped");
+        ppage + ((vaddr - start) << PPAGE_OFFSET_SHIFT)
     }
 
     #[doc = " 从`alloc`返回的虚存区域中，映射其中一块到某个物理页面并设置权限。"]
@@ -70,13 +141,14 @@ impl MemIf for MemImpl {
     #[doc = ""]
     #[doc = " `flags`可能包含：READ、WRITE、EXECUTE、USER。"]
     fn map(
-        _vspace: usize,
+        vspace: usize,
         vaddr: *mut u8,
-        _ppage: PhysPagePtr,
+        ppage: PhysPagePtr,
         size: usize,
         flags: MappingFlags,
         _shared: bool,
     ) {
+        let vaddr = host_addr(vspace, vaddr);
         let mut libc_flag = libc::PROT_READ;
         if flags.contains(MappingFlags::EXECUTE) {
             libc_flag |= libc::PROT_EXEC;
@@ -84,17 +156,29 @@ impl MemIf for MemImpl {
         if flags.contains(MappingFlags::WRITE) {
             libc_flag |= libc::PROT_WRITE;
         }
+        let fd = ppage & ((1 << PPAGE_OFFSET_SHIFT) - 1);
+        let offset = ppage >> PPAGE_OFFSET_SHIFT;
         unsafe {
-            if libc::mprotect(vaddr as _, size, libc_flag) == libc::MAP_FAILED as _ {
-                panic!("vdso: mprotect res failed");
+            let ptr = libc::mmap(
+                vaddr as _,
+                size,
+                libc_flag,
+                libc::MAP_SHARED | libc::MAP_FIXED,
+                fd as _,
+                offset as _,
+            );
+            if ptr != vaddr as _ {
+                panic!("vdso: mmap res failed");
             }
         };
+        MAPPED.lock().unwrap().push((vaddr as usize, size, ppage));
     }
 
     #[doc = " 重新设置已映射好的，虚拟首地址为`vspace`区域的权限。"]
     #[doc = " "]
     #[doc = " 保证vaddr对齐到build_vdso传入的config.page_size。"]
-    fn change_protect(_vspace: usize, vaddr: *mut u8, size: usize, flags: MappingFlags) {
+    fn change_protect(vspace: usize, vaddr: *mut u8, size: usize, flags: MappingFlags) {
+        let vaddr = host_addr(vspace, vaddr);
         let mut libc_flag = libc::PROT_READ;
         if flags.contains(MappingFlags::EXECUTE) {
             libc_flag |= libc::PROT_EXEC;
@@ -111,8 +195,8 @@ impl MemIf for MemImpl {
 
     #[doc = " 获取`vspace`空间中`vaddr`地址对应的内核虚拟地址。"]
     #[doc = " （也就是当前代码可以直接访问的地址）"]
-    fn get_kernel_vaddr(_vspace: usize, vaddr: *mut u8) -> *mut u8 {
-        vaddr
+    fn get_kernel_vaddr(vspace: usize, vaddr: *mut u8) -> *mut u8 {
+        host_addr(vspace, vaddr)
     }
 
     #[doc = " 复制物理页指针，复制前后指向同一块物理页。复制后，参数和返回值对应的两个指针均需可用。"]
@@ -120,8 +204,53 @@ impl MemIf for MemImpl {
     #[doc = " 如果物理页使用RAII管理，则需调用其`clone`方法。"]
     #[doc = " "]
     #[doc = " 如果物理页不使用RAII管理，则可以直接返回参数。"]
-    fn ppage_clone(_ppage: PhysPagePtr) -> PhysPagePtr {
-        0
+    fn ppage_clone(ppage: PhysPagePtr) -> PhysPagePtr {
+        ppage
+    }
+
+    #[doc = " 释放一个没有被`map`的物理页指针，例如库中暂存的副本。"]
+    #[doc = ""]
+    #[doc = " 如果物理页使用RAII管理，则需将其转化回`PhysPage`并释放。"]
+    fn ppage_free(_ppage: PhysPagePtr) {}
+
+    #[doc = " 解除`vspace`中从`vaddr`开始、大小为`size`的一块映射，并释放`map`时传入的物理页。"]
+    #[doc = ""]
+    #[doc = " 若物理页与其它地址空间共享，则只释放该地址空间持有的引用。"]
+    #[doc = ""]
+    #[doc = " 保证vaddr对齐到build_vdso传入的config.page_size，且与某次`map`的参数相同。"]
+    fn unmap(vspace: usize, vaddr: *mut u8, size: usize) {
+        let vaddr = host_addr(vspace, vaddr);
+        UNMAP_COUNT.fetch_add(1, Ordering::SeqCst);
+        MAPPED
+            .lock()
+            .unwrap()
+            .retain(|&(start, _, _)| start != vaddr as usize);
+        unsafe {
+            if libc::mprotect(vaddr as _, size, libc::PROT_NONE) == libc::MAP_FAILED as _ {
+                panic!("vdso: mprotect res failed");
+            }
+        };
+    }
+
+    #[doc = " 释放`valloc`分配的虚存区域。调用前，区域中的映射均已通过`unmap`解除。"]
+    fn vfree(vspace: usize, vaddr: *mut u8, size: usize) {
+        let vaddr = host_addr(vspace, vaddr);
+        VFREE_COUNT.fetch_add(1, Ordering::SeqCst);
+        unsafe {
+            if libc::munmap(vaddr as _, size) != 0 {
+                panic!("vdso: munmap res failed");
+            }
+        };
+    }
+}
+
+struct ModuleImpl;
+
+#[crate_interface::impl_interface]
+impl ModuleIf for ModuleImpl {
+    #[doc = " 在当前地址空间中映射`instance`的模块`module`，返回该模块的首地址；无法映射时返回`None`。"]
+    fn load_module(instance: &VdsoInstance, module: &str) -> Option<*mut u8> {
+        instance.map_module(0, module).ok()
     }
 }
 
@@ -144,11 +273,66 @@ impl TestIf for TestImpl {
     }
 }
 
+/// 通过futex实现的等待接口。vVAR映射自memfd，futex以文件和偏移区分等待队列，因此跨映射同样有效
+struct WaitImpl;
+
+/// `WaitImpl`还原锁的地址时使用的vDSO首地址，模拟内核自己的映射
+static WAIT_BASE: AtomicUsize = AtomicUsize::new(0);
+
+impl WaitImpl {
+    /// `key`为锁相对于vDSO首地址的偏移，在`WAIT_BASE`处的映射中还原其地址
+    fn addr(key: usize) -> *const u32 {
+        WAIT_BASE.load(Ordering::Relaxed).wrapping_add(key) as *const u32
+    }
+}
+
+impl WaitIf for WaitImpl {
+    fn wait(key: usize, expected: u32) {
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                Self::addr(key),
+                libc::FUTEX_WAIT,
+                expected,
+                core::ptr::null::<libc::timespec>(),
+            )
+        };
+    }
+
+    fn wake(key: usize, count: u32) {
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                Self::addr(key),
+                libc::FUTEX_WAKE,
+                count.min(i32::MAX as u32),
+            )
+        };
+    }
+}
+
+/// 记录被唤醒次数的Waker
+struct CountWaker(AtomicUsize);
+
+impl Wake for CountWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
 fn main() {
     env_logger::init();
     log::info!("Starting VDSO test...");
+    assert!(matches!(
+        try_get_shared(),
+        Err(VdsoError::NotInitialized("get_shared"))
+    ));
     // let regions = load_and_init(0);
-    load_and_init(0);
+    load_and_init(0).expect("Failed to load vDSO");
     // println!("vDSO and vVAR loaded with the following regions:");
     // for (i, (addr, size, flags)) in regions.iter().enumerate() {
     //     println!(
@@ -183,12 +367,179 @@ fn main() {
         example.i
     );
 
-    assert_eq!(test_args(Some(1), Ok(2), (3, 4)), (Some(2), Ok(3), (4, 5)));
+    let (mut a, mut b) = (1, 2);
+    let pair = PairExample {
+        first: 3,
+        second: 4,
+    };
+    let expected = PairExample {
+        first: 4,
+        second: 5,
+    };
+    assert_eq!(test_args(Some(&mut a), Ok(&mut b), pair), expected);
+    assert_eq!((a, b), (2, 3));
+    assert_eq!(try_test_args(None, Err(()), pair), Ok(expected));
+    assert_eq!(vdso_abi_version(), Some((1, 1)));
+    assert_eq!(test_version(), VersionExample { major: 1, minor: 1 });
 
     init_vtable_TestIf::<TestImpl>();
     let mut test_impl = TestImpl(10);
     let ptr = &mut test_impl as *mut TestImpl as *mut ();
     test_call(ptr);
+    assert_eq!(unsafe { (*(ptr as *mut TestImpl)).0 }, 12);
+
+    // ext模块在首次调用时加载，与主模块共享vVAR，并注册已注册的依赖接口
+    assert_eq!(ext_get_shared(1), 2);
+    ext_test_call(ptr);
+    assert_eq!(unsafe { (*(ptr as *mut TestImpl)).0 }, 14);
     test_log();
+
+    // 共享数据中的票据锁在被占用时通过注册的WaitIf睡眠
+    // 等待接口在另一个映射中还原锁的地址，与调用者的映射共享vVAR的物理页
+    WAIT_BASE.store(map_so(0) as usize, Ordering::Relaxed);
+    init_vtable_WaitIf::<WaitImpl>();
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            std::thread::spawn(|| {
+                (0..1000).for_each(|_| {
+                    add_counter(1);
+                })
+            })
+        })
+        .collect();
+    threads.into_iter().for_each(|t| t.join().unwrap());
+    assert_eq!(add_counter(0), 4000);
+    // 以`#[vdso_impl]`导出的方法可以直接在类型上调用，经由VTABLE执行vDSO中的实现
+    let mut counter = CounterHandle::new(2);
+    assert_eq!(counter.bump(), 4002);
+    counter.set_step(3);
+    assert_eq!(counter.try_bump(), Ok(4005));
+
+    // 内核预留一块已映射的区域，将另一份vDSO实例加载到其中，并将其vVAR共享给用户空间的实例
+    static KERNEL_VDSO: VdsoInstance = VdsoInstance::new();
+    static USER_VDSO: VdsoInstance = VdsoInstance::new();
+    let size = kernel_region_size();
+    let region = <MemImpl as MemIf>::valloc(0, size);
+    let ppage = <MemImpl as MemIf>::ppage_alloc(size);
+    <MemImpl as MemIf>::map(
+        0,
+        region,
+        ppage,
+        size,
+        MappingFlags::READ | MappingFlags::WRITE,
+        false,
+    );
+    let region = unsafe { std::slice::from_raw_parts_mut(region, size) };
+    KERNEL_VDSO
+        .load_into_kernel(0, region)
+        .expect("Failed to load vDSO into the reserved region");
+    assert_eq!(
+        KERNEL_VDSO.load_into_kernel(0, &mut []),
+        Err(VdsoError::AlreadyLoaded)
+    );
+    KERNEL_VDSO.set_shared(7);
+    let user_vdso = KERNEL_VDSO.map_so(1);
+    unsafe { USER_VDSO.init_vdso_vtable(user_vdso as _) }.unwrap();
+    assert_eq!(USER_VDSO.get_shared().i, 7);
+    // 标记为`#[user_readonly]`的共享数据由内核写入，用户空间的实例可以读到
+    KERNEL_VDSO.set_ticks(42);
+    assert_eq!(USER_VDSO.get_ticks(), 42);
+    KERNEL_VDSO.set_clock(1, 500);
+    assert_eq!(
+        USER_VDSO.get_clock(),
+        ClockSnapshot {
+            secs: 1,
+            nanos: 500
+        }
+    );
+    // 内核中构建的链表保存的是位置无关指针，用户空间的实例可以正确遍历
+    KERNEL_VDSO.queue_push(2);
+    KERNEL_VDSO.queue_push(0);
+    KERNEL_VDSO.queue_push(2);
+    let mut index = 0;
+    assert!(USER_VDSO.queue_pop(&mut index));
+    assert_eq!(index, 2);
+    USER_VDSO.queue_push(3);
+    assert!(KERNEL_VDSO.queue_pop(&mut index));
+    assert_eq!(index, 0);
+    assert!(USER_VDSO.queue_pop(&mut index));
+    assert_eq!(index, 3);
+    assert!(!KERNEL_VDSO.queue_pop(&mut index));
+    // 共享内存中分配的对象以偏移为句柄，可以在其它地址空间中访问和释放，释放后的内存会被重新使用
+    let handle = KERNEL_VDSO.shared_box_new(5);
+    assert_ne!(handle, 0);
+    assert_eq!(unsafe { USER_VDSO.shared_box_get(handle) }, 5);
+    unsafe { USER_VDSO.shared_box_free(handle) };
+    assert_eq!(KERNEL_VDSO.shared_box_new(6), handle);
+    assert_eq!(unsafe { USER_VDSO.shared_box_get(handle) }, 6);
+    // 私有堆位于私有数据中，每个地址空间中的记录互相独立
+    assert_eq!(KERNEL_VDSO.record_private(1), 1);
+    assert_eq!(KERNEL_VDSO.record_private(2), 2);
+    assert_eq!(USER_VDSO.record_private(3), 1);
+    // 泛型API按类型实参调用vDSO中对应的实例
+    assert_eq!(USER_VDSO.max_of(3u32, 5), 5);
+    assert_eq!(max_of(7u64, 2), 7);
+    assert_eq!(
+        max_of(ArgumentExample { i: 1 }, ArgumentExample { i: 2 }).i,
+        2
+    );
+    assert_eq!(sum_pair(1u8, 2u16), 3);
+    assert_eq!(KERNEL_VDSO.try_sum_pair(3u16, 4u8), Ok(7));
+    // 异步API的Future由vDSO中的代码推进，唤醒的是调用者的Waker
+    let count = Arc::new(CountWaker(AtomicUsize::new(0)));
+    let waker = Waker::from(count.clone());
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(USER_VDSO.async_add_one(41));
+    assert_eq!(future.as_mut().poll(&mut cx), Poll::Pending);
+    assert_eq!(count.0.load(Ordering::SeqCst), 1);
+    assert_eq!(future.as_mut().poll(&mut cx), Poll::Ready(42));
+    // 未完成的Future被析构时，vDSO中的Future也会被析构
+    let mut future = pin!(async_add_one(1));
+    assert_eq!(future.as_mut().poll(&mut cx), Poll::Pending);
+    assert_eq!(get_shared().i, 1);
+    // 进程退出时解除vDSO的映射：各个区域通过`unmap`解除，虚存区域通过`vfree`释放
+    static PROCESS_VDSO: VdsoInstance = VdsoInstance::new();
+    let process = map_so(1);
+    let unmapped = UNMAP_COUNT.load(Ordering::SeqCst);
+    let freed = VFREE_COUNT.load(Ordering::SeqCst);
+    assert_eq!(unmap_so(1, process), Ok(()));
+    assert!(UNMAP_COUNT.load(Ordering::SeqCst) > unmapped);
+    assert_eq!(VFREE_COUNT.load(Ordering::SeqCst), freed + 1);
+    assert_eq!(unmap_so(1, process), Err(VdsoError::NotMapped));
+    // 解除映射后可以再次映射，新的映射与其它地址空间共享vVAR
+    let process = map_so(1);
+    unsafe { PROCESS_VDSO.init_vdso_vtable(process as u64) }.unwrap();
+    assert_eq!(PROCESS_VDSO.get_shared().i, 1);
+    // fork时复制vDSO：私有数据在子进程中为副本，vVAR仍然共享
+    static CHILD_VDSO: VdsoInstance = VdsoInstance::new();
+    PROCESS_VDSO.set_private(7);
+    let child = clone_so(1, CHILD_VSPACE).unwrap();
+    assert_eq!(child, process);
+    let child = <MemImpl as MemIf>::get_kernel_vaddr(CHILD_VSPACE, child);
+    unsafe { CHILD_VDSO.init_vdso_vtable(child as u64) }.unwrap();
+    assert_eq!(CHILD_VDSO.get_private().i, 7);
+    CHILD_VDSO.set_private(8);
+    assert_eq!(CHILD_VDSO.get_private().i, 8);
+    assert_eq!(PROCESS_VDSO.get_private().i, 7);
+    CHILD_VDSO.set_shared(9);
+    assert_eq!(PROCESS_VDSO.get_shared().i, 9);
+    PROCESS_VDSO.set_shared(1);
+    assert_eq!(unmap_so(CHILD_VSPACE, process), Ok(()));
+    // 热更新vDSO：vVAR的布局不变，因此共享数据保持不变，私有数据则重新初始化
+    #[cfg(feature = "resolve_at_runtime")]
+    {
+        static NEW_VDSO: &[u8] =
+            include_bytes_aligned::include_bytes_aligned!(8, "../../../output/libvdsoexample.so");
+        let old_fn = vdso_vtable().get_shared.unwrap() as usize;
+        set_shared(3);
+        set_private(5);
+        replace_so(NEW_VDSO, None).expect("Failed to replace vDSO");
+        assert_ne!(vdso_vtable().get_shared.unwrap() as usize, old_fn);
+        assert_eq!(get_shared().i, 3);
+        assert_eq!(get_private().i, 0);
+        assert_eq!(ext_get_shared(1), 4);
+        assert_eq!(replace_so(&[0u8; 64], None), Err(VdsoError::InvalidImage));
+        set_shared(1);
+    }
     println!("Test passed!");
 }
@@ -1,50 +1,222 @@
+use alloc::vec::Vec;
+use core::future::Future;
 use core::mem::MaybeUninit;
+use core::pin::Pin;
 use core::sync::atomic::Ordering;
+use core::task::{Context, Poll};
 
-use vdso_helper::{get_vvar_data, log};
+use vdso_helper::{get_vvar_data, log, vdso_api, vdso_async, vdso_impl, SpinLock, VvarPtr};
 
-use crate::{interface, ArgumentExample, PRIVATE_DATA_EXAMPLE};
+use crate::{
+    interface, ArgumentExample, ClockSnapshot, CounterHandle, PairExample, VersionExample,
+    PRIVATE_DATA_EXAMPLE,
+};
 
-#[unsafe(no_mangle)]
-pub extern "C" fn get_shared() -> ArgumentExample {
+#[vdso_api]
+pub fn get_shared() -> ArgumentExample {
     ArgumentExample {
         i: get_vvar_data!(example).load(Ordering::Acquire),
     }
 }
 
-#[unsafe(no_mangle)]
-pub extern "C" fn set_shared(i: usize) {
+#[vdso_api]
+pub fn set_shared(i: usize) {
     get_vvar_data!(example).store(i, Ordering::Release);
 }
 
-#[unsafe(no_mangle)]
-pub extern "C" fn get_private() -> ArgumentExample {
+/// 读取由内核更新的计数。
+#[vdso_api(since = "1.1")]
+pub fn get_ticks() -> usize {
+    get_vvar_data!(ticks).load(Ordering::Acquire)
+}
+
+/// 更新计数。`ticks`在用户进程中映射为只读，因此只能由内核调用。
+#[vdso_api(since = "1.1")]
+pub fn set_ticks(ticks: usize) {
+    get_vvar_data!(ticks).store(ticks, Ordering::Release);
+}
+
+/// 读取由内核更新的时间。
+#[vdso_api(since = "1.1")]
+pub fn get_clock() -> ClockSnapshot {
+    get_vvar_data!(clock).read()
+}
+
+/// 更新时间。`clock`在用户进程中映射为只读，因此只能由内核调用。
+#[vdso_api(since = "1.1")]
+pub fn set_clock(sec: usize, nsec: usize) {
+    get_vvar_data!(clock).write(|clock| {
+        *clock = ClockSnapshot {
+            secs: sec,
+            nanos: nsec,
+        }
+    });
+}
+
+/// 将共享的计数加上`n`，返回相加后的值。
+#[vdso_api(since = "1.1")]
+pub fn add_counter(n: usize) -> usize {
+    let mut counter = get_vvar_data!(counter).lock();
+    *counter += n;
+    *counter
+}
+
+#[vdso_impl(since = "1.1")]
+impl CounterHandle {
+    /// 创建每次累加`step`的句柄。
+    pub fn new(step: usize) -> Self {
+        Self { step }
+    }
+
+    /// 将共享的计数加上`step`，返回相加后的值。
+    pub fn bump(&self) -> usize {
+        add_counter(self.step)
+    }
+
+    /// 修改每次累加的值。
+    pub fn set_step(&mut self, step: usize) {
+        self.step = step;
+    }
+}
+
+/// 将第`i`个节点加入共享队列的尾部，节点已在队列中时不做任何操作。
+#[vdso_api(since = "1.1")]
+pub fn queue_push(i: usize) {
+    let node = &get_vvar_data!(nodes)[i];
+    let queue = get_vvar_data!(queue).lock();
+    if unsafe { !queue.iter().any(|n| core::ptr::eq(n, node)) } {
+        unsafe { queue.push_back(node) };
+    }
+}
+
+/// 从共享队列的头部取出一个节点，将其序号写入`index`。队列为空时返回`false`。
+#[vdso_api(since = "1.1")]
+pub fn queue_pop(index: &mut usize) -> bool {
+    let nodes = get_vvar_data!(nodes);
+    let Some(node) = (unsafe { get_vvar_data!(queue).lock().pop_front() }) else {
+        return false;
+    };
+    *index = nodes.iter().position(|n| core::ptr::eq(n, node)).unwrap();
+    true
+}
+
+/// 在共享内存中分配一个保存`value`的对象，返回可以在各个地址空间中使用的句柄，内存不足时返回0。
+///
+/// 句柄为对象的`VvarPtr`偏移，0对应空指针，因此不会与合法的句柄混淆。
+#[vdso_api(since = "1.1")]
+pub fn shared_box_new(value: usize) -> isize {
+    get_vvar_data!(heap)
+        .alloc_value(value)
+        .map_or(0, |ptr| ptr.offset())
+}
+
+/// 读取句柄对应的对象。
+///
+/// # Safety
+///
+/// `handle`需要由`shared_box_new`返回，且尚未被释放。
+#[vdso_api(since = "1.1")]
+pub unsafe fn shared_box_get(handle: isize) -> usize {
+    unsafe { *VvarPtr::<usize>::from_offset(handle).as_ptr() }
+}
+
+/// 释放句柄对应的对象。
+///
+/// # Safety
+///
+/// `handle`需要由`shared_box_new`返回，且尚未被释放。
+#[vdso_api(since = "1.1")]
+pub unsafe fn shared_box_free(handle: isize) {
+    unsafe { get_vvar_data!(heap).free_value(VvarPtr::<usize>::from_offset(handle)) };
+}
+
+/// 返回`a`和`b`中较大的一个。
+#[vdso_api(since = "1.1", instances = "u32, u64, ArgumentExample")]
+pub fn max_of<T: PartialOrd>(a: T, b: T) -> T {
+    if a >= b {
+        a
+    } else {
+        b
+    }
+}
+
+/// 返回`a`与`b`的和。
+#[vdso_api(since = "1.1", instances = "(u8, u16), (u16, u8)")]
+pub fn sum_pair<T: Into<usize>, U: Into<usize>>(a: T, b: U) -> usize {
+    a.into() + b.into()
+}
+
+/// 私有数据中的记录，保存在vDSO的私有堆中。
+static RECORDS: SpinLock<Vec<usize>> = SpinLock::new(Vec::new());
+
+/// 在私有数据中记录`i`，返回当前地址空间中的记录数。
+#[vdso_api(since = "1.1")]
+pub fn record_private(i: usize) -> usize {
+    let mut records = RECORDS.lock();
+    records.push(i);
+    records.len()
+}
+
+/// 第一次被推进时唤醒当前任务并返回`Pending`，第二次返回`Ready`。
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+/// 让出一次后返回`n + 1`。
+#[vdso_async(since = "1.1")]
+pub async fn async_add_one(n: usize) -> usize {
+    YieldNow(false).await;
+    n + 1
+}
+
+#[vdso_api]
+pub fn get_private() -> ArgumentExample {
     ArgumentExample {
         i: PRIVATE_DATA_EXAMPLE.load(Ordering::Acquire),
     }
 }
 
-#[unsafe(no_mangle)]
-pub extern "C" fn set_private(i: usize) {
+#[vdso_api]
+pub fn set_private(i: usize) {
     PRIVATE_DATA_EXAMPLE.store(i, Ordering::Release);
 }
 
-#[unsafe(no_mangle)]
-pub extern "C" fn test_args(
-    a: Option<usize>,
-    b: Result<usize, ()>,
-    c: (usize, usize),
-) -> (Option<usize>, Result<usize, ()>, (usize, usize)) {
-    (a.map(|i| i + 1), b.map(|i| i + 1), (c.0 + 1, c.1 + 1))
+/// 将`a`、`b`指向的值以及`c`中的各项加1。
+///
+/// 参数和返回值需要能通过C ABI传递：`Option`和`Result<_, ()>`只能包装引用等非空指针，多个值需要放在`#[repr(C)]`结构体中。
+#[vdso_api]
+pub fn test_args(a: Option<&mut usize>, b: Result<&mut usize, ()>, c: PairExample) -> PairExample {
+    if let Some(a) = a {
+        *a += 1;
+    }
+    if let Ok(b) = b {
+        *b += 1;
+    }
+    PairExample {
+        first: c.first + 1,
+        second: c.second + 1,
+    }
 }
 
-#[unsafe(no_mangle)]
-pub extern "C" fn test_call(ptr: *mut ()) {
+#[vdso_api]
+pub fn test_call(ptr: *mut ()) {
     interface::test_call(ptr);
 }
 
-#[unsafe(no_mangle)]
-pub extern "C" fn test_log() {
+#[vdso_api]
+pub fn test_log() {
     log::error!("Hello, this is a log within the vDSO!");
     log::warn!("Hello, this is a log within the vDSO!");
     log::info!("Hello, this is a log within the vDSO!");
@@ -52,6 +224,20 @@ pub extern "C" fn test_log() {
     log::trace!("Hello, this is a log within the vDSO!");
 }
 
+/// 旧版本的`test_version`，只返回主版本号。
+///
+/// 导出为`test_version@VDSO_1.0`，供依赖1.0版本API库的程序使用。
+#[vdso_api(compat = "1.0", symbol = "test_version")]
+pub fn test_version_v1_0() -> usize {
+    1
+}
+
+/// 返回vDSO的ABI版本号。
+#[vdso_api(since = "1.1")]
+pub fn test_version() -> VersionExample {
+    VersionExample { major: 1, minor: 1 }
+}
+
 // #[unsafe(no_mangle)]
 // pub extern "C" fn init_TestIf_table(test_fn1: usize, test_fn2: usize, test_fn3: usize) {
 //     interface::TestIf_TABLE.init_once([test_fn1, test_fn2, test_fn3]);
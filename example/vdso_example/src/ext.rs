@@ -0,0 +1,21 @@
+//! 按需加载的模块示例。
+//!
+//! 该模块中的API链接为单独的so文件，与主模块共享vVAR，在首次调用时才会被加载。
+
+use core::sync::atomic::Ordering;
+
+use vdso_helper::{get_vvar_data, vdso_api};
+
+use crate::interface;
+
+/// 返回共享数据与`n`之和。该模块与主模块读写同一份共享数据。
+#[vdso_api(module = "ext")]
+pub fn ext_get_shared(n: usize) -> usize {
+    get_vvar_data!(example).load(Ordering::Acquire) + n
+}
+
+/// 在该模块中调用依赖接口。在主模块中注册的依赖接口也会在该模块中注册。
+#[vdso_api(module = "ext")]
+pub fn ext_test_call(ptr: *mut ()) {
+    interface::test_call(ptr);
+}
@@ -1,3 +1,8 @@
+use core::sync::atomic::AtomicU32;
+
+use vdso_helper::lock::{Spin, Waiter};
+use vdso_helper::VvarPtr;
+
 vdso_helper::trait_interface! {
     pub trait TestIf {
         fn test_fn1(&self, arg: usize) -> usize;
@@ -6,6 +11,43 @@ vdso_helper::trait_interface! {
     }
 }
 
+vdso_helper::trait_interface! {
+    /// 等待和唤醒接口，由内核实现，用于在共享数据中的锁被占用时睡眠。
+    pub trait WaitIf {
+        /// 在`key`处的值仍为`expected`时睡眠。
+        ///
+        /// `key`为锁相对于vDSO首地址的偏移（见`VvarPtr::offset`），在各个地址空间中相同；
+        /// 内核根据调用者的地址空间中vDSO的首地址还原锁的地址。
+        fn wait(key: usize, expected: u32);
+        /// 唤醒至多`count`个在`key`上睡眠的执行流，`key`的含义同`wait`。
+        fn wake(key: usize, count: u32);
+    }
+}
+
+/// 通过`WaitIf`等待的[`Waiter`]，尚未注册`WaitIf`时退化为自旋。
+pub struct HookWaiter;
+
+/// 锁在各个地址空间中的地址不同，因此以其在vVAR中的偏移作为等待队列的键
+fn wait_key(key: &AtomicU32) -> usize {
+    VvarPtr::new(key.as_ptr()).offset() as usize
+}
+
+impl Waiter for HookWaiter {
+    fn wait(key: &AtomicU32, expected: u32) {
+        if WaitIf_TABLE.is_inited() {
+            WaitIfVirtImpl::wait(wait_key(key), expected);
+        } else {
+            Spin::wait(key, expected);
+        }
+    }
+
+    fn wake(key: &AtomicU32, count: u32) {
+        if WaitIf_TABLE.is_inited() {
+            WaitIfVirtImpl::wake(wait_key(key), count);
+        }
+    }
+}
+
 pub fn test_call(ptr: *mut ()) {
     let virt = unsafe { TestIfVirtImpl::from_mut(ptr) };
     virt.test_fn1(1);
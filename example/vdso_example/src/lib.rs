@@ -16,30 +16,95 @@
 //!
 //! 为了让项目的`build_vdso`库自动完成该库的构建，因此本库内的API需要遵从以下约定：
 //!
-//! 1. 所有的API均放置在本模块的`api`子模块中。
-//! 2. 目前，API仅支持函数形式。并且函数需要声明为`#[unsafe(no_mangle)]`和`pub extern "C"`。
-//! 3. 函数的参数和返回值用到的自定义数据结构，均需要声明为`pub`和`#[repr(C)]`（例如此处的`ArgumentExample`）。
+//! 1. API可以放置在任意模块中，并使用`vdso_helper::vdso_api`属性标记。
+//!     （也兼容旧的约定：在`api`子模块中声明为`#[unsafe(no_mangle)]`和`pub extern "C"`的函数。）
+//! 2. API为函数，或以`vdso_helper::vdso_impl`标记的`impl`块中的`pub`方法（例如`CounterHandle`的方法）。
+//! 3. 函数的参数和返回值需要能通过C ABI传递：用到的自定义数据结构均需要声明为`pub`和`#[repr(C)]`（例如此处的`ArgumentExample`），不能使用元组、切片、`&str`，`Option`和`Result`只能包装引用等非空指针。
 //! 4. 该库导出的所有函数和数据结构均需要导出在根模块中。
 //!     （例如，导出子模块中的`pub`符号时，需要使用`pub use submod::*;`，而非`pub use submod;`）
+//! 5. 以`#[vdso_api(module = "...")]`标记的API属于单独的模块，会链接为单独的so文件，在首次调用时按需加载（例如`ext`模块）。
 //!
 #![no_std]
 
+extern crate alloc;
+
 mod api;
+mod ext;
 mod interface;
 
 use core::sync::atomic::AtomicUsize;
-use vdso_helper::vvar_data;
+use vdso_helper::{
+    vvar_data, SeqLock, SpinLock, TicketLock, VvarArena, VvarLink, VvarLinked, VvarList,
+};
 
 pub use api::*;
+pub use ext::*;
 pub use interface::*;
 
 vvar_data! {
-    example: AtomicUsize
+    example: AtomicUsize,
+    /// 由内核更新的计数，用户进程只读
+    #[user_readonly]
+    ticks: AtomicUsize,
+    /// 由内核更新的时间，用户进程通过顺序锁读取一致的快照
+    #[user_readonly]
+    clock: SeqLock<ClockSnapshot>,
+    /// 由票据锁保护的计数，锁被占用时通过`WaitIf`等待
+    counter: TicketLock<usize, HookWaiter>,
+    /// 链表节点，通过位置无关指针链接，在各个地址空间中都能遍历
+    nodes: [NodeExample; 4],
+    /// 由`nodes`中的节点组成的队列
+    queue: SpinLock<VvarList<NodeExample>>,
+    /// 共享内存分配器，各个地址空间都可以在其中分配和释放对象
+    heap: VvarArena<0x4000>,
 }
 
 static PRIVATE_DATA_EXAMPLE: AtomicUsize = AtomicUsize::new(0);
 
 #[repr(C)]
+#[derive(PartialEq, PartialOrd)]
 pub struct ArgumentExample {
     pub i: usize,
 }
+
+/// 由`test_args`使用的一对值。
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PairExample {
+    pub first: usize,
+    pub second: usize,
+}
+
+/// ABI版本号，由`test_version`返回。
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VersionExample {
+    pub major: usize,
+    pub minor: usize,
+}
+
+/// 由内核更新的时间。
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ClockSnapshot {
+    pub secs: usize,
+    pub nanos: usize,
+}
+
+/// 共享计数的句柄，每次累加`step`。其方法以`#[vdso_impl]`导出，见`api`模块。
+#[repr(C)]
+pub struct CounterHandle {
+    pub step: usize,
+}
+
+/// 存放在vVAR中的链表节点。
+#[derive(Default)]
+pub struct NodeExample {
+    link: VvarLink<NodeExample>,
+}
+
+unsafe impl VvarLinked for NodeExample {
+    fn link(&self) -> &VvarLink<Self> {
+        &self.link
+    }
+}
@@ -0,0 +1,397 @@
+//! vVAR中的共享内存分配器。
+//!
+//! vDSO库无法使用外部的堆分配器，而[`vvar_data!`](`crate::vvar_data!`)中只能声明固定大小的数据。
+//! [`VvarArena`]在vVAR中声明的一块固定大小的内存上实现无锁的分配器，分配结果以[`VvarPtr`]表示，
+//! 因此任意地址空间都可以分配、访问和释放其中的共享对象（例如IPC消息、任务控制块）。
+//!
+//! 分配器按2的幂划分大小类别：每个类别维护一个无锁的空闲链表，释放的内存块加入对应类别的链表，
+//! 分配时优先从链表中取出，链表为空时从内存中尚未使用的部分顺序分配。
+//!
+//! [`PrivateHeap`]在vDSO的私有数据中使用相同的分配器，作为`#[global_allocator]`供`alloc` crate使用。
+//!
+//! ```ignore
+//! vvar_data! {
+//!     heap: VvarArena<0x10000>,
+//! }
+//!
+//! let msg: VvarPtr<Message> = get_vvar_data!(heap).alloc_value(Message::new()).unwrap();
+//! // 将msg通过VvarPtr传递给其它地址空间，由对方释放
+//! unsafe { get_vvar_data!(heap).free_value(msg) };
+//! ```
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    cell::UnsafeCell,
+    fmt,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+};
+
+use crate::{vvar_data::VvarZeroed, VvarPtr};
+
+/// 最小的大小类别（16字节）对应的2的幂
+const MIN_CLASS_SHIFT: u32 = 4;
+/// 大小类别的数量，最大的类别为512K
+const CLASSES: usize = 16;
+/// 内存块的最大对齐
+const MAX_ALIGN: usize = 64;
+
+/// 分配器管理的内存
+#[repr(C, align(64))]
+struct ArenaMemory<const SIZE: usize>([u8; SIZE]);
+
+/// vVAR中大小为`SIZE`字节的共享内存分配器。
+///
+/// 分配器的状态全部保存在自身中，可以直接声明在[`vvar_data!`](`crate::vvar_data!`)中。
+/// 分配的大小向上取整到2的幂（至少16字节），对齐不能超过64字节，大小不能超过512K。
+///
+/// 分配器的初始状态全为0（见[`VvarZeroed`]），内核在vVAR中原地初始化时不会在栈上构造分配器。
+#[repr(C)]
+pub struct VvarArena<const SIZE: usize> {
+    /// 顺序分配的位置
+    bump: AtomicU32,
+    /// 各个大小类别的空闲链表。高32位为修改次数（用于避免ABA问题），低32位为第一个空闲块的偏移加1，为0时链表为空
+    free_lists: [AtomicU64; CLASSES],
+    memory: UnsafeCell<ArenaMemory<SIZE>>,
+}
+
+unsafe impl<const SIZE: usize> Sync for VvarArena<SIZE> {}
+unsafe impl<const SIZE: usize> Send for VvarArena<SIZE> {}
+
+// `new`中的各项均为0
+unsafe impl<const SIZE: usize> VvarZeroed for VvarArena<SIZE> {}
+
+impl<const SIZE: usize> VvarArena<SIZE> {
+    /// 创建空的分配器。
+    pub const fn new() -> Self {
+        assert!(SIZE <= u32::MAX as usize, "VvarArena is too large");
+        Self {
+            bump: AtomicU32::new(0),
+            free_lists: [const { AtomicU64::new(0) }; CLASSES],
+            memory: UnsafeCell::new(ArenaMemory([0; SIZE])),
+        }
+    }
+
+    /// 分配符合`layout`的内存，内存不足或`layout`不受支持时返回空指针。
+    pub fn alloc(&self, layout: Layout) -> VvarPtr<u8> {
+        let Some(class) = Self::class_of(layout) else {
+            return VvarPtr::null();
+        };
+        match self.pop(class).or_else(|| self.bump(class)) {
+            Some(offset) => VvarPtr::new(unsafe { self.base().add(offset as usize) }),
+            None => VvarPtr::null(),
+        }
+    }
+
+    /// 释放由[`VvarArena::alloc`]分配的内存。
+    ///
+    /// # Safety
+    ///
+    /// `ptr`需要由同一个分配器以相同的`layout`分配，且尚未被释放。
+    pub unsafe fn dealloc(&self, ptr: VvarPtr<u8>, layout: Layout) {
+        if ptr.is_null() {
+            return;
+        }
+        let class = Self::class_of(layout).expect("invalid layout");
+        let offset = (ptr.as_ptr() as usize).wrapping_sub(self.base() as usize);
+        debug_assert!(offset < SIZE, "pointer does not belong to the arena");
+        self.push(class, offset as u32);
+    }
+
+    /// 分配内存并将`value`写入其中。内存不足时返回`Err(value)`。
+    pub fn alloc_value<T>(&self, value: T) -> Result<VvarPtr<T>, T> {
+        let ptr = self.alloc(Layout::new::<T>());
+        if ptr.is_null() {
+            return Err(value);
+        }
+        let ptr = VvarPtr::<T>::from_offset(ptr.offset());
+        unsafe { ptr.as_ptr().write(value) };
+        Ok(ptr)
+    }
+
+    /// 析构并释放由[`VvarArena::alloc_value`]分配的对象。
+    ///
+    /// # Safety
+    ///
+    /// `ptr`需要由同一个分配器的[`VvarArena::alloc_value`]分配，且尚未被释放，此后不能再访问该对象。
+    pub unsafe fn free_value<T>(&self, ptr: VvarPtr<T>) {
+        if ptr.is_null() {
+            return;
+        }
+        unsafe {
+            ptr.as_ptr().drop_in_place();
+            self.dealloc(VvarPtr::from_offset(ptr.offset()), Layout::new::<T>());
+        }
+    }
+
+    /// 已经顺序分配出去的字节数（包括已释放、位于空闲链表中的内存块）。
+    pub fn used(&self) -> usize {
+        self.bump.load(Ordering::Relaxed) as usize
+    }
+
+    /// 分配器管理的内存的大小。
+    pub const fn capacity(&self) -> usize {
+        SIZE
+    }
+
+    /// 当前地址空间中内存的首地址
+    fn base(&self) -> *mut u8 {
+        self.memory.get() as *mut u8
+    }
+
+    /// `layout`对应的大小类别
+    fn class_of(layout: Layout) -> Option<usize> {
+        if layout.align() > MAX_ALIGN {
+            return None;
+        }
+        let size = layout.size().max(layout.align()).max(1 << MIN_CLASS_SHIFT);
+        let class = (size.next_power_of_two().trailing_zeros() - MIN_CLASS_SHIFT) as usize;
+        (class < CLASSES).then_some(class)
+    }
+
+    /// 大小类别`class`中内存块的大小
+    const fn class_size(class: usize) -> usize {
+        1 << (class as u32 + MIN_CLASS_SHIFT)
+    }
+
+    /// 空闲内存块的开头用于保存下一个空闲块的偏移加1
+    fn next_of(&self, offset: u32) -> &AtomicU32 {
+        unsafe { &*(self.base().add(offset as usize) as *const AtomicU32) }
+    }
+
+    /// 从空闲链表中取出一个内存块
+    fn pop(&self, class: usize) -> Option<u32> {
+        let list = &self.free_lists[class];
+        let mut head = list.load(Ordering::Acquire);
+        loop {
+            let offset = (head as u32).checked_sub(1)?;
+            // 该内存块可能已被其它执行流取出并修改，此时读到的值无效，但修改次数的变化会使下面的CAS失败
+            let next = self.next_of(offset).load(Ordering::Relaxed);
+            let new = ((head >> 32).wrapping_add(1) << 32) | next as u64;
+            match list.compare_exchange_weak(head, new, Ordering::Acquire, Ordering::Acquire) {
+                Ok(_) => return Some(offset),
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    /// 将内存块加入空闲链表
+    fn push(&self, class: usize, offset: u32) {
+        let list = &self.free_lists[class];
+        let mut head = list.load(Ordering::Relaxed);
+        loop {
+            self.next_of(offset).store(head as u32, Ordering::Relaxed);
+            let new = ((head >> 32).wrapping_add(1) << 32) | (offset as u64 + 1);
+            match list.compare_exchange_weak(head, new, Ordering::Release, Ordering::Relaxed) {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    /// 从尚未使用的内存中顺序分配一个内存块
+    fn bump(&self, class: usize) -> Option<u32> {
+        let size = Self::class_size(class);
+        let align = size.min(MAX_ALIGN);
+        let mut current = self.bump.load(Ordering::Relaxed);
+        loop {
+            let offset = (current as usize + align - 1) & !(align - 1);
+            let end = offset.checked_add(size).filter(|&end| end <= SIZE)?;
+            match self.bump.compare_exchange_weak(
+                current,
+                end as u32,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(offset as u32),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+impl<const SIZE: usize> Default for VvarArena<SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SIZE: usize> fmt::Debug for VvarArena<SIZE> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VvarArena")
+            .field("capacity", &SIZE)
+            .field("used", &self.used())
+            .finish()
+    }
+}
+
+/// vDSO私有数据中大小为`SIZE`字节的堆分配器，可以注册为`#[global_allocator]`。
+///
+/// 由`build_vdso`在`BuildConfig::heap_size`不为0时自动注册，一般不需要手动使用。
+/// 与其它私有数据相同，每个地址空间持有各自的堆，在一个地址空间中分配的内存不能在其它地址空间中访问。
+///
+/// 分配的限制与[`VvarArena`]相同：对齐不能超过64字节，大小不能超过512K。
+pub struct PrivateHeap<const SIZE: usize>(VvarArena<SIZE>);
+
+impl<const SIZE: usize> PrivateHeap<SIZE> {
+    /// 创建空的堆。
+    pub const fn new() -> Self {
+        Self(VvarArena::new())
+    }
+
+    /// 已经使用的字节数，见[`VvarArena::used`]。
+    pub fn used(&self) -> usize {
+        self.0.used()
+    }
+}
+
+impl<const SIZE: usize> Default for PrivateHeap<SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<const SIZE: usize> GlobalAlloc for PrivateHeap<SIZE> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.0.alloc(layout).as_ptr()
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.0.dealloc(VvarPtr::new(ptr), layout) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{thread, vec::Vec};
+
+    use super::*;
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test]
+    fn reuse_per_class() {
+        static ARENA: VvarArena<0x1000> = VvarArena::new();
+        let a = ARENA.alloc(layout(24, 8));
+        let b = ARENA.alloc(layout(100, 4));
+        assert!(!a.is_null() && !b.is_null());
+        assert_ne!(a.offset(), b.offset());
+        // 24字节和100字节分别取整到32字节和128字节，后者按64字节对齐
+        assert_eq!(ARENA.used(), 64 + 128);
+        unsafe { ARENA.dealloc(a, layout(24, 8)) };
+        // 同一类别的请求复用释放的内存块，其它类别的请求不会取走它
+        let c = ARENA.alloc(layout(16, 16));
+        assert_ne!(c.offset(), a.offset());
+        let d = ARENA.alloc(layout(32, 1));
+        assert_eq!(d.offset(), a.offset());
+        let used = ARENA.used();
+        unsafe {
+            ARENA.dealloc(b, layout(100, 4));
+            ARENA.dealloc(c, layout(16, 16));
+            ARENA.dealloc(d, layout(32, 1));
+        }
+        // 释放的内存块全部复用，不再顺序分配
+        let again = [
+            ARENA.alloc(layout(128, 8)),
+            ARENA.alloc(layout(16, 1)),
+            ARENA.alloc(layout(20, 4)),
+        ];
+        assert_eq!(
+            again.map(|ptr| ptr.offset()),
+            [b.offset(), c.offset(), d.offset()]
+        );
+        assert_eq!(ARENA.used(), used);
+    }
+
+    #[test]
+    fn values() {
+        static ARENA: VvarArena<0x1000> = VvarArena::new();
+        let ptr = ARENA.alloc_value([7u64; 3]).unwrap();
+        assert_eq!(unsafe { *ptr.as_ptr() }, [7; 3]);
+        unsafe { ARENA.free_value(ptr) };
+        let again = ARENA.alloc_value(1u32).unwrap();
+        assert_ne!(again.offset(), ptr.offset());
+        let same_class = ARENA.alloc_value([0u8; 32]).unwrap();
+        assert_eq!(same_class.offset(), ptr.offset());
+    }
+
+    #[test]
+    fn exhaustion() {
+        static ARENA: VvarArena<256> = VvarArena::new();
+        let blocks: Vec<_> = (0..4).map(|_| ARENA.alloc(layout(64, 8))).collect();
+        assert!(blocks.iter().all(|ptr| !ptr.is_null()));
+        assert!(ARENA.alloc(layout(64, 8)).is_null());
+        assert!(ARENA.alloc(layout(1, 1)).is_null());
+        assert_eq!(ARENA.alloc_value(5usize).map(|_| ()), Err(5));
+        // 不受支持的大小和对齐
+        assert!(ARENA.alloc(layout(512, 8)).is_null());
+        assert!(ARENA.alloc(layout(1 << 20, 8)).is_null());
+        assert!(ARENA.alloc(layout(8, 128)).is_null());
+        unsafe { ARENA.dealloc(blocks[2], layout(64, 8)) };
+        assert_eq!(ARENA.alloc(layout(40, 8)).offset(), blocks[2].offset());
+        assert!(ARENA.alloc(layout(40, 8)).is_null());
+        assert_eq!(ARENA.used(), ARENA.capacity());
+    }
+
+    #[test]
+    fn alignment() {
+        static ARENA: VvarArena<0x4000> = VvarArena::new();
+        for align in [1, 2, 4, 8, 16, 32, 64] {
+            for size in [1, 3, 17, 48, 65, 200] {
+                let ptr = ARENA.alloc(layout(size, align));
+                assert!(!ptr.is_null());
+                let addr = ptr.as_ptr() as usize;
+                assert_eq!(addr % align, 0, "size {} align {}", size, align);
+                // 内存块按其大小（至多64字节）对齐
+                assert_eq!(addr % size.next_power_of_two().clamp(16, MAX_ALIGN), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn concurrent_alloc_free() {
+        const THREADS: usize = 8;
+        const ROUNDS: usize = 2000;
+        static ARENA: VvarArena<0x10000> = VvarArena::new();
+        let threads: Vec<_> = (0..THREADS)
+            .map(|t| {
+                thread::spawn(move || {
+                    let mut live: Vec<(isize, usize, u64)> = Vec::new();
+                    for i in 0..ROUNDS {
+                        let size = 8 << (i % 4);
+                        let value = ((t as u64) << 32) | i as u64;
+                        let ptr = ARENA.alloc(layout(size, 8));
+                        assert!(!ptr.is_null(), "arena exhausted");
+                        // 在整个内存块中写满标记，其它线程的写入会破坏标记
+                        let words = size / 8;
+                        let block = ptr.as_ptr() as *mut u64;
+                        (0..words).for_each(|w| unsafe { block.add(w).write(value) });
+                        live.push((ptr.offset(), size, value));
+                        if live.len() > 8 || i % 3 == 0 {
+                            let (offset, size, value) = live.swap_remove((i * 7) % live.len());
+                            let block = VvarPtr::<u64>::from_offset(offset).as_ptr();
+                            (0..size / 8).for_each(|w| assert_eq!(unsafe { *block.add(w) }, value));
+                            unsafe {
+                                ARENA.dealloc(VvarPtr::from_offset(offset), layout(size, 8));
+                            }
+                        }
+                    }
+                    for (offset, size, _) in live {
+                        unsafe { ARENA.dealloc(VvarPtr::from_offset(offset), layout(size, 8)) };
+                    }
+                })
+            })
+            .collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        // 全部释放后，每个类别的空闲块都可以再次分配，而不需要顺序分配新的内存
+        let used = ARENA.used();
+        let again: Vec<_> = (0..4)
+            .map(|class| ARENA.alloc(layout(8 << class, 8)))
+            .collect();
+        assert!(again.iter().all(|ptr| !ptr.is_null()));
+        assert_eq!(ARENA.used(), used);
+    }
+}
@@ -0,0 +1,70 @@
+//! 支持[`macro@crate::vdso_async`]的辅助函数。
+//!
+//! `async fn`返回的Future类型无法命名，因此这里的函数都接受一个调用该`async fn`的闭包（参数为所有实参组成的元组），
+//! 通过闭包的返回值类型推导出Future的类型。除[`init_state`]外，闭包只用于类型推导，不会被调用。
+//!
+//! 这些函数由[`macro@crate::vdso_async`]生成的代码调用，不应被用户直接调用。
+
+use core::{
+    alloc::Layout,
+    future::Future,
+    mem::ManuallyDrop,
+    pin::Pin,
+    ptr,
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+/// Future的内存布局。
+pub fn state_layout<A, F>(_f: &impl FnOnce(A) -> F) -> Layout {
+    Layout::new::<F>()
+}
+
+/// 调用`f`创建Future，并将其写入`state`。
+///
+/// # Safety
+///
+/// `state`需要满足[`state_layout`]返回的大小和对齐，且其中没有未析构的Future。
+pub unsafe fn init_state<A, F>(f: impl FnOnce(A) -> F, args: A, state: *mut u8) {
+    unsafe { (state as *mut F).write(f(args)) };
+}
+
+/// 以调用者的Waker推进`state`中的Future。
+///
+/// 完成时将结果写入`output`，析构`state`中的Future，并返回`true`；否则返回`false`。
+///
+/// # Safety
+///
+/// `state`中需要有由[`init_state`]写入、尚未完成的Future，且自写入后没有移动；
+/// `waker_data`和`waker_vtable`需要来自调用者的一个有效的`Waker`。
+pub unsafe fn poll_state<A, F: Future>(
+    _f: &impl FnOnce(A) -> F,
+    state: *mut u8,
+    waker_data: *const (),
+    waker_vtable: *const RawWakerVTable,
+    output: *mut F::Output,
+) -> bool {
+    // Waker仍属于调用者，这里只是借用，因此不能析构
+    let waker =
+        ManuallyDrop::new(unsafe { Waker::from_raw(RawWaker::new(waker_data, &*waker_vtable)) });
+    let mut cx = Context::from_waker(&waker);
+    let future = unsafe { Pin::new_unchecked(&mut *(state as *mut F)) };
+    match future.poll(&mut cx) {
+        Poll::Ready(value) => {
+            unsafe {
+                output.write(value);
+                ptr::drop_in_place(state as *mut F);
+            }
+            true
+        }
+        Poll::Pending => false,
+    }
+}
+
+/// 析构`state`中尚未完成的Future。
+///
+/// # Safety
+///
+/// `state`中需要有由[`init_state`]写入、尚未完成的Future。
+pub unsafe fn drop_state<A, F>(_f: &impl FnOnce(A) -> F, state: *mut u8) {
+    unsafe { ptr::drop_in_place(state as *mut F) };
+}
@@ -1,19 +1,36 @@
 //! 提供vDSO库相关的辅助功能。
 //!
+//! - [`mod@arena`]模块提供vVAR中的共享内存分配器，以及私有数据中的堆分配器。
 //! - [`mod@vvar_data`]模块用于声明和使用vVAR共享数据。
+//! - [`mod@vvar_ptr`]模块提供可存放在vVAR中的位置无关指针，以及链表和树的构件。
+//! - [`mod@lock`]模块提供可放在vVAR中、跨地址空间使用的锁。
 //! - [`mod@mut_cfg`]模块用于在编译期由环境变量指定的常量。
+//! - [`mod@seqlock`]模块提供由顺序锁保护、可放在vVAR中的共享数据。
+//! - [`macro@vdso_api`]属性用于将函数标记为vDSO的API。
+//! - [`macro@vdso_async`]属性用于将`async fn`导出为vDSO的异步API，[`mod@async_api`]模块提供其使用的辅助函数。
+//! - [`macro@vdso_impl`]属性用于将`#[repr(C)]`类型的方法导出为vDSO的API。
 
 #![no_std]
 #![deny(missing_docs)]
 
+pub mod arena;
+pub mod async_api;
+pub mod lock;
 #[cfg(feature = "log")]
 pub mod log_init;
 pub mod mut_cfg;
+pub mod seqlock;
 pub mod trait_interface;
 pub mod vvar_data;
+pub mod vvar_ptr;
 
+pub use arena::{PrivateHeap, VvarArena};
 pub use lazyinit;
+pub use lock::{RwLock, SpinLock, TicketLock, Waiter};
 pub use paste;
+pub use seqlock::SeqLock;
+pub use vdso_macros::{vdso_api, vdso_async, vdso_impl};
+pub use vvar_ptr::{AtomicVvarPtr, VvarLink, VvarLinked, VvarList, VvarPtr, VvarTreeLink};
 
 #[cfg(feature = "log")]
 pub use log;
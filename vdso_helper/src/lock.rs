@@ -0,0 +1,700 @@
+//! 可以放在vVAR中、跨地址空间使用的锁。
+//!
+//! vVAR中的共享数据在内核和各个进程中位于不同的虚拟地址，因此这里的锁均为`#[repr(C)]`，
+//! 状态全部保存在锁自身中，不包含任何指针，在任意地址空间中访问同一把锁都能正确互斥。
+//! 所有锁都实现了`Default`，可以直接声明在[`vvar_data!`](`crate::vvar_data!`)中
+//! （但不能放在`#[user_readonly]`标记的项中，因为加锁需要写入锁的状态）。
+//!
+//! - [`SpinLock`]：互斥锁。
+//! - [`TicketLock`]：按照请求顺序获得锁的公平互斥锁。
+//! - [`RwLock`]：读写锁。
+//!
+//! 锁的类型参数`W`指定获取锁失败时的等待方式，见[`Waiter`]。默认的[`Spin`]在原地自旋。
+
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    hint::spin_loop,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+/// 获取锁失败时的等待方式。
+///
+/// 语义与Linux的futex相同：`wait(key, expected)`在`key`的值仍为`expected`时睡眠，直到被`wake`唤醒，允许虚假唤醒；
+/// `wake(key, count)`至多唤醒`count`个在`key`上等待的执行流。
+///
+/// 同一把锁在不同地址空间中的地址不同，因此`Waiter`的实现需要以`key`所在的物理地址区分等待队列。
+///
+/// 若需要在等待时睡眠而不是自旋，可以在vDSO库中通过[`trait_interface!`](`crate::trait_interface!`)声明等待接口，
+/// 并在其基础上实现`Waiter`，由内核注册实际的等待和唤醒函数：
+///
+/// ```ignore
+/// trait_interface! {
+///     pub trait WaitIf {
+///         fn wait(key: usize, expected: u32);
+///         fn wake(key: usize, count: u32);
+///     }
+/// }
+///
+/// pub struct HookWaiter;
+///
+/// // 锁的虚拟地址在各个地址空间中不同，不能作为等待队列的键；
+/// // 以锁在vVAR中的偏移作为键，由内核根据调用者的地址空间还原地址，再以其物理地址区分等待队列
+/// fn wait_key(key: &AtomicU32) -> usize {
+///     VvarPtr::new(key.as_ptr()).offset() as usize
+/// }
+///
+/// impl Waiter for HookWaiter {
+///     fn wait(key: &AtomicU32, expected: u32) {
+///         // 尚未注册等待接口时，退化为自旋
+///         if WaitIf_TABLE.is_inited() {
+///             WaitIfVirtImpl::wait(wait_key(key), expected);
+///         } else {
+///             Spin::wait(key, expected);
+///         }
+///     }
+///
+///     fn wake(key: &AtomicU32, count: u32) {
+///         if WaitIf_TABLE.is_inited() {
+///             WaitIfVirtImpl::wake(wait_key(key), count);
+///         }
+///     }
+/// }
+///
+/// vvar_data! {
+///     counter: TicketLock<usize, HookWaiter>,
+/// }
+/// ```
+pub trait Waiter {
+    /// 在`key`的值仍为`expected`时等待。
+    fn wait(key: &AtomicU32, expected: u32);
+    /// 唤醒至多`count`个在`key`上等待的执行流。
+    fn wake(key: &AtomicU32, count: u32);
+}
+
+/// 在原地自旋的[`Waiter`]。
+#[derive(Debug, Default)]
+pub struct Spin;
+
+impl Waiter for Spin {
+    fn wait(_key: &AtomicU32, _expected: u32) {
+        spin_loop();
+    }
+
+    fn wake(_key: &AtomicU32, _count: u32) {}
+}
+
+/// 锁的类型参数，不影响锁的`Send`和`Sync`。
+type WaiterMarker<W> = PhantomData<fn() -> W>;
+
+/// 互斥锁。
+///
+/// 状态为0时未加锁，为1时已加锁，为2时已加锁且可能有等待者。只有存在等待者时，释放锁才会调用[`Waiter::wake`]。
+#[repr(C)]
+pub struct SpinLock<T, W: Waiter = Spin> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+    _waiter: WaiterMarker<W>,
+}
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+const CONTENDED: u32 = 2;
+
+unsafe impl<T: Send, W: Waiter> Sync for SpinLock<T, W> {}
+unsafe impl<T: Send, W: Waiter> Send for SpinLock<T, W> {}
+
+impl<T, W: Waiter> SpinLock<T, W> {
+    /// 创建未加锁的互斥锁。
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+            data: UnsafeCell::new(data),
+            _waiter: PhantomData,
+        }
+    }
+
+    /// 获取锁。
+    pub fn lock(&self) -> SpinLockGuard<'_, T, W> {
+        if let Err(mut state) =
+            self.state
+                .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+        {
+            // 将状态设为2，使持有者在释放锁时唤醒等待者
+            if state != CONTENDED {
+                state = self.state.swap(CONTENDED, Ordering::Acquire);
+            }
+            while state != UNLOCKED {
+                W::wait(&self.state, CONTENDED);
+                state = self.state.swap(CONTENDED, Ordering::Acquire);
+            }
+        }
+        SpinLockGuard { lock: self }
+    }
+
+    /// 尝试获取锁，锁已被持有时返回`None`。
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T, W>> {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| SpinLockGuard { lock: self })
+    }
+
+    /// 锁是否已被持有。
+    pub fn is_locked(&self) -> bool {
+        self.state.load(Ordering::Relaxed) != UNLOCKED
+    }
+
+    /// 获取数据的可变引用。由于持有`&mut self`，不需要加锁。
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            W::wake(&self.state, 1);
+        }
+    }
+}
+
+/// [`SpinLock`]的锁守卫，离开作用域时释放锁。
+pub struct SpinLockGuard<'a, T, W: Waiter = Spin> {
+    lock: &'a SpinLock<T, W>,
+}
+
+impl<T, W: Waiter> Deref for SpinLockGuard<'_, T, W> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T, W: Waiter> DerefMut for SpinLockGuard<'_, T, W> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T, W: Waiter> Drop for SpinLockGuard<'_, T, W> {
+    fn drop(&mut self) {
+        self.lock.unlock();
+    }
+}
+
+/// 票据锁。
+///
+/// 每个请求者领取一个递增的票号，按照票号顺序获得锁，不会出现饥饿。
+#[repr(C)]
+pub struct TicketLock<T, W: Waiter = Spin> {
+    next: AtomicU32,
+    serving: AtomicU32,
+    data: UnsafeCell<T>,
+    _waiter: WaiterMarker<W>,
+}
+
+unsafe impl<T: Send, W: Waiter> Sync for TicketLock<T, W> {}
+unsafe impl<T: Send, W: Waiter> Send for TicketLock<T, W> {}
+
+impl<T, W: Waiter> TicketLock<T, W> {
+    /// 创建未加锁的票据锁。
+    pub const fn new(data: T) -> Self {
+        Self {
+            next: AtomicU32::new(0),
+            serving: AtomicU32::new(0),
+            data: UnsafeCell::new(data),
+            _waiter: PhantomData,
+        }
+    }
+
+    /// 获取锁。
+    pub fn lock(&self) -> TicketLockGuard<'_, T, W> {
+        // 领取票号与读取`serving`、以及`unlock`中递增`serving`与读取`next`涉及两个原子变量，
+        // 均需使用`SeqCst`，否则`unlock`可能读到旧的`next`而不唤醒即将睡眠的请求者
+        let ticket = self.next.fetch_add(1, Ordering::SeqCst);
+        loop {
+            let serving = self.serving.load(Ordering::SeqCst);
+            if serving == ticket {
+                return TicketLockGuard { lock: self };
+            }
+            W::wait(&self.serving, serving);
+        }
+    }
+
+    /// 尝试获取锁，锁已被持有或有其它请求者在等待时返回`None`。
+    pub fn try_lock(&self) -> Option<TicketLockGuard<'_, T, W>> {
+        let serving = self.serving.load(Ordering::Acquire);
+        self.next
+            .compare_exchange(
+                serving,
+                serving.wrapping_add(1),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .ok()
+            .map(|_| TicketLockGuard { lock: self })
+    }
+
+    /// 锁是否已被持有。
+    pub fn is_locked(&self) -> bool {
+        self.next.load(Ordering::Relaxed) != self.serving.load(Ordering::Relaxed)
+    }
+
+    /// 获取数据的可变引用。由于持有`&mut self`，不需要加锁。
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn unlock(&self) {
+        let serving = self.serving.fetch_add(1, Ordering::SeqCst).wrapping_add(1);
+        // 还有请求者在等待时，唤醒全部等待者，由持有下一个票号的请求者获得锁
+        if self.next.load(Ordering::SeqCst) != serving {
+            W::wake(&self.serving, u32::MAX);
+        }
+    }
+}
+
+/// [`TicketLock`]的锁守卫，离开作用域时释放锁。
+pub struct TicketLockGuard<'a, T, W: Waiter = Spin> {
+    lock: &'a TicketLock<T, W>,
+}
+
+impl<T, W: Waiter> Deref for TicketLockGuard<'_, T, W> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T, W: Waiter> DerefMut for TicketLockGuard<'_, T, W> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T, W: Waiter> Drop for TicketLockGuard<'_, T, W> {
+    fn drop(&mut self) {
+        self.lock.unlock();
+    }
+}
+
+/// 读写锁。
+///
+/// 状态的最低位表示写者持有锁，次低位表示可能有等待者，其余位为持有锁的读者数量。
+/// 写者持有锁时，读者和写者均需等待；读者持有锁时，新的读者可以直接获得锁，写者需要等待所有读者释放锁。
+#[repr(C)]
+pub struct RwLock<T, W: Waiter = Spin> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+    _waiter: WaiterMarker<W>,
+}
+
+const WRITER: u32 = 1;
+const WAITING: u32 = 2;
+const READER: u32 = 4;
+
+unsafe impl<T: Send + Sync, W: Waiter> Sync for RwLock<T, W> {}
+unsafe impl<T: Send, W: Waiter> Send for RwLock<T, W> {}
+
+impl<T, W: Waiter> RwLock<T, W> {
+    /// 创建未加锁的读写锁。
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            data: UnsafeCell::new(data),
+            _waiter: PhantomData,
+        }
+    }
+
+    /// 以读者身份获取锁。
+    pub fn read(&self) -> RwLockReadGuard<'_, T, W> {
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            if state & WRITER == 0 {
+                if self
+                    .state
+                    .compare_exchange_weak(
+                        state,
+                        state + READER,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+                {
+                    return RwLockReadGuard { lock: self };
+                }
+                spin_loop();
+            } else {
+                self.wait(state);
+            }
+        }
+    }
+
+    /// 以写者身份获取锁。
+    pub fn write(&self) -> RwLockWriteGuard<'_, T, W> {
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            if state & !WAITING == 0 {
+                // 保留等待标记，使释放锁时唤醒其它等待者
+                if self
+                    .state
+                    .compare_exchange_weak(
+                        state,
+                        state | WRITER,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+                {
+                    return RwLockWriteGuard { lock: self };
+                }
+                spin_loop();
+            } else {
+                self.wait(state);
+            }
+        }
+    }
+
+    /// 尝试以读者身份获取锁，写者持有锁时返回`None`。
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T, W>> {
+        let state = self.state.load(Ordering::Relaxed);
+        if state & WRITER != 0 {
+            return None;
+        }
+        self.state
+            .compare_exchange(state, state + READER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| RwLockReadGuard { lock: self })
+    }
+
+    /// 尝试以写者身份获取锁，锁已被持有时返回`None`。
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T, W>> {
+        let state = self.state.load(Ordering::Relaxed) & WAITING;
+        self.state
+            .compare_exchange(state, state | WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| RwLockWriteGuard { lock: self })
+    }
+
+    /// 获取数据的可变引用。由于持有`&mut self`，不需要加锁。
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /// 设置等待标记，并在状态不变时等待。
+    fn wait(&self, state: u32) {
+        if state & WAITING != 0
+            || self
+                .state
+                .compare_exchange(state, state | WAITING, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            W::wait(&self.state, state | WAITING);
+        }
+    }
+
+    /// 清除等待标记，并唤醒全部等待者。
+    fn wake(&self) {
+        if self
+            .state
+            .compare_exchange(WAITING, 0, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            W::wake(&self.state, u32::MAX);
+        }
+    }
+
+    fn read_unlock(&self) {
+        let state = self.state.fetch_sub(READER, Ordering::Release) - READER;
+        // 最后一个读者释放锁时，若有等待者则唤醒
+        if state == WAITING {
+            self.wake();
+        }
+    }
+
+    fn write_unlock(&self) {
+        let state = self.state.fetch_and(!WRITER, Ordering::Release) & !WRITER;
+        if state == WAITING {
+            self.wake();
+        }
+    }
+}
+
+/// [`RwLock`]的读者锁守卫，离开作用域时释放锁。
+pub struct RwLockReadGuard<'a, T, W: Waiter = Spin> {
+    lock: &'a RwLock<T, W>,
+}
+
+impl<T, W: Waiter> Deref for RwLockReadGuard<'_, T, W> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T, W: Waiter> Drop for RwLockReadGuard<'_, T, W> {
+    fn drop(&mut self) {
+        self.lock.read_unlock();
+    }
+}
+
+/// [`RwLock`]的写者锁守卫，离开作用域时释放锁。
+pub struct RwLockWriteGuard<'a, T, W: Waiter = Spin> {
+    lock: &'a RwLock<T, W>,
+}
+
+impl<T, W: Waiter> Deref for RwLockWriteGuard<'_, T, W> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T, W: Waiter> DerefMut for RwLockWriteGuard<'_, T, W> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T, W: Waiter> Drop for RwLockWriteGuard<'_, T, W> {
+    fn drop(&mut self) {
+        self.lock.write_unlock();
+    }
+}
+
+impl<T: Default, W: Waiter> Default for SpinLock<T, W> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Default, W: Waiter> Default for TicketLock<T, W> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Default, W: Waiter> Default for RwLock<T, W> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug, W: Waiter> fmt::Debug for SpinLock<T, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => f.debug_struct("SpinLock").field("data", &*guard).finish(),
+            None => f.write_str("SpinLock { <locked> }"),
+        }
+    }
+}
+
+impl<T: fmt::Debug, W: Waiter> fmt::Debug for TicketLock<T, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => f.debug_struct("TicketLock").field("data", &*guard).finish(),
+            None => f.write_str("TicketLock { <locked> }"),
+        }
+    }
+}
+
+impl<T: fmt::Debug, W: Waiter> fmt::Debug for RwLock<T, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => f.debug_struct("RwLock").field("data", &*guard).finish(),
+            None => f.write_str("RwLock { <locked> }"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::sync::atomic::{AtomicBool, AtomicUsize};
+    use std::{sync::Arc, thread, vec::Vec};
+
+    use super::*;
+
+    /// 各个测试使用的[`Counting`]的调用次数，以`ID`区分，避免并行运行的测试互相影响
+    static WAITS: [AtomicUsize; 4] = [const { AtomicUsize::new(0) }; 4];
+    static WAKES: [AtomicUsize; 4] = [const { AtomicUsize::new(0) }; 4];
+
+    /// 记录调用次数并让出CPU的[`Waiter`]
+    struct Counting<const ID: usize>;
+
+    impl<const ID: usize> Waiter for Counting<ID> {
+        fn wait(key: &AtomicU32, expected: u32) {
+            WAITS[ID].fetch_add(1, Ordering::SeqCst);
+            if key.load(Ordering::SeqCst) == expected {
+                thread::yield_now();
+            }
+        }
+
+        fn wake(_key: &AtomicU32, _count: u32) {
+            WAKES[ID].fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn waits(id: usize) -> usize {
+        WAITS[id].load(Ordering::SeqCst)
+    }
+
+    fn wakes(id: usize) -> usize {
+        WAKES[id].load(Ordering::SeqCst)
+    }
+
+    /// 在另一个线程中执行`f`，等到它调用[`Waiter::wait`]后再执行`release`，检查等待者被唤醒
+    fn assert_hook_invoked<const ID: usize>(
+        release: impl FnOnce(),
+        f: impl FnOnce() + Send + 'static,
+    ) {
+        let done = Arc::new(AtomicBool::new(false));
+        let waiter = {
+            let done = done.clone();
+            thread::spawn(move || {
+                f();
+                done.store(true, Ordering::SeqCst);
+            })
+        };
+        while waits(ID) == 0 {
+            thread::yield_now();
+        }
+        assert!(!done.load(Ordering::SeqCst));
+        assert_eq!(wakes(ID), 0);
+        release();
+        waiter.join().unwrap();
+        assert!(wakes(ID) > 0);
+    }
+
+    #[test]
+    fn ticket_lock_mutual_exclusion() {
+        const THREADS: usize = 8;
+        const ROUNDS: usize = 5000;
+        static LOCK: TicketLock<(usize, usize), Counting<0>> = TicketLock::new((0, 0));
+        static INSIDE: AtomicBool = AtomicBool::new(false);
+        let threads: Vec<_> = (0..THREADS)
+            .map(|_| {
+                thread::spawn(|| {
+                    for _ in 0..ROUNDS {
+                        let mut guard = LOCK.lock();
+                        assert!(!INSIDE.swap(true, Ordering::Relaxed));
+                        // 两次非原子的写入之间让出CPU，其它线程若同时持有锁会看到不一致的值
+                        assert_eq!(guard.0, guard.1);
+                        guard.0 += 1;
+                        thread::yield_now();
+                        guard.1 += 1;
+                        INSIDE.store(false, Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        assert_eq!(*LOCK.lock(), (THREADS * ROUNDS, THREADS * ROUNDS));
+        assert!(!LOCK.is_locked());
+    }
+
+    #[test]
+    fn ticket_lock_try_lock() {
+        let lock: TicketLock<usize> = TicketLock::new(0);
+        let guard = lock.try_lock().unwrap();
+        assert!(lock.is_locked());
+        assert!(lock.try_lock().is_none());
+        drop(guard);
+        assert!(!lock.is_locked());
+        assert!(lock.try_lock().is_some());
+    }
+
+    #[test]
+    fn ticket_lock_waiter_hook() {
+        static LOCK: TicketLock<usize, Counting<1>> = TicketLock::new(0);
+        let guard = LOCK.lock();
+        assert_hook_invoked::<1>(
+            || drop(guard),
+            || {
+                *LOCK.lock() += 1;
+            },
+        );
+        assert_eq!(*LOCK.lock(), 1);
+    }
+
+    #[test]
+    fn rw_lock_exclusion() {
+        const READERS: usize = 4;
+        const WRITERS: usize = 4;
+        const ROUNDS: usize = 2000;
+        static LOCK: RwLock<(usize, usize), Counting<2>> = RwLock::new((0, 0));
+        static ACTIVE_READERS: AtomicUsize = AtomicUsize::new(0);
+        static WRITING: AtomicBool = AtomicBool::new(false);
+        let readers = (0..READERS).map(|_| {
+            thread::spawn(|| {
+                for _ in 0..ROUNDS {
+                    let guard = LOCK.read();
+                    ACTIVE_READERS.fetch_add(1, Ordering::SeqCst);
+                    assert!(!WRITING.load(Ordering::SeqCst));
+                    assert_eq!(guard.0, guard.1);
+                    ACTIVE_READERS.fetch_sub(1, Ordering::SeqCst);
+                }
+            })
+        });
+        let writers = (0..WRITERS).map(|_| {
+            thread::spawn(|| {
+                for _ in 0..ROUNDS {
+                    let mut guard = LOCK.write();
+                    assert!(!WRITING.swap(true, Ordering::SeqCst));
+                    assert_eq!(ACTIVE_READERS.load(Ordering::SeqCst), 0);
+                    guard.0 += 1;
+                    thread::yield_now();
+                    guard.1 += 1;
+                    WRITING.store(false, Ordering::SeqCst);
+                }
+            })
+        });
+        let threads: Vec<_> = readers.chain(writers).collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        assert_eq!(*LOCK.read(), (WRITERS * ROUNDS, WRITERS * ROUNDS));
+    }
+
+    #[test]
+    fn rw_lock_try() {
+        let lock: RwLock<usize> = RwLock::new(0);
+        // 多个读者可以同时持有锁，此时写者不能获得锁
+        let r1 = lock.read();
+        let r2 = lock.try_read().unwrap();
+        assert!(lock.try_write().is_none());
+        drop((r1, r2));
+        let w = lock.try_write().unwrap();
+        assert!(lock.try_read().is_none());
+        assert!(lock.try_write().is_none());
+        drop(w);
+        assert!(lock.try_read().is_some());
+    }
+
+    #[test]
+    fn rw_lock_waiter_hook() {
+        static LOCK: RwLock<usize, Counting<3>> = RwLock::new(0);
+        let guard = LOCK.write();
+        assert_hook_invoked::<3>(
+            || drop(guard),
+            || {
+                assert_eq!(*LOCK.read(), 0);
+            },
+        );
+        // 读者持有锁时，写者同样通过`Waiter`等待
+        let before = (waits(3), wakes(3));
+        let guard = LOCK.read();
+        let writer = thread::spawn(|| *LOCK.write() += 1);
+        while waits(3) == before.0 {
+            thread::yield_now();
+        }
+        drop(guard);
+        writer.join().unwrap();
+        assert!(wakes(3) > before.1);
+        assert_eq!(*LOCK.read(), 1);
+    }
+}
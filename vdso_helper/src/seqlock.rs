@@ -0,0 +1,145 @@
+//! 由顺序锁保护的共享数据。
+//!
+//! vDSO中常见的场景是内核作为唯一的写者更新数据（例如时间、负载和调度统计），而大量用户进程无锁地读取。
+//! [`SeqLock`]可以直接放在[`vvar_data!`](`crate::vvar_data!`)中：
+//! 写者通过[`SeqLock::write`]修改数据，读者通过[`SeqLock::read`]获取一份一致的快照，
+//! 若读取期间数据被修改，则会重试。读者不会写入任何内存，因此同样适用于`#[user_readonly]`标记的项。
+//!
+//! ```ignore
+//! vvar_data! {
+//!     #[user_readonly]
+//!     clock: SeqLock<(u64, u64)>,
+//! }
+//!
+//! // 内核中
+//! get_vvar_data!(clock).write(|c| *c = (sec, nsec));
+//! // 用户进程中
+//! let (sec, nsec) = get_vvar_data!(clock).read();
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    hint::spin_loop,
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::{fence, AtomicUsize, Ordering},
+};
+
+/// 顺序锁。
+///
+/// 序号为偶数时数据处于稳定状态，为奇数时写者正在修改数据。
+/// 写者之间通过序号互斥；读者不持有锁，读取前后的序号相同且为偶数时，读到的数据才是一致的。
+///
+/// `T`需要实现`Copy`，因为读者可能读到正在被修改的数据，并在校验失败后丢弃它。
+#[repr(C)]
+pub struct SeqLock<T: Copy> {
+    seq: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+unsafe impl<T: Copy + Send> Send for SeqLock<T> {}
+
+impl<T: Copy> SeqLock<T> {
+    /// 以初始值`data`创建顺序锁。
+    pub const fn new(data: T) -> Self {
+        Self {
+            seq: AtomicUsize::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// 读取数据的一份一致的快照。若读取期间有写者修改数据，则重试。
+    pub fn read(&self) -> T {
+        loop {
+            if let Some(data) = self.try_read() {
+                return data;
+            }
+            spin_loop();
+        }
+    }
+
+    /// 尝试读取数据，若有写者正在修改或读取期间数据被修改，则返回`None`。
+    pub fn try_read(&self) -> Option<T> {
+        // Acquire保证之后对数据的读取不会被重排到读取序号之前
+        let seq1 = self.seq.load(Ordering::Acquire);
+        if seq1 & 1 != 0 {
+            return None;
+        }
+        // 数据可能正在被写者修改，因此逐字节进行volatile读取，并在校验序号后才使用
+        let data = unsafe { read_volatile_bytes(self.data.get()) };
+        // Acquire屏障保证对数据的读取不会被重排到第二次读取序号之后
+        fence(Ordering::Acquire);
+        let seq2 = self.seq.load(Ordering::Relaxed);
+        if seq1 == seq2 {
+            Some(unsafe { data.assume_init() })
+        } else {
+            None
+        }
+    }
+
+    /// 修改数据。多个写者之间互斥，读者会等待修改完成后再返回。
+    ///
+    /// `f`中不应发生panic，否则序号将一直为奇数，读者会一直等待。
+    pub fn write<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let seq = self.lock();
+        // Release屏障保证序号变为奇数先于对数据的修改被读者看到
+        fence(Ordering::Release);
+        let ret = f(unsafe { &mut *self.data.get() });
+        // Release保证对数据的修改先于序号变为偶数被读者看到
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+        ret
+    }
+
+    /// 获取数据的可变引用。由于持有`&mut self`，不需要加锁。
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /// 将序号从偶数改为奇数，返回原来的序号。
+    fn lock(&self) -> usize {
+        loop {
+            let seq = self.seq.load(Ordering::Relaxed);
+            if seq & 1 == 0
+                && self
+                    .seq
+                    .compare_exchange_weak(
+                        seq,
+                        seq.wrapping_add(1),
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+            {
+                return seq;
+            }
+            spin_loop();
+        }
+    }
+}
+
+/// 逐字节volatile读取`src`处的`T`。读到的值可能不一致，需要校验后才能`assume_init`。
+unsafe fn read_volatile_bytes<T: Copy>(src: *const T) -> MaybeUninit<T> {
+    let mut data = MaybeUninit::<T>::uninit();
+    let src = src as *const u8;
+    let dst = data.as_mut_ptr() as *mut u8;
+    for i in 0..core::mem::size_of::<T>() {
+        ptr::write(dst.add(i), ptr::read_volatile(src.add(i)));
+    }
+    data
+}
+
+impl<T: Copy + Default> Default for SeqLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for SeqLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SeqLock")
+            .field("data", &self.read())
+            .finish()
+    }
+}
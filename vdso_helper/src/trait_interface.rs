@@ -8,7 +8,7 @@
 ///
 /// vDSO中的函数实现则可以将指向泛型的指针转化为`$nameVirtImpl`的引用后，通过虚拟实现结构体调用这些trait中的函数，而不需要直接操作函数指针。
 ///
-/// 这样声明的接口应该放在`interface.rs`中，这样`build_vdso`就会为`init_vtable_$name`函数生成调用vDSO内函数的接口，就像其为`api.rs`中的函数生成接口一样。
+/// 这样声明的接口可以放在vDSO库的任意模块中，`build_vdso`会扫描到它，并为`init_vtable_$name`函数生成调用vDSO内函数的接口，就像其为`#[vdso_api]`函数生成接口一样。
 #[macro_export]
 macro_rules! trait_interface {
     ($(#[doc = $trait_doc:literal])* pub trait $name:ident { $($(#[doc = $fn_doc:literal])* fn $fn_name:ident $args:tt $(-> $ret:ty)?;)+ }) => {
@@ -6,6 +6,9 @@
 //! vDSO库通过[`get_vvar_data!`](`crate::get_vvar_data!`)访问共享数据。
 //! 所有进程和内核访问同一份共享数据，在一个地址空间中对共享数据的修改会被其它地址空间看到。
 //!
+//! 共享数据中标记为`#[user_readonly]`的字段位于单独的页中，只有内核可以写入，用户进程只能读取，
+//! 适用于由内核维护、用户进程读取的状态（类似于Linux vvar中的时间页）。
+//!
 //! 私有数据则存放在vDSO库自身的数据段中，通过一般的静态变量声明方式定义，
 //! 仅供vDSO库内部使用。
 //! 不同地址空间使用vDSO库时，私有数据写时复制，互不影响。
@@ -14,6 +17,92 @@
 //!
 //! - [`vvar_data!`](`crate::vvar_data!`)
 //! - [`get_vvar_data!`](`crate::get_vvar_data!`)
+//! - [`PAGE_SIZE`]
+//! - [`VvarZeroed`]
+
+use core::marker::PhantomData;
+
+/// 映射代码和数据段过程中的页面大小。
+///
+/// 由`build_vdso`根据`BuildConfig::page_size`，通过环境变量`VDSO_PAGE_SIZE`在编译期传入；未指定时为4K。
+pub const PAGE_SIZE: usize = match option_env!("VDSO_PAGE_SIZE") {
+    Some(page_size) => parse_page_size(page_size),
+    None => 0x1000,
+};
+
+const _: () = assert!(
+    PAGE_SIZE.is_power_of_two(),
+    "VDSO_PAGE_SIZE must be a power of two"
+);
+
+/// 在编译期解析十进制或以`0x`开头的十六进制页面大小。
+const fn parse_page_size(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let (radix, mut i) =
+        if bytes.len() > 2 && bytes[0] == b'0' && (bytes[1] == b'x' || bytes[1] == b'X') {
+            (16, 2)
+        } else {
+            (10, 0)
+        };
+    let mut value = 0;
+    while i < bytes.len() {
+        let digit = match bytes[i] {
+            b @ b'0'..=b'9' => (b - b'0') as usize,
+            b @ b'a'..=b'f' if radix == 16 => (b - b'a') as usize + 10,
+            b @ b'A'..=b'F' if radix == 16 => (b - b'A') as usize + 10,
+            _ => panic!("VDSO_PAGE_SIZE is not a valid number"),
+        };
+        value = value * radix + digit;
+        i += 1;
+    }
+    value
+}
+
+/// 全为0的字节即为初始状态的类型。
+///
+/// 内核初始化vVAR时先将其所在的页清零，再原地初始化各项：实现了该trait的项保持全0，其它项写入`Default::default()`。
+/// 因此较大的对象（例如[`VvarArena`](crate::VvarArena)）不会在栈上构造。
+///
+/// # Safety
+///
+/// 全为0的字节需要是该类型的合法值，且与`Default::default()`的结果等价。
+pub unsafe trait VvarZeroed {}
+
+/// 原地初始化vVAR中类型为`T`的一项，供[`vvar_data!`](`crate::vvar_data!`)使用。
+///
+/// 通过方法解析的优先级选择初始化方式：`T`实现了[`VvarZeroed`]时匹配[`InitZeroed`]，否则自动借用后匹配[`InitDefault`]。
+#[doc(hidden)]
+pub struct FieldInit<T>(pub PhantomData<T>);
+
+/// 见[`FieldInit`]。
+#[doc(hidden)]
+pub trait InitZeroed<T> {
+    /// 项所在的内存已经清零，不需要写入。
+    ///
+    /// # Safety
+    ///
+    /// `field`指向的内存需已清零。
+    #[inline(always)]
+    unsafe fn init_field(&self, _field: *mut T) {}
+}
+
+impl<T: VvarZeroed> InitZeroed<T> for FieldInit<T> {}
+
+/// 见[`FieldInit`]。
+#[doc(hidden)]
+pub trait InitDefault<T: Default> {
+    /// 写入`Default::default()`。
+    ///
+    /// # Safety
+    ///
+    /// `field`需要可写且已对齐。
+    #[inline(always)]
+    unsafe fn init_field(&self, field: *mut T) {
+        unsafe { field.write(T::default()) };
+    }
+}
+
+impl<T: Default> InitDefault<T> for &FieldInit<T> {}
 
 /// 声明共享数据结构。
 ///
@@ -21,11 +110,27 @@
 ///
 /// - 类似于struct的定义，在大括号中声明每一项的名称和类型。
 ///     - 每一项的类型都需要实现`Default`和`Sync` trait。
+///     - 以`#[user_readonly]`标记的项只允许内核写入，用户进程只能读取。
 /// - 需要在crate的根模块下使用该宏。
 /// - 在项目各处通过[`get_vvar_data!`](`crate::get_vvar_data!`)获取共享数据结构的引用。
+///
+/// 该宏生成两个结构体：未标记的项组成`VvarData`，标记为`#[user_readonly]`的项组成`VvarKernelData`。
+/// 内核通过它们的`init_in_place`在vVAR中原地初始化各项，见[`VvarZeroed`]。
+/// vVAR中，`VvarKernelData`位于`VvarData`之前的单独的页中，内核映射为可读可写，用户进程中映射为只读。
+/// 两种项都可以通过[`get_vvar_data!`](`crate::get_vvar_data!`)获取，它根据当前的映射基址定位各项所在的页。
+///
+/// ```ignore
+/// vvar_data! {
+///     /// 用户进程可以修改的数据
+///     counter: AtomicUsize,
+///     /// 由内核更新的时间，用户进程只读
+///     #[user_readonly]
+///     ticks: AtomicU64,
+/// }
+/// ```
 #[macro_export]
 macro_rules! vvar_data {
-    ($($(#[doc = $doc:literal])* $i:ident: $t:ty),* $(,)?) => {
+    (@fields [$($(#[doc = $doc:literal])* $i:ident: $t:ty,)*] [$($(#[doc = $kdoc:literal])* $ki:ident: $kt:ty,)*]) => {
         #[allow(missing_docs)]
         #[derive(Default)]
         #[repr(C)]
@@ -36,8 +141,103 @@ macro_rules! vvar_data {
             ),*
         }
 
+        /// 用户进程只读的共享数据，由`vvar_data!`中标记为`#[user_readonly]`的项组成，位于`VvarData`之前的单独的页中。
+        #[allow(missing_docs)]
+        #[derive(Default)]
+        #[repr(C)]
+        pub struct VvarKernelData {
+            $(
+                $(#[doc = $kdoc])*
+                pub $ki: $kt
+            ),*
+        }
+
+        /// 按名称获取vVAR中的各项，供[`get_vvar_data!`](`crate::get_vvar_data!`)使用。
+        ///
+        /// 各项的地址由[`get_code_base`](`crate::vvar_data::get_code_base`)计算，参数`page_size`为映射时的页面大小。
+        struct VvarFields;
+
+        #[allow(dead_code)]
+        impl VvarFields {
+            $(
+                #[inline(always)]
+                fn $i(page_size: usize) -> &'static $t {
+                    &Self::__data(page_size).$i
+                }
+            )*
+            $(
+                #[inline(always)]
+                fn $ki(page_size: usize) -> &'static $kt {
+                    &Self::__kernel_data(page_size).$ki
+                }
+            )*
+
+            #[inline(always)]
+            fn __data(page_size: usize) -> &'static VvarData {
+                let vvar_size = (core::mem::size_of::<VvarData>() + (page_size - 1)) & !(page_size - 1);
+                let data_base = $crate::vvar_data::get_code_base() - vvar_size;
+                unsafe { &*(data_base as *const VvarData) }
+            }
+
+            #[inline(always)]
+            fn __kernel_data(page_size: usize) -> &'static VvarKernelData {
+                let kernel_size =
+                    (core::mem::size_of::<VvarKernelData>() + (page_size - 1)) & !(page_size - 1);
+                let kernel_base = Self::__data(page_size) as *const VvarData as usize - kernel_size;
+                unsafe { &*(kernel_base as *const VvarKernelData) }
+            }
+        }
+
+        impl VvarData {
+            /// 在已清零的内存中原地初始化各项，见[`VvarZeroed`](`crate::vvar_data::VvarZeroed`)。
+            ///
+            /// # Safety
+            ///
+            /// `this`需要可写、已对齐，且指向的内存已清零。
+            #[doc(hidden)]
+            #[allow(unused_imports, unused_variables)]
+            pub unsafe fn init_in_place(this: *mut Self) {
+                use $crate::vvar_data::{InitDefault as _, InitZeroed as _};
+                $(
+                    unsafe {
+                        (&$crate::vvar_data::FieldInit::<$t>(core::marker::PhantomData))
+                            .init_field(core::ptr::addr_of_mut!((*this).$i))
+                    };
+                )*
+            }
+        }
+
+        impl VvarKernelData {
+            /// 在已清零的内存中原地初始化各项，见[`VvarZeroed`](`crate::vvar_data::VvarZeroed`)。
+            ///
+            /// # Safety
+            ///
+            /// `this`需要可写、已对齐，且指向的内存已清零。
+            #[doc(hidden)]
+            #[allow(unused_imports, unused_variables)]
+            pub unsafe fn init_in_place(this: *mut Self) {
+                use $crate::vvar_data::{InitDefault as _, InitZeroed as _};
+                $(
+                    unsafe {
+                        (&$crate::vvar_data::FieldInit::<$kt>(core::marker::PhantomData))
+                            .init_field(core::ptr::addr_of_mut!((*this).$ki))
+                    };
+                )*
+            }
+        }
+
         trait VvarDataRequirements: Default + Sync {}
         impl VvarDataRequirements for VvarData {}
+        impl VvarDataRequirements for VvarKernelData {}
+    };
+    (@fields [$($fields:tt)*] [$($kernel:tt)*] $(#[doc = $doc:literal])* #[user_readonly] $(#[doc = $doc2:literal])* $i:ident: $t:ty $(, $($rest:tt)*)?) => {
+        $crate::vvar_data!(@fields [$($fields)*] [$($kernel)* $(#[doc = $doc])* $(#[doc = $doc2])* $i: $t,] $($($rest)*)?);
+    };
+    (@fields [$($fields:tt)*] [$($kernel:tt)*] $(#[doc = $doc:literal])* $i:ident: $t:ty $(, $($rest:tt)*)?) => {
+        $crate::vvar_data!(@fields [$($fields)* $(#[doc = $doc])* $i: $t,] [$($kernel)*] $($($rest)*)?);
+    };
+    ($($body:tt)*) => {
+        $crate::vvar_data!(@fields [] [] $($body)*);
     };
 }
 
@@ -45,45 +245,58 @@ macro_rules! vvar_data {
 ///
 /// 参数：
 ///
-/// - $1: 共享数据结构的字段名（在[`vvar_data!`](`crate::vvar_data!`)中定义）。
-/// - $2: 映射代码和数据段过程中的页面大小（`usize`类型）（可不填写，则默认为4K）。
+/// - $1: 共享数据结构的字段名（在[`vvar_data!`](`crate::vvar_data!`)中定义）。标记为`#[user_readonly]`的字段同样可以获取，但在用户进程中不能写入。
+/// - $2: 映射代码和数据段过程中的页面大小（`usize`类型）（可不填写，则为构建时`BuildConfig::page_size`指定的[`PAGE_SIZE`]）。
 ///
 /// 返回值：`&'static T`类型，代表对相应结构的引用
 ///
 #[macro_export]
 macro_rules! get_vvar_data {
-    ($i:ident, $e:expr) => {{
-        let vvar_size = (core::mem::size_of::<crate::VvarData>() + ($e - 1)) & !($e - 1);
-        let data_base = $crate::vvar_data::get_code_base($e) - vvar_size;
-        let vvar_data_ref = unsafe { &*(data_base as *const crate::VvarData) };
-        &(vvar_data_ref.$i)
-    }};
-    ($i:ident) => {{
-        let vvar_size = (core::mem::size_of::<crate::VvarData>() + (0x1000 - 1)) & !(0x1000 - 1);
-        let data_base = $crate::vvar_data::get_code_base(0x1000) - vvar_size;
-        let vvar_data_ref = unsafe { &*(data_base as *const crate::VvarData) };
-        &(vvar_data_ref.$i)
-    }};
+    ($i:ident, $e:expr) => {
+        crate::VvarFields::$i($e)
+    };
+    ($i:ident) => {
+        $crate::get_vvar_data!($i, $crate::vvar_data::PAGE_SIZE)
+    };
 }
 
-/// 此处的pub仅用于在动态符号表中得到该函数的地址以便检查
+/// 获取vDSO镜像在当前地址空间中的首地址。
 ///
-/// 该函数不应被用户直接调用
-/// 
-/// [`get_code_base`] 会从当前代码所在页开始，向前扫描 ELF 头，
-/// 从而定位 vDSO 的真实映射基址。
-#[inline(never)]
-#[no_mangle]
-// #[link_section = ".text.start"]
-pub fn get_code_base(page_size: usize) -> usize {
-    let pc = unsafe { hal::asm::get_pc() };
-    // pc & !(page_size - 1)
-    let mut elf_base = pc & !(page_size - 1);
-    loop {
-        let magic = unsafe { core::ptr::read_unaligned(elf_base as *const [u8; 4]) };
-        if magic == [0x7f, b'E', b'L', b'F'] {
-            return elf_base;
-        }
-        elf_base -= page_size;
+/// 此处的pub仅用于在[`get_vvar_data!`](`crate::get_vvar_data!`)中调用，该函数不应被用户直接调用。
+///
+/// `build_vdso`生成的链接脚本在镜像的首地址（ELF头所在的位置）定义了隐藏符号`__vdso_start`，
+/// [`get_code_base`]通过PC相对寻址读取该符号的地址，因此无论镜像被映射到何处，都能在常数时间内得到真实的映射基址。
+#[inline(always)]
+pub fn get_code_base() -> usize {
+    let base: usize;
+    unsafe {
+        #[cfg(target_arch = "x86_64")]
+        core::arch::asm!(
+            "lea {}, [rip + __vdso_start]",
+            out(reg) base,
+            options(pure, nomem, nostack)
+        );
+        #[cfg(target_arch = "riscv64")]
+        core::arch::asm!(
+            "lla {}, __vdso_start",
+            out(reg) base,
+            options(pure, nomem, nostack)
+        );
+        #[cfg(target_arch = "aarch64")]
+        core::arch::asm!(
+            "adrp {0}, __vdso_start",
+            "add {0}, {0}, :lo12:__vdso_start",
+            out(reg) base,
+            options(pure, nomem, nostack)
+        );
     }
+    base
 }
+
+// 单元测试在宿主机上运行，没有链接脚本定义的`__vdso_start`，以测试程序中的一个符号代替镜像首地址
+#[cfg(test)]
+core::arch::global_asm!(
+    ".globl __vdso_start",
+    ".hidden __vdso_start",
+    "__vdso_start:"
+);
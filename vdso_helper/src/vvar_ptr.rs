@@ -0,0 +1,481 @@
+//! 可以存放在vVAR中的位置无关指针，以及基于它的链表和树的构件。
+//!
+//! vVAR在每个地址空间中被映射到不同的虚拟地址，因此在[`vvar_data!`](`crate::vvar_data!`)中保存裸指针是错误的：
+//! 一个地址空间写入的指针在另一个地址空间中指向无关的内存。
+//! [`VvarPtr`]保存的是目标相对于vDSO镜像首地址（[`get_code_base`]）的偏移。
+//! vVAR与镜像的相对位置在所有地址空间中都相同，因此任意地址空间都能将偏移还原为正确的地址。
+//!
+//! 在此基础上，[`VvarList`]提供侵入式双向链表，[`VvarTreeLink`]提供构建树所需的父、左、右指针，
+//! 可用于在vVAR中构建共享的运行队列、IPC队列等链式结构。
+//!
+//! 这些类型只能在vDSO库中使用（通过[`get_code_base`]定位），目标只应位于vVAR中。
+//! 指向私有数据的指针在每个地址空间中会还原为该地址空间自己的副本；指向栈和堆的指针则无法还原。
+//!
+//! ```ignore
+//! #[derive(Default)]
+//! pub struct Task {
+//!     link: VvarLink<Task>,
+//!     // ...
+//! }
+//!
+//! unsafe impl VvarLinked for Task {
+//!     fn link(&self) -> &VvarLink<Self> {
+//!         &self.link
+//!     }
+//! }
+//!
+//! vvar_data! {
+//!     tasks: [Task; 16],
+//!     run_queue: SpinLock<VvarList<Task>>,
+//! }
+//!
+//! let tasks = get_vvar_data!(tasks);
+//! unsafe { get_vvar_data!(run_queue).lock().push_back(&tasks[0]) };
+//! ```
+
+use core::{
+    fmt,
+    marker::PhantomData,
+    ptr,
+    sync::atomic::{AtomicIsize, Ordering},
+};
+
+use crate::vvar_data::get_code_base;
+
+/// 保存相对于vDSO镜像首地址的偏移的指针。
+///
+/// 偏移为0时表示空指针（镜像首地址处是ELF头，不会是vVAR中的数据）。
+/// 构造和比较`VvarPtr`是安全的，但解引用需要保证目标仍然有效。
+#[repr(transparent)]
+pub struct VvarPtr<T> {
+    offset: isize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> VvarPtr<T> {
+    /// 空指针。
+    pub const fn null() -> Self {
+        Self::from_offset(0)
+    }
+
+    /// 由相对于镜像首地址的偏移构造指针。
+    pub const fn from_offset(offset: isize) -> Self {
+        Self {
+            offset,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 由当前地址空间中的指针构造，空指针对应空的`VvarPtr`。
+    pub fn new(ptr: *const T) -> Self {
+        if ptr.is_null() {
+            Self::null()
+        } else {
+            Self::from_offset((ptr as usize).wrapping_sub(get_code_base()) as isize)
+        }
+    }
+
+    /// 由当前地址空间中的引用构造。
+    pub fn from_ref(r: &T) -> Self {
+        Self::new(r)
+    }
+
+    /// 相对于镜像首地址的偏移。
+    pub const fn offset(self) -> isize {
+        self.offset
+    }
+
+    /// 是否为空指针。
+    pub const fn is_null(self) -> bool {
+        self.offset == 0
+    }
+
+    /// 还原为当前地址空间中的指针。
+    pub fn as_ptr(self) -> *mut T {
+        if self.is_null() {
+            ptr::null_mut()
+        } else {
+            get_code_base().wrapping_add(self.offset as usize) as *mut T
+        }
+    }
+
+    /// 还原为当前地址空间中的引用，空指针返回`None`。
+    ///
+    /// # Safety
+    ///
+    /// 目标需要在生命周期`'a`内有效，且没有被可变地借用。
+    pub unsafe fn as_ref<'a>(self) -> Option<&'a T> {
+        unsafe { self.as_ptr().as_ref() }
+    }
+
+    /// 还原为当前地址空间中的可变引用，空指针返回`None`。
+    ///
+    /// # Safety
+    ///
+    /// 目标需要在生命周期`'a`内有效，且没有被其它引用借用。
+    pub unsafe fn as_mut<'a>(self) -> Option<&'a mut T> {
+        unsafe { self.as_ptr().as_mut() }
+    }
+}
+
+impl<T> Clone for VvarPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for VvarPtr<T> {}
+
+impl<T> PartialEq for VvarPtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.offset == other.offset
+    }
+}
+
+impl<T> Eq for VvarPtr<T> {}
+
+impl<T> Default for VvarPtr<T> {
+    fn default() -> Self {
+        Self::null()
+    }
+}
+
+impl<T> fmt::Debug for VvarPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "VvarPtr({:#x})", self.offset)
+    }
+}
+
+/// 可以原子地读写的[`VvarPtr`]。
+#[repr(transparent)]
+pub struct AtomicVvarPtr<T> {
+    offset: AtomicIsize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> AtomicVvarPtr<T> {
+    /// 以`ptr`为初始值创建。
+    pub const fn new(ptr: VvarPtr<T>) -> Self {
+        Self {
+            offset: AtomicIsize::new(ptr.offset),
+            _marker: PhantomData,
+        }
+    }
+
+    /// 空指针。
+    pub const fn null() -> Self {
+        Self::new(VvarPtr::null())
+    }
+
+    /// 读取指针。
+    pub fn load(&self, order: Ordering) -> VvarPtr<T> {
+        VvarPtr::from_offset(self.offset.load(order))
+    }
+
+    /// 写入指针。
+    pub fn store(&self, ptr: VvarPtr<T>, order: Ordering) {
+        self.offset.store(ptr.offset, order);
+    }
+
+    /// 写入指针，返回原来的值。
+    pub fn swap(&self, ptr: VvarPtr<T>, order: Ordering) -> VvarPtr<T> {
+        VvarPtr::from_offset(self.offset.swap(ptr.offset, order))
+    }
+
+    /// 若当前值为`current`，则写入`new`。成功时返回`Ok(current)`，失败时返回`Err(当前值)`。
+    pub fn compare_exchange(
+        &self,
+        current: VvarPtr<T>,
+        new: VvarPtr<T>,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<VvarPtr<T>, VvarPtr<T>> {
+        self.offset
+            .compare_exchange(current.offset, new.offset, success, failure)
+            .map(VvarPtr::from_offset)
+            .map_err(VvarPtr::from_offset)
+    }
+}
+
+impl<T> Default for AtomicVvarPtr<T> {
+    fn default() -> Self {
+        Self::null()
+    }
+}
+
+impl<T> fmt::Debug for AtomicVvarPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.load(Ordering::Relaxed).fmt(f)
+    }
+}
+
+/// 侵入式双向链表的链接，嵌入在链表节点中。
+///
+/// 链接使用原子变量保存，因此节点可以直接放在vVAR中；但对链表的修改仍需由外部（例如[`crate::SpinLock`]）互斥。
+pub struct VvarLink<T> {
+    prev: AtomicVvarPtr<T>,
+    next: AtomicVvarPtr<T>,
+}
+
+impl<T> VvarLink<T> {
+    /// 创建未加入链表的链接。
+    pub const fn new() -> Self {
+        Self {
+            prev: AtomicVvarPtr::null(),
+            next: AtomicVvarPtr::null(),
+        }
+    }
+
+    /// 前一个节点。
+    pub fn prev(&self) -> VvarPtr<T> {
+        self.prev.load(Ordering::Relaxed)
+    }
+
+    /// 后一个节点。
+    pub fn next(&self) -> VvarPtr<T> {
+        self.next.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Default for VvarLink<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for VvarLink<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VvarLink")
+            .field("prev", &self.prev())
+            .field("next", &self.next())
+            .finish()
+    }
+}
+
+/// 可以加入[`VvarList`]的节点。
+///
+/// # Safety
+///
+/// `link`每次调用都需要返回节点中同一个[`VvarLink`]，且该链接只能被一个链表使用。
+pub unsafe trait VvarLinked: Sized {
+    /// 节点中的链接。
+    fn link(&self) -> &VvarLink<Self>;
+}
+
+/// 侵入式双向链表。
+///
+/// 链表不拥有节点，节点需要位于vVAR中并在加入链表期间保持有效。
+/// 修改链表的方法需要由外部互斥，通常将链表放在[`crate::SpinLock`]等锁中。
+pub struct VvarList<T: VvarLinked> {
+    head: AtomicVvarPtr<T>,
+    tail: AtomicVvarPtr<T>,
+}
+
+impl<T: VvarLinked> VvarList<T> {
+    /// 创建空链表。
+    pub const fn new() -> Self {
+        Self {
+            head: AtomicVvarPtr::null(),
+            tail: AtomicVvarPtr::null(),
+        }
+    }
+
+    /// 链表是否为空。
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Relaxed).is_null()
+    }
+
+    /// 第一个节点。
+    pub fn front(&self) -> VvarPtr<T> {
+        self.head.load(Ordering::Relaxed)
+    }
+
+    /// 最后一个节点。
+    pub fn back(&self) -> VvarPtr<T> {
+        self.tail.load(Ordering::Relaxed)
+    }
+
+    /// 将`node`加入链表尾部。
+    ///
+    /// # Safety
+    ///
+    /// `node`需要位于vVAR中，在加入链表期间保持有效，且当前不在任何链表中；调用者需要保证对链表的修改互斥。
+    pub unsafe fn push_back(&self, node: &T) {
+        let ptr = VvarPtr::from_ref(node);
+        let tail = self.back();
+        node.link().prev.store(tail, Ordering::Relaxed);
+        node.link().next.store(VvarPtr::null(), Ordering::Relaxed);
+        match unsafe { tail.as_ref() } {
+            Some(tail) => tail.link().next.store(ptr, Ordering::Relaxed),
+            None => self.head.store(ptr, Ordering::Relaxed),
+        }
+        self.tail.store(ptr, Ordering::Relaxed);
+    }
+
+    /// 将`node`加入链表头部。
+    ///
+    /// # Safety
+    ///
+    /// 同[`VvarList::push_back`]。
+    pub unsafe fn push_front(&self, node: &T) {
+        let ptr = VvarPtr::from_ref(node);
+        let head = self.front();
+        node.link().prev.store(VvarPtr::null(), Ordering::Relaxed);
+        node.link().next.store(head, Ordering::Relaxed);
+        match unsafe { head.as_ref() } {
+            Some(head) => head.link().prev.store(ptr, Ordering::Relaxed),
+            None => self.tail.store(ptr, Ordering::Relaxed),
+        }
+        self.head.store(ptr, Ordering::Relaxed);
+    }
+
+    /// 移除并返回第一个节点。
+    ///
+    /// # Safety
+    ///
+    /// 链表中的节点在生命周期`'a`内有效；调用者需要保证对链表的修改互斥。
+    pub unsafe fn pop_front<'a>(&self) -> Option<&'a T> {
+        let node = unsafe { self.front().as_ref() }?;
+        unsafe { self.remove(node) };
+        Some(node)
+    }
+
+    /// 移除并返回最后一个节点。
+    ///
+    /// # Safety
+    ///
+    /// 同[`VvarList::pop_front`]。
+    pub unsafe fn pop_back<'a>(&self) -> Option<&'a T> {
+        let node = unsafe { self.back().as_ref() }?;
+        unsafe { self.remove(node) };
+        Some(node)
+    }
+
+    /// 将`node`从链表中移除。
+    ///
+    /// # Safety
+    ///
+    /// `node`需要在该链表中；调用者需要保证对链表的修改互斥。
+    pub unsafe fn remove(&self, node: &T) {
+        let (prev, next) = (node.link().prev(), node.link().next());
+        match unsafe { prev.as_ref() } {
+            Some(prev) => prev.link().next.store(next, Ordering::Relaxed),
+            None => self.head.store(next, Ordering::Relaxed),
+        }
+        match unsafe { next.as_ref() } {
+            Some(next) => next.link().prev.store(prev, Ordering::Relaxed),
+            None => self.tail.store(prev, Ordering::Relaxed),
+        }
+        node.link().prev.store(VvarPtr::null(), Ordering::Relaxed);
+        node.link().next.store(VvarPtr::null(), Ordering::Relaxed);
+    }
+
+    /// 从头到尾遍历链表。
+    ///
+    /// # Safety
+    ///
+    /// 遍历期间链表中的节点需要保持有效，且链表不被修改。
+    pub unsafe fn iter(&self) -> VvarListIter<'_, T> {
+        VvarListIter {
+            next: self.front(),
+            _list: PhantomData,
+        }
+    }
+}
+
+impl<T: VvarLinked> Default for VvarList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: VvarLinked> fmt::Debug for VvarList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VvarList")
+            .field("head", &self.front())
+            .field("tail", &self.back())
+            .finish()
+    }
+}
+
+/// [`VvarList`]的迭代器，由[`VvarList::iter`]创建。
+pub struct VvarListIter<'a, T: VvarLinked> {
+    next: VvarPtr<T>,
+    _list: PhantomData<&'a VvarList<T>>,
+}
+
+impl<'a, T: VvarLinked> Iterator for VvarListIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = unsafe { self.next.as_ref() }?;
+        self.next = node.link().next();
+        Some(node)
+    }
+}
+
+/// 树节点的链接，保存父节点和左右子节点，嵌入在树节点中，用于在vVAR中构建二叉搜索树、堆等结构。
+///
+/// 与[`VvarLink`]相同，链接使用原子变量保存，对树的修改需要由外部互斥。
+pub struct VvarTreeLink<T> {
+    parent: AtomicVvarPtr<T>,
+    left: AtomicVvarPtr<T>,
+    right: AtomicVvarPtr<T>,
+}
+
+impl<T> VvarTreeLink<T> {
+    /// 创建不在树中的链接。
+    pub const fn new() -> Self {
+        Self {
+            parent: AtomicVvarPtr::null(),
+            left: AtomicVvarPtr::null(),
+            right: AtomicVvarPtr::null(),
+        }
+    }
+
+    /// 父节点。
+    pub fn parent(&self) -> VvarPtr<T> {
+        self.parent.load(Ordering::Relaxed)
+    }
+
+    /// 左子节点。
+    pub fn left(&self) -> VvarPtr<T> {
+        self.left.load(Ordering::Relaxed)
+    }
+
+    /// 右子节点。
+    pub fn right(&self) -> VvarPtr<T> {
+        self.right.load(Ordering::Relaxed)
+    }
+
+    /// 设置父节点。
+    pub fn set_parent(&self, parent: VvarPtr<T>) {
+        self.parent.store(parent, Ordering::Relaxed);
+    }
+
+    /// 设置左子节点。
+    pub fn set_left(&self, left: VvarPtr<T>) {
+        self.left.store(left, Ordering::Relaxed);
+    }
+
+    /// 设置右子节点。
+    pub fn set_right(&self, right: VvarPtr<T>) {
+        self.right.store(right, Ordering::Relaxed);
+    }
+}
+
+impl<T> Default for VvarTreeLink<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for VvarTreeLink<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VvarTreeLink")
+            .field("parent", &self.parent())
+            .field("left", &self.left())
+            .field("right", &self.right())
+            .finish()
+    }
+}
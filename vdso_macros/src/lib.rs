@@ -0,0 +1,766 @@
+//! vDSO库使用的过程宏。
+//!
+//! 该库不应被直接依赖，而是通过`vdso_helper`中的重新导出使用。
+//!
+//! - [`macro@vdso_api`]：将函数标记为vDSO的API。
+//! - [`macro@vdso_async`]：将`async fn`导出为vDSO的异步API。
+//! - [`macro@vdso_impl`]：将`impl`块中的方法导出为vDSO的API。
+
+#![deny(missing_docs)]
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{
+    parse::Parser, parse_macro_input, parse_quote, punctuated::Punctuated, spanned::Spanned, Error,
+    Expr, ExprLit, FnArg, ImplItem, ItemFn, ItemImpl, Lit, LitStr, MetaNameValue, Pat, ReturnType,
+    Token,
+};
+
+/// 将函数标记为vDSO的API。
+///
+/// 被标记的函数可以位于vDSO库的任意模块中，`build_vdso`会扫描整个模块树，
+/// 将其加入版本脚本的导出符号，并在生成的API库中为其生成调用接口。
+///
+/// 该宏会为函数加上`#[unsafe(no_mangle)]`，并将其调用约定设置为`extern "C"`，因此以下两种写法等价：
+///
+/// ```ignore
+/// #[vdso_api]
+/// pub fn get_shared() -> ArgumentExample { ... }
+///
+/// #[unsafe(no_mangle)]
+/// pub extern "C" fn get_shared() -> ArgumentExample { ... }
+/// ```
+///
+/// 与手动声明的API相同，函数的参数和返回值中用到的自定义数据结构需要声明为`pub`和`#[repr(C)]`，
+/// 且需要导出在vDSO库的根模块中。
+///
+/// # 符号版本
+///
+/// 未指定版本的API属于版本节点`VDSO_1.0`。可以通过以下参数指定API所属的版本：
+///
+/// - `#[vdso_api(since = "1.1")]`：该API从ABI版本`1.1`开始提供，导出为`函数名@@VDSO_1.1`。
+/// - `#[vdso_api(compat = "1.0", symbol = "foo")]`：保留旧版本的入口，导出为`foo@VDSO_1.0`，
+///   供依赖旧版本API库的程序使用。新的API库不会为其生成调用接口。
+///   此时`foo`的当前定义需要通过`since`指定一个更新的版本。
+///
+/// ```ignore
+/// #[vdso_api(compat = "1.0", symbol = "get_count")]
+/// pub fn get_count_v1_0() -> u32 { ... }
+///
+/// #[vdso_api(since = "1.1")]
+/// pub fn get_count() -> u64 { ... }
+/// ```
+///
+/// # 模块
+///
+/// 通过`#[vdso_api(module = "name")]`可以将API放入名为`name`的模块中（可与版本参数一同使用）。
+/// 每个模块链接为单独的so文件，与主模块共享同一份vVAR，由API库在首次调用该模块的API时按需加载。
+/// 模块名需为合法的Rust标识符。未指定模块的API属于主模块，随`map_so`一同映射。
+///
+/// ```ignore
+/// #[vdso_api(module = "net")]
+/// pub fn net_poll() -> usize { ... }
+/// ```
+///
+/// # 泛型API
+///
+/// 泛型函数无法直接导出，但可以通过`#[vdso_api(instances = "...")]`列出需要实例化的类型（可与版本和模块参数一同使用）。
+/// 该宏保留原函数，并为每个实例生成一个导出的`extern "C"`函数，符号名为`<函数名>__<类型实参>`，
+/// 其中类型实参中除字母、数字外的字符替换为`_`，多个类型实参之间以`__`分隔。
+/// 生成的API库中只有一个泛型函数，根据类型实参调用对应的符号。
+///
+/// 只有一个类型参数时，每个实例为一个类型；有多个类型参数时，每个实例为一个元组，依次给出各个类型参数。
+/// 不支持生命周期参数和const泛型参数。
+///
+/// ```ignore
+/// #[vdso_api(instances = "u32, u64, TaskRef")]
+/// pub fn push_value<T: Copy>(value: T) -> usize { ... }
+///
+/// #[vdso_api(instances = "(u8, u16), (u16, u8)")]
+/// pub fn sum_pair<T: Into<usize>, U: Into<usize>>(a: T, b: U) -> usize { ... }
+/// ```
+#[proc_macro_attribute]
+pub fn vdso_api(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = match Punctuated::<MetaNameValue, Token![,]>::parse_terminated.parse(attr) {
+        Ok(args) => args,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let mut func = parse_macro_input!(item as ItemFn);
+
+    if let Some(instances) = args.iter().find(|arg| arg.path.is_ident("instances")) {
+        return match expand_instances(&args, instances, &func) {
+            Ok(tokens) => tokens.into(),
+            Err(e) => e.to_compile_error().into(),
+        };
+    }
+
+    match &func.sig.abi {
+        None => func.sig.abi = Some(parse_quote!(extern "C")),
+        Some(abi) => {
+            let is_c = abi.name.as_ref().is_none_or(|name| name.value() == "C");
+            if !is_c {
+                return Error::new(
+                    abi.span(),
+                    format!(
+                        "vDSO API `{}` must use the \"C\" calling convention",
+                        func.sig.ident
+                    ),
+                )
+                .to_compile_error()
+                .into();
+            }
+        }
+    }
+
+    if let Err(e) = check_module_arg(&args) {
+        return e.to_compile_error().into();
+    }
+
+    let version = match parse_version_args(&args, &func) {
+        Ok(version) => version,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    match version {
+        None => quote! {
+            #[unsafe(no_mangle)]
+            #func
+        },
+        Some(VersionArgs {
+            symbol,
+            version,
+            is_default,
+        }) => {
+            // 以内部符号名导出函数，再通过`.symver`为其指定带版本的符号名
+            let internal = format!("__vdso_{}_{}", symbol, version.replace('.', "_"));
+            let separator = if is_default { "@@" } else { "@" };
+            let symver = LitStr::new(
+                &format!(
+                    ".symver {}, {}{}VDSO_{}",
+                    internal, symbol, separator, version
+                ),
+                Span::call_site(),
+            );
+            let internal = LitStr::new(&internal, Span::call_site());
+            quote! {
+                #[unsafe(export_name = #internal)]
+                #func
+
+                core::arch::global_asm!(#symver);
+            }
+        }
+    }
+    .into()
+}
+
+/// 将`async fn`导出为vDSO的异步API。
+///
+/// `async fn`实际上是返回`impl Future`的泛型函数的语法糖，无法直接通过C ABI导出。
+/// 该宏保留原函数，并为其生成以下几个API（均以`#[vdso_api]`导出，参数与`#[vdso_api]`相同，但不支持`compat`和`symbol`）：
+///
+/// - `<函数名>_state_size`和`<函数名>_state_align`：Future的大小和对齐；
+/// - `<函数名>_init_state(state, 参数...)`：调用原函数，将返回的Future写入调用者提供的内存`state`中；
+/// - `<函数名>_poll(state, waker_data, waker_vtable, output)`：以调用者的`Waker`推进Future，
+///   完成时将结果写入`output`、析构Future并返回`true`，否则返回`false`；
+/// - `<函数名>_drop_state(state)`：析构尚未完成的Future。
+///
+/// 生成的API库会将它们重新包装为实现了`Future`的类型，调用者可以像调用原函数一样`.await`。
+///
+/// Future保存在调用者的内存中，在完成前会跨越多次调用，因此参数不能包含引用，函数也不能有泛型参数。
+///
+/// ```ignore
+/// #[vdso_async(since = "1.1")]
+/// pub async fn wait_event(id: usize) -> usize { ... }
+/// ```
+#[proc_macro_attribute]
+pub fn vdso_async(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let attr = proc_macro2::TokenStream::from(attr);
+    let args = match Punctuated::<MetaNameValue, Token![,]>::parse_terminated.parse2(attr.clone()) {
+        Ok(args) => args,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    if let Some(arg) = args
+        .iter()
+        .find(|arg| arg.path.is_ident("compat") || arg.path.is_ident("symbol"))
+    {
+        return Error::new(
+            arg.path.span(),
+            "`#[vdso_async]` does not support `compat` or `symbol`",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let func = parse_macro_input!(item as ItemFn);
+    match expand_vdso_async(&attr, &func) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_vdso_async(
+    attr: &proc_macro2::TokenStream,
+    func: &ItemFn,
+) -> Result<proc_macro2::TokenStream, Error> {
+    let sig = &func.sig;
+    if sig.asyncness.is_none() {
+        return Err(Error::new(
+            sig.fn_token.span(),
+            "`#[vdso_async]` can only be used on `async fn`",
+        ));
+    }
+    if !sig.generics.params.is_empty() || sig.generics.where_clause.is_some() {
+        return Err(Error::new(
+            sig.generics.span(),
+            "`#[vdso_async]` functions cannot be generic",
+        ));
+    }
+    let mut names = Vec::new();
+    let mut types = Vec::new();
+    for (index, input) in sig.inputs.iter().enumerate() {
+        let FnArg::Typed(pat_type) = input else {
+            return Err(Error::new(
+                input.span(),
+                "`self` parameters are not supported",
+            ));
+        };
+        if matches!(*pat_type.ty, syn::Type::Reference(_)) {
+            return Err(Error::new(
+                pat_type.ty.span(),
+                "the future outlives the call, so parameters cannot be references",
+            ));
+        }
+        let name = match &*pat_type.pat {
+            Pat::Ident(pat_ident) if pat_ident.subpat.is_none() => pat_ident.ident.clone(),
+            Pat::Wild(_) => format_ident!("arg{}", index),
+            pat => return Err(Error::new(pat.span(), "unsupported parameter pattern")),
+        };
+        names.push(name);
+        types.push((*pat_type.ty).clone());
+    }
+    let output: syn::Type = match &sig.output {
+        ReturnType::Default => parse_quote!(()),
+        ReturnType::Type(_, ty) => (**ty).clone(),
+    };
+
+    let vis = &func.vis;
+    let ident = &sig.ident;
+    let unsafety = &sig.unsafety;
+    let state_size = format_ident!("{}_state_size", ident);
+    let state_align = format_ident!("{}_state_align", ident);
+    let init_state = format_ident!("{}_init_state", ident);
+    let poll = format_ident!("{}_poll", ident);
+    let drop_state = format_ident!("{}_drop_state", ident);
+    // 以所有参数组成的元组为参数、调用原函数的闭包，用于推导Future的类型
+    let call = if unsafety.is_some() {
+        quote! { unsafe { #ident(#(#names),*) } }
+    } else {
+        quote! { #ident(#(#names),*) }
+    };
+    let closure = quote! { |(#(#names,)*): (#(#types,)*)| #call };
+    let size_doc = LitStr::new(
+        &format!(" `{}`返回的Future的大小。", ident),
+        Span::call_site(),
+    );
+    let align_doc = LitStr::new(
+        &format!(" `{}`返回的Future的对齐。", ident),
+        Span::call_site(),
+    );
+    let init_doc = LitStr::new(
+        &format!(" 调用`{}`，将返回的Future写入`state`。", ident),
+        Span::call_site(),
+    );
+    let poll_doc = LitStr::new(
+        &format!(
+            " 推进`state`中由`{}`返回的Future，完成时将结果写入`output`并返回`true`。",
+            ident
+        ),
+        Span::call_site(),
+    );
+    let drop_doc = LitStr::new(
+        &format!(" 析构`state`中由`{}`返回的、尚未完成的Future。", ident),
+        Span::call_site(),
+    );
+
+    Ok(quote! {
+        #func
+
+        #[doc = #size_doc]
+        #[::vdso_helper::vdso_api(#attr)]
+        #vis fn #state_size() -> usize {
+            ::vdso_helper::async_api::state_layout(&#closure).size()
+        }
+
+        #[doc = #align_doc]
+        #[::vdso_helper::vdso_api(#attr)]
+        #vis fn #state_align() -> usize {
+            ::vdso_helper::async_api::state_layout(&#closure).align()
+        }
+
+        #[doc = #init_doc]
+        #[::vdso_helper::vdso_api(#attr)]
+        #vis unsafe fn #init_state(state: *mut u8 #(, #names: #types)*) {
+            unsafe { ::vdso_helper::async_api::init_state(#closure, (#(#names,)*), state) }
+        }
+
+        #[doc = #poll_doc]
+        #[::vdso_helper::vdso_api(#attr)]
+        #vis unsafe fn #poll(
+            state: *mut u8,
+            waker_data: *const (),
+            waker_vtable: *const core::task::RawWakerVTable,
+            output: *mut #output,
+        ) -> bool {
+            unsafe { ::vdso_helper::async_api::poll_state(&#closure, state, waker_data, waker_vtable, output) }
+        }
+
+        #[doc = #drop_doc]
+        #[::vdso_helper::vdso_api(#attr)]
+        #vis unsafe fn #drop_state(state: *mut u8) {
+            unsafe { ::vdso_helper::async_api::drop_state(&#closure, state) }
+        }
+    })
+}
+
+/// 将`impl`块中的`pub`方法导出为vDSO的API。
+///
+/// 只能用于没有泛型参数的固有`impl`块，类型需要与其它API中的自定义数据结构相同，声明为`pub`和`#[repr(C)]`，
+/// 并导出在vDSO库的根模块中。参数与`#[vdso_api]`相同，但不支持`compat`、`symbol`和`instances`，
+/// 作用于块中的所有`pub`方法。
+///
+/// 对于块中的每个`pub`方法，该宏生成一个导出的`extern "C"`函数，符号名为`<类型名>__<方法名>`，
+/// 接收者作为名为`this`的第一个参数传入（`&self`、`&mut self`和`self`分别对应`&T`、`&mut T`和`T`），
+/// 没有接收者的关联函数则原样导出。原方法的可见性改为`pub(crate)`，在vDSO库内部仍可以直接调用。
+///
+/// 生成的API库为该类型实现`<类型名>Methods` trait，其中的方法与原方法同名，通过VTABLE调用vDSO中的实现。
+/// 由于原方法在vDSO库外不可见，引入API库后即可直接以`obj.method()`的形式调用。
+///
+/// 方法不能有泛型类型参数或const泛型参数，也不支持`self: Box<Self>`等形式的接收者。
+///
+/// ```ignore
+/// #[repr(C)]
+/// pub struct Counter { step: usize }
+///
+/// #[vdso_impl(since = "1.1")]
+/// impl Counter {
+///     pub fn new(step: usize) -> Self { ... }
+///     pub fn bump(&self) -> usize { ... }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn vdso_impl(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let attr = proc_macro2::TokenStream::from(attr);
+    let args = match Punctuated::<MetaNameValue, Token![,]>::parse_terminated.parse2(attr.clone()) {
+        Ok(args) => args,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    if let Some(arg) = args.iter().find(|arg| {
+        arg.path.is_ident("compat") || arg.path.is_ident("symbol") || arg.path.is_ident("instances")
+    }) {
+        return Error::new(
+            arg.path.span(),
+            "`#[vdso_impl]` does not support `compat`, `symbol` or `instances`",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let item_impl = parse_macro_input!(item as ItemImpl);
+    match expand_vdso_impl(&attr, item_impl) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_vdso_impl(
+    attr: &proc_macro2::TokenStream,
+    mut item_impl: ItemImpl,
+) -> Result<proc_macro2::TokenStream, Error> {
+    if let Some((_, path, _)) = &item_impl.trait_ {
+        return Err(Error::new(
+            path.span(),
+            "`#[vdso_impl]` can only be used on inherent `impl` blocks",
+        ));
+    }
+    if !item_impl.generics.params.is_empty() || item_impl.generics.where_clause.is_some() {
+        return Err(Error::new(
+            item_impl.generics.span(),
+            "`#[vdso_impl]` cannot be used on generic `impl` blocks",
+        ));
+    }
+    let self_ty = (*item_impl.self_ty).clone();
+    let Some(type_name) = (match &self_ty {
+        syn::Type::Path(syn::TypePath { qself: None, path }) => path.get_ident().cloned(),
+        _ => None,
+    }) else {
+        return Err(Error::new(
+            self_ty.span(),
+            "the type of a `#[vdso_impl]` block must be a plain type name",
+        ));
+    };
+    let self_params = [syn::Ident::new("Self", Span::call_site())];
+    let self_types = [self_ty.clone()];
+
+    let mut shims = proc_macro2::TokenStream::new();
+    for impl_item in item_impl.items.iter_mut() {
+        let ImplItem::Fn(method) = impl_item else {
+            continue;
+        };
+        if !matches!(method.vis, syn::Visibility::Public(_)) {
+            continue;
+        }
+        // 原方法只在vDSO库内部可见，vDSO库外通过API库中的同名方法调用
+        method.vis = parse_quote!(pub(crate));
+        let sig = &method.sig;
+        if let Some(param) = sig
+            .generics
+            .params
+            .iter()
+            .find(|param| !matches!(param, syn::GenericParam::Lifetime(_)))
+        {
+            return Err(Error::new(
+                param.span(),
+                "methods exported by `#[vdso_impl]` cannot have type or const parameters",
+            ));
+        }
+        let ident = &sig.ident;
+        let symbol = format_ident!("{}__{}", type_name, ident);
+        let mut inputs = Vec::new();
+        let mut names = Vec::new();
+        for (index, input) in sig.inputs.iter().enumerate() {
+            match input {
+                FnArg::Receiver(receiver) => {
+                    if receiver.colon_token.is_some() {
+                        return Err(Error::new(
+                            receiver.span(),
+                            "only `self`, `&self` and `&mut self` receivers are supported",
+                        ));
+                    }
+                    let ty = match &receiver.reference {
+                        Some((_, lifetime)) => {
+                            let mutability = &receiver.mutability;
+                            quote! { &#lifetime #mutability #self_ty }
+                        }
+                        None => quote! { #self_ty },
+                    };
+                    inputs.push(quote! { this: #ty });
+                    names.push(format_ident!("this"));
+                }
+                FnArg::Typed(pat_type) => {
+                    let name = match &*pat_type.pat {
+                        Pat::Ident(pat_ident) if pat_ident.subpat.is_none() => {
+                            pat_ident.ident.clone()
+                        }
+                        Pat::Wild(_) => format_ident!("arg{}", index),
+                        pat => return Err(Error::new(pat.span(), "unsupported parameter pattern")),
+                    };
+                    let ty = substitute(&pat_type.ty, &self_params, &self_types);
+                    inputs.push(quote! { #name: #ty });
+                    names.push(name);
+                }
+            }
+        }
+        let output = match &sig.output {
+            ReturnType::Default => quote! {},
+            ReturnType::Type(_, ty) => {
+                let ty = substitute(ty, &self_params, &self_types);
+                quote! { -> #ty }
+            }
+        };
+        let generics = &sig.generics;
+        let unsafety = &sig.unsafety;
+        let call = if unsafety.is_some() {
+            quote! { unsafe { #self_ty::#ident(#(#names),*) } }
+        } else {
+            quote! { #self_ty::#ident(#(#names),*) }
+        };
+        shims.extend(quote! {
+            #[doc(hidden)]
+            #[allow(non_snake_case)]
+            #[::vdso_helper::vdso_api(#attr)]
+            pub #unsafety fn #symbol #generics (#(#inputs),*) #output {
+                #call
+            }
+        });
+    }
+    Ok(quote! {
+        #item_impl
+
+        #shims
+    })
+}
+
+/// 展开`#[vdso_api(instances = "...")]`：保留泛型函数，并为每个实例生成一个导出的函数。
+fn expand_instances(
+    args: &Punctuated<MetaNameValue, Token![,]>,
+    instances: &MetaNameValue,
+    func: &ItemFn,
+) -> Result<proc_macro2::TokenStream, Error> {
+    if let Some(arg) = args
+        .iter()
+        .find(|arg| arg.path.is_ident("compat") || arg.path.is_ident("symbol"))
+    {
+        return Err(Error::new(
+            arg.path.span(),
+            "`instances` cannot be used together with `compat` or `symbol`",
+        ));
+    }
+    if args
+        .iter()
+        .filter(|arg| arg.path.is_ident("instances"))
+        .count()
+        > 1
+    {
+        return Err(Error::new(instances.path.span(), "duplicate argument"));
+    }
+    let Expr::Lit(ExprLit {
+        lit: Lit::Str(list),
+        ..
+    }) = &instances.value
+    else {
+        return Err(Error::new(
+            instances.value.span(),
+            "expected a string literal",
+        ));
+    };
+
+    let sig = &func.sig;
+    let mut params = Vec::new();
+    for param in &sig.generics.params {
+        match param {
+            syn::GenericParam::Type(ty) => params.push(ty.ident.clone()),
+            param => {
+                return Err(Error::new(
+                    param.span(),
+                    "generic APIs can only have type parameters",
+                ))
+            }
+        }
+    }
+    if params.is_empty() {
+        return Err(Error::new(
+            instances.path.span(),
+            "`instances` can only be used on generic functions",
+        ));
+    }
+    let instance_types =
+        parse_instances(&list.value(), params.len()).map_err(|e| Error::new(list.span(), e))?;
+
+    // 实例导出时使用的参数：去掉`instances`，其余参数原样传给每个实例
+    let forwarded: Punctuated<&MetaNameValue, Token![,]> = args
+        .iter()
+        .filter(|arg| !arg.path.is_ident("instances"))
+        .collect();
+    let mut names = Vec::new();
+    for (index, input) in sig.inputs.iter().enumerate() {
+        let FnArg::Typed(pat_type) = input else {
+            return Err(Error::new(
+                input.span(),
+                "`self` parameters are not supported",
+            ));
+        };
+        names.push(match &*pat_type.pat {
+            Pat::Ident(pat_ident) if pat_ident.subpat.is_none() => pat_ident.ident.clone(),
+            Pat::Wild(_) => format_ident!("arg{}", index),
+            pat => return Err(Error::new(pat.span(), "unsupported parameter pattern")),
+        });
+    }
+
+    let vis = &func.vis;
+    let ident = &sig.ident;
+    let unsafety = &sig.unsafety;
+    let mut expanded = quote! { #func };
+    for types in instance_types {
+        let symbol = format_ident!("{}", instance_symbol(&ident.to_string(), &types));
+        let mut inputs = Vec::new();
+        for (name, input) in names.iter().zip(sig.inputs.iter()) {
+            let FnArg::Typed(pat_type) = input else {
+                unreachable!()
+            };
+            let ty = substitute(&pat_type.ty, &params, &types);
+            inputs.push(quote! { #name: #ty });
+        }
+        let output = match &sig.output {
+            ReturnType::Default => quote! {},
+            ReturnType::Type(_, ty) => {
+                let ty = substitute(ty, &params, &types);
+                quote! { -> #ty }
+            }
+        };
+        let call = if unsafety.is_some() {
+            quote! { unsafe { #ident::<#(#types),*>(#(#names),*) } }
+        } else {
+            quote! { #ident::<#(#types),*>(#(#names),*) }
+        };
+        expanded.extend(quote! {
+            #[doc(hidden)]
+            #[allow(non_snake_case)]
+            #[::vdso_helper::vdso_api(#forwarded)]
+            #vis #unsafety fn #symbol(#(#inputs),*) #output {
+                #call
+            }
+        });
+    }
+    Ok(expanded)
+}
+
+/// 解析实例列表，返回每个实例的类型实参。`count`为类型参数的个数。
+fn parse_instances(list: &str, count: usize) -> Result<Vec<Vec<syn::Type>>, String> {
+    let types = Punctuated::<syn::Type, Token![,]>::parse_terminated
+        .parse_str(list)
+        .map_err(|e| format!("invalid instance list: {}", e))?;
+    if types.is_empty() {
+        return Err("the instance list is empty".into());
+    }
+    types
+        .into_iter()
+        .map(|ty| match ty {
+            _ if count == 1 => Ok(vec![ty]),
+            syn::Type::Tuple(tuple) if tuple.elems.len() == count => {
+                Ok(tuple.elems.into_iter().collect())
+            }
+            ty => Err(format!(
+                "expected a tuple of {} types for each instance, found `{}`",
+                count,
+                quote!(#ty)
+            )),
+        })
+        .collect()
+}
+
+/// 实例导出的符号名：`<函数名>__<类型实参>`，规则需与`build_vdso`中的相同。
+fn instance_symbol(name: &str, types: &[syn::Type]) -> String {
+    let mut symbol = name.to_string();
+    for ty in types {
+        let mut mangled = String::new();
+        for c in quote!(#ty).to_string().chars() {
+            if c.is_ascii_alphanumeric() {
+                mangled.push(c);
+            } else if !mangled.is_empty() && !mangled.ends_with('_') {
+                mangled.push('_');
+            }
+        }
+        symbol.push_str("__");
+        symbol.push_str(mangled.trim_end_matches('_'));
+    }
+    symbol
+}
+
+/// 将`ty`中的类型参数`params`替换为对应的类型实参`types`。
+fn substitute(ty: &syn::Type, params: &[syn::Ident], types: &[syn::Type]) -> syn::Type {
+    struct Substitute<'a>(&'a [syn::Ident], &'a [syn::Type]);
+    impl syn::visit_mut::VisitMut for Substitute<'_> {
+        fn visit_type_mut(&mut self, ty: &mut syn::Type) {
+            if let syn::Type::Path(syn::TypePath { qself: None, path }) = ty {
+                if let Some(index) = self.0.iter().position(|param| path.is_ident(param)) {
+                    *ty = self.1[index].clone();
+                    return;
+                }
+            }
+            syn::visit_mut::visit_type_mut(self, ty);
+        }
+    }
+    let mut ty = ty.clone();
+    syn::visit_mut::VisitMut::visit_type_mut(&mut Substitute(params, types), &mut ty);
+    ty
+}
+
+/// `#[vdso_api]`的版本参数。
+struct VersionArgs {
+    /// 导出的符号名
+    symbol: String,
+    /// 所属的ABI版本
+    version: String,
+    /// 是否为该符号的默认版本
+    is_default: bool,
+}
+
+/// 检查`module`参数：只能出现一次，且需为合法的标识符。
+fn check_module_arg(args: &Punctuated<MetaNameValue, Token![,]>) -> Result<(), Error> {
+    let mut modules = args.iter().filter(|arg| arg.path.is_ident("module"));
+    let Some(module) = modules.next() else {
+        return Ok(());
+    };
+    if let Some(duplicate) = modules.next() {
+        return Err(Error::new(duplicate.path.span(), "duplicate argument"));
+    }
+    match &module.value {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(s), ..
+        }) if syn::parse_str::<syn::Ident>(&s.value()).is_ok() => Ok(()),
+        value => Err(Error::new(
+            value.span(),
+            "expected a module name, which must be a valid identifier",
+        )),
+    }
+}
+
+fn parse_version_args(
+    args: &Punctuated<MetaNameValue, Token![,]>,
+    func: &ItemFn,
+) -> Result<Option<VersionArgs>, Error> {
+    let mut since = None;
+    let mut compat = None;
+    let mut symbol = None;
+    for arg in args.iter().filter(|arg| !arg.path.is_ident("module")) {
+        let value = match &arg.value {
+            Expr::Lit(ExprLit {
+                lit: Lit::Str(s), ..
+            }) => s,
+            _ => return Err(Error::new(arg.value.span(), "expected a string literal")),
+        };
+        let slot = if arg.path.is_ident("since") {
+            &mut since
+        } else if arg.path.is_ident("compat") {
+            &mut compat
+        } else if arg.path.is_ident("symbol") {
+            &mut symbol
+        } else {
+            return Err(Error::new(
+                arg.path.span(),
+                "unknown argument, expected `since`, `compat`, `symbol`, `module` or `instances`",
+            ));
+        };
+        if slot.replace(value.clone()).is_some() {
+            return Err(Error::new(arg.path.span(), "duplicate argument"));
+        }
+    }
+    for version in [&since, &compat].into_iter().flatten() {
+        let valid = version
+            .value()
+            .split_once('.')
+            .is_some_and(|(major, minor)| {
+                major.parse::<u32>().is_ok() && minor.parse::<u32>().is_ok()
+            });
+        if !valid {
+            return Err(Error::new(
+                version.span(),
+                "invalid ABI version, expected `<major>.<minor>`",
+            ));
+        }
+    }
+    match (since, compat, symbol) {
+        (None, None, None) => Ok(None),
+        (Some(since), None, None) => Ok(Some(VersionArgs {
+            symbol: func.sig.ident.to_string(),
+            version: since.value(),
+            is_default: true,
+        })),
+        (None, Some(compat), Some(symbol)) => Ok(Some(VersionArgs {
+            symbol: symbol.value(),
+            version: compat.value(),
+            is_default: false,
+        })),
+        (Some(since), Some(_), _) => Err(Error::new(
+            since.span(),
+            "`since` and `compat` cannot be used together",
+        )),
+        (_, Some(compat), None) => Err(Error::new(
+            compat.span(),
+            "`compat` requires `symbol` to name the exported symbol",
+        )),
+        (_, None, Some(symbol)) => Err(Error::new(
+            symbol.span(),
+            "`symbol` can only be used together with `compat`",
+        )),
+    }
+}